@@ -0,0 +1,36 @@
+// Pairs bucketed by distance, so that the safe ones can be cleared in bulk and only the loose
+// ones reviewed by hand. The bands are relative to the similarity threshold, so that they keep
+// their meaning with every hash algorithm.
+
+// Within this distance, pairs are the same picture, give or take a re-encode.
+const EXACT_MAX_DISTANCE: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Band {
+    Exact,
+    // Closer than half the threshold.
+    Near,
+    Loose,
+}
+
+impl Band {
+    pub const ALL: [Band; 3] = [Band::Exact, Band::Near, Band::Loose];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Band::Exact => "Exact",
+            Band::Near => "Near",
+            Band::Loose => "Loose",
+        }
+    }
+
+    pub fn of(distance: u32, threshold: u32) -> Band {
+        if distance <= EXACT_MAX_DISTANCE {
+            Band::Exact
+        } else if distance < threshold / 2 {
+            Band::Near
+        } else {
+            Band::Loose
+        }
+    }
+}
@@ -0,0 +1,37 @@
+// Shots taken within a few seconds of each other by the same camera: bursts, and the RAW and JPEG
+// a camera writes for the same shot. Perceptual hashes miss those where the subject moved or the
+// JPEG was processed differently, the capture time does not.
+use crate::exif;
+use rayon::prelude::*;
+
+// Groups of at least two of the `images` (index and path), each shot within `window` seconds of
+// the first one of its group, so that a long sequence is split into several groups rather than
+// pairing shots minutes apart. Images without a capture time are left out, those without a camera
+// are only grouped together.
+pub fn groups(images: &[(usize, String)], window: i64) -> Vec<Vec<usize>> {
+    let mut shots: Vec<(Option<String>, i64, usize)> = images
+        .par_iter()
+        .filter_map(|(idx, path)| {
+            let summary = exif::summary_of(path);
+            Some((summary.camera, summary.capture_time?, *idx))
+        })
+        .collect();
+    shots.sort();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut first: Option<(&Option<String>, i64)> = None;
+    for (camera, time, idx) in &shots {
+        match first {
+            Some((first_camera, first_time))
+                if first_camera == camera && time - first_time <= window =>
+            {
+                groups.last_mut().unwrap().push(*idx);
+            }
+            _ => {
+                groups.push(vec![*idx]);
+                first = Some((camera, *time));
+            }
+        }
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
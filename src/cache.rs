@@ -0,0 +1,359 @@
+use crate::paths;
+use crate::settings::HashAlgorithm;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::hash::BuildHasher;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+const CENTRAL_CACHE_FILE_NAME: &str = "hashes.json";
+const STATS_FILE_NAME: &str = "hash_stats.json";
+pub const SIDECAR_FILE_NAME: &str = ".img-dedup-hashes";
+// Bumped when decoding changes the hashes of some images, e.g. 16-bit and TIFF images, so that
+// their entries are hashed again. Entries written before it have 0.
+pub const DECODER_VERSION: u32 = 1;
+// Saved during long scans too, so that quitting or crashing midway keeps most of the work.
+const SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashCacheMode {
+    Disabled,
+    // One file in the cache directory, keyed by absolute path.
+    Central,
+    // One file per scanned directory, keyed by file name, so that it moves along with the images.
+    Sidecar,
+}
+
+impl HashCacheMode {
+    pub const ALL: [HashCacheMode; 3] = [
+        HashCacheMode::Disabled,
+        HashCacheMode::Central,
+        HashCacheMode::Sidecar,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashCacheMode::Disabled => "disabled",
+            HashCacheMode::Central => "central",
+            HashCacheMode::Sidecar => "sidecar",
+        }
+    }
+}
+
+impl std::str::FromStr for HashCacheMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HashCacheMode::ALL
+            .into_iter()
+            .find(|mode| mode.name() == s)
+            .ok_or(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub size: u64,
+    // Milliseconds since the Unix epoch.
+    pub mtime: u64,
+    pub hash_alg: HashAlgorithm,
+    // Base64, as produced by `ImageHash::to_base64`.
+    pub hash: String,
+    pub dimensions: (u32, u32),
+    // Animation frames or icon sizes, 0 when unknown.
+    #[serde(default)]
+    pub frames: u32,
+    // Color management changes the hash of images with an ICC profile.
+    #[serde(default)]
+    pub color_management: bool,
+    #[serde(default)]
+    pub decoder: u32,
+}
+
+pub fn mtime_millis(metadata: &std::fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |mtime| mtime.as_millis() as u64)
+}
+
+type Entries = HashMap<String, CacheEntry>;
+
+fn read_entries(path: &Path) -> Entries {
+    match std::fs::read(path) {
+        Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|err| {
+            error!("Ignoring invalid hash cache {}: {}", path.display(), err);
+            Entries::new()
+        }),
+        Err(_) => Entries::new(),
+    }
+}
+
+fn write_entries(path: &Path, entries: &Entries) -> Result<(), String> {
+    let content = serde_json::to_vec(entries).map_err(|err| err.to_string())?;
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, content).map_err(|err| err.to_string())?;
+    std::fs::rename(&tmp_path, path).map_err(|err| err.to_string())
+}
+
+pub fn central_cache_path() -> Option<PathBuf> {
+    paths::cache_dir().map(|dir| dir.join(CENTRAL_CACHE_FILE_NAME))
+}
+
+// Lifetime lookup counters, accumulated over all scans.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+    fn path() -> Option<PathBuf> {
+        paths::cache_dir().map(|dir| dir.join(STATS_FILE_NAME))
+    }
+
+    pub fn load() -> Self {
+        Self::path()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn add(session: CacheStats) {
+        let Some(path) = Self::path() else {
+            return;
+        };
+        let mut stats = Self::load();
+        stats.hits += session.hits;
+        stats.misses += session.misses;
+        if let Some(dir) = path.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        if let Err(err) = serde_json::to_vec(&stats)
+            .map_err(|err| err.to_string())
+            .and_then(|content| std::fs::write(&path, content).map_err(|err| err.to_string()))
+        {
+            error!("Failed to save the cache statistics: {}", err);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MaintenanceReport {
+    pub entries_before: usize,
+    // Files that no longer exist.
+    pub pruned: usize,
+    pub verified: usize,
+    // Sampled entries whose file changed since it was hashed.
+    pub stale: usize,
+    pub entries_after: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+    pub stats: CacheStats,
+}
+
+impl std::fmt::Display for MaintenanceReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lookups = self.stats.hits + self.stats.misses;
+        writeln!(
+            f,
+            "Entries: {} -> {} ({} pruned, {} stale out of {} verified)",
+            self.entries_before, self.entries_after, self.pruned, self.stale, self.verified
+        )?;
+        writeln!(
+            f,
+            "Size: {} -> {}",
+            ubyte::ByteUnit::Byte(self.bytes_before),
+            ubyte::ByteUnit::Byte(self.bytes_after)
+        )?;
+        write!(
+            f,
+            "Hits: {}/{} ({:.1}%)",
+            self.stats.hits,
+            lookups,
+            if lookups == 0 {
+                0.0
+            } else {
+                self.stats.hits as f64 * 100.0 / lookups as f64
+            }
+        )
+    }
+}
+
+// Prunes entries of deleted files, checks a random sample of the rest against the files on
+// disk, and rewrites the central cache without the dropped entries.
+pub fn maintain(sample_size: usize) -> Result<MaintenanceReport, String> {
+    let path = central_cache_path().ok_or("No cache directory on this platform")?;
+    let file_size = |path: &Path| std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+    let mut cache = HashCache::load(HashCacheMode::Central);
+    let mut report = MaintenanceReport {
+        entries_before: cache.central.len(),
+        bytes_before: file_size(&path),
+        ..Default::default()
+    };
+
+    cache.central.retain(|path, _| Path::new(path).exists());
+    report.pruned = report.entries_before - cache.central.len();
+
+    // Ordering by a randomly seeded hash of the path gives a cheap random sample.
+    let random_state = RandomState::new();
+    let mut sample: Vec<String> = cache.central.keys().cloned().collect();
+    sample.sort_by_cached_key(|path| random_state.hash_one(path));
+    sample.truncate(sample_size);
+    report.verified = sample.len();
+    for key in sample {
+        let fresh = match std::fs::metadata(&key) {
+            Ok(metadata) => {
+                let entry = &cache.central[&key];
+                entry.size == metadata.len() && entry.mtime == mtime_millis(&metadata)
+            }
+            Err(_) => false,
+        };
+        if !fresh {
+            cache.central.remove(&key);
+            report.stale += 1;
+        }
+    }
+
+    report.entries_after = cache.central.len();
+    cache.dirty = true;
+    cache.save();
+    report.bytes_after = file_size(&path);
+    report.stats = CacheStats::load();
+    info!("Hash cache maintenance: {}", report);
+    Ok(report)
+}
+
+pub struct HashCache {
+    mode: HashCacheMode,
+    central: Entries,
+    // Loaded lazily, one per directory.
+    sidecars: HashMap<PathBuf, Entries>,
+    dirty_dirs: HashSet<PathBuf>,
+    dirty: bool,
+    last_saved: Instant,
+    stats: CacheStats,
+}
+
+impl HashCache {
+    pub fn load(mode: HashCacheMode) -> Self {
+        let central = match (mode, central_cache_path()) {
+            (HashCacheMode::Central, Some(path)) => read_entries(&path),
+            _ => Entries::new(),
+        };
+        HashCache {
+            mode,
+            central,
+            sidecars: HashMap::new(),
+            dirty_dirs: HashSet::new(),
+            dirty: false,
+            last_saved: Instant::now(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    // Sidecar entries are keyed by file name, central ones by canonical path, the scanned directory
+    // may be relative or reached through a link.
+    fn entries_for(&mut self, path: &Path) -> Option<(&mut Entries, String)> {
+        match self.mode {
+            HashCacheMode::Disabled => None,
+            HashCacheMode::Central => {
+                let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+                Some((&mut self.central, path.to_string_lossy().to_string()))
+            }
+            HashCacheMode::Sidecar => {
+                let dir = path.parent()?.to_path_buf();
+                let name = path.file_name()?.to_string_lossy().to_string();
+                let entries = self
+                    .sidecars
+                    .entry(dir)
+                    .or_insert_with_key(|dir| read_entries(&dir.join(SIDECAR_FILE_NAME)));
+                Some((entries, name))
+            }
+        }
+    }
+
+    // Only returns an entry still matching the file on disk and the requested algorithm.
+    pub fn get(
+        &mut self,
+        path: &Path,
+        metadata: &std::fs::Metadata,
+        hash_alg: HashAlgorithm,
+        color_management: bool,
+    ) -> Option<CacheEntry> {
+        let (entries, key) = self.entries_for(path)?;
+        let entry = entries
+            .get(&key)
+            .filter(|entry| {
+                entry.size == metadata.len()
+                    && entry.mtime == mtime_millis(metadata)
+                    && entry.hash_alg == hash_alg
+                    && entry.color_management == color_management
+                    && entry.decoder == DECODER_VERSION
+            })
+            .cloned();
+        match entry {
+            Some(_) => self.stats.hits += 1,
+            None => self.stats.misses += 1,
+        }
+        entry
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &CacheEntry)> {
+        self.central.iter()
+    }
+
+    pub fn insert(&mut self, path: &Path, entry: CacheEntry) {
+        let Some((entries, key)) = self.entries_for(path) else {
+            return;
+        };
+        entries.insert(key, entry);
+        self.dirty = true;
+        if self.mode == HashCacheMode::Sidecar {
+            if let Some(dir) = path.parent() {
+                self.dirty_dirs.insert(dir.to_path_buf());
+            }
+        }
+        if self.last_saved.elapsed() >= SAVE_INTERVAL {
+            self.save();
+        }
+    }
+
+    pub fn save(&mut self) {
+        self.last_saved = Instant::now();
+        if self.stats.hits + self.stats.misses > 0 {
+            CacheStats::add(std::mem::take(&mut self.stats));
+        }
+        if !self.dirty {
+            return;
+        }
+        match self.mode {
+            HashCacheMode::Disabled => {}
+            HashCacheMode::Central => {
+                let Some(path) = central_cache_path() else {
+                    return;
+                };
+                if let Some(dir) = path.parent() {
+                    let _ = std::fs::create_dir_all(dir);
+                }
+                match write_entries(&path, &self.central) {
+                    Ok(()) => info!("Saved {} cached hashes", self.central.len()),
+                    Err(err) => error!("Failed to save the hash cache {}: {}", path.display(), err),
+                }
+            }
+            HashCacheMode::Sidecar => {
+                for dir in self.dirty_dirs.drain() {
+                    let path = dir.join(SIDECAR_FILE_NAME);
+                    if let Err(err) = write_entries(&path, &self.sidecars[&dir]) {
+                        error!("Failed to write {}: {}", path.display(), err);
+                    }
+                }
+            }
+        }
+        self.dirty = false;
+    }
+}
@@ -0,0 +1,92 @@
+use crate::settings::HashAlgorithm;
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(name = "img-dedup", version, about = "Find and remove similar images")]
+pub struct Cli {
+    /// Directory to scan right away when the window opens, or remote storage such as
+    /// `s3://bucket/prefix` or `ssh://user@host/path`
+    pub path: Option<PathBuf>,
+
+    /// Maximum hash distance for two images to be considered similar (0-100)
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(0..=100))]
+    pub threshold: Option<u32>,
+
+    /// Keep settings and all other state next to the executable instead of the platform
+    /// directories (also enabled by a `portable.flag` file beside the executable)
+    #[arg(long)]
+    pub portable: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Print a shell completion script to stdout
+    Completions { shell: Shell },
+    /// Print the man page (roff) to stdout
+    Man,
+    /// Manage the hash index (the central hash cache)
+    Index {
+        #[command(subcommand)]
+        action: IndexAction,
+    },
+    /// Print the hash of each file, pHash hashes in the hexadecimal form used by libpHash tools
+    Hash {
+        #[arg(long, default_value = "phash", value_parser = parse_hash_alg)]
+        alg: HashAlgorithm,
+        /// Convert images with an embedded ICC profile to sRGB before hashing, like scans do
+        #[arg(long)]
+        color_management: bool,
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Print what changed between two saved sessions of the same tree: files added and removed,
+    /// duplicates introduced and resolved
+    Diff { old: PathBuf, new: PathBuf },
+    /// Maintain the hash cache: drop entries of deleted or changed files and report statistics
+    Cache {
+        /// Number of random entries checked against the files on disk
+        #[arg(long, default_value_t = 1000)]
+        sample: usize,
+    },
+    /// Serve a hash cache shared by several machines over HTTP, e.g. from a NAS. Point the
+    /// clients at it with the shared cache URL setting. There is no authentication, only run it on
+    /// a trusted network
+    CacheServer {
+        /// Where the entries are stored, one file each
+        dir: PathBuf,
+        #[arg(long, default_value = "0.0.0.0:8371")]
+        listen: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum IndexAction {
+    /// Hash all images in a directory into the index, without opening a window
+    Build { path: PathBuf },
+    /// Write the whole index to a portable file
+    Export { file: PathBuf },
+    /// Merge an exported index file into the local index
+    Import { file: PathBuf },
+}
+
+fn parse_hash_alg(s: &str) -> Result<HashAlgorithm, String> {
+    s.parse().map_err(|_| {
+        let names: Vec<_> = HashAlgorithm::ALL.iter().map(|alg| alg.name()).collect();
+        format!("expected one of: {}", names.join(", "))
+    })
+}
+
+pub fn print_completions(shell: Shell) {
+    let mut cmd = Cli::command();
+    let name = cmd.get_name().to_owned();
+    clap_complete::generate(shell, &mut cmd, name, &mut std::io::stdout());
+}
+
+pub fn print_man() -> std::io::Result<()> {
+    clap_mangen::Man::new(Cli::command()).render(&mut std::io::stdout())
+}
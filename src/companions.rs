@@ -0,0 +1,151 @@
+// Files that belong with an image and follow it to the trash or to its new name, so that they are
+// not left orphaned:
+// - the video of an iPhone Live Photo, `IMG_0001.MOV` next to `IMG_0001.HEIC` or `IMG_0001.JPG`,
+// - sidecars: XMP edits (`IMG_0001.xmp` or `IMG_0001.CR2.xmp`), Apple edits (`IMG_0001.AAE`) and
+//   Google Takeout metadata (`IMG_0001.JPG.json`, `IMG_0001.JPG.supplemental-metadata.json`).
+use std::path::{Path, PathBuf};
+
+const LIVE_PHOTO_STILL_EXTENSIONS: [&str; 4] = ["heic", "heif", "jpg", "jpeg"];
+const LIVE_PHOTO_VIDEO_EXTENSIONS: [&str; 1] = ["mov"];
+const SIDECAR_EXTENSIONS: [&str; 3] = ["xmp", "aae", "json"];
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extensions.iter().any(|x| x.eq_ignore_ascii_case(extension)))
+}
+
+fn is_companion_type(path: &Path) -> bool {
+    has_extension(path, &LIVE_PHOTO_VIDEO_EXTENSIONS) || has_extension(path, &SIDECAR_EXTENSIONS)
+}
+
+fn lowercase_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_ascii_lowercase()
+}
+
+fn lowercase_stem(path: &Path) -> String {
+    path.file_stem()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_ascii_lowercase()
+}
+
+// The companions of `path` that exist, matched case-insensitively since iPhones name files in
+// upper case and some tools rename them in lower case. Companions named after the stem only, like
+// `IMG_0001.xmp`, are left out when another image has the same stem, e.g. with RAW+JPEG, since
+// they are more likely to belong to that one.
+pub fn find(path: &Path) -> Vec<PathBuf> {
+    let Some(dir) = path.parent() else {
+        return Vec::new();
+    };
+    // `a.jpg` has an empty parent.
+    let dir = if dir.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        dir
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let (name, stem) = (lowercase_name(path), lowercase_stem(path));
+    let siblings: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|sibling| {
+            let sibling_name = lowercase_name(sibling);
+            sibling_name != name && sibling_name.starts_with(&stem)
+        })
+        .collect();
+    let stem_shared = siblings
+        .iter()
+        .any(|sibling| lowercase_stem(sibling) == stem && !is_companion_type(sibling));
+
+    siblings
+        .into_iter()
+        .filter(|sibling| {
+            let sibling_stem = lowercase_stem(sibling);
+            if has_extension(sibling, &LIVE_PHOTO_VIDEO_EXTENSIONS) {
+                has_extension(path, &LIVE_PHOTO_STILL_EXTENSIONS)
+                    && sibling_stem == stem
+                    && !stem_shared
+            } else if has_extension(sibling, &SIDECAR_EXTENSIONS) {
+                sibling_stem == name
+                    || sibling_stem.starts_with(&format!("{}.", name))
+                    || (sibling_stem == stem && !stem_shared)
+            } else {
+                false
+            }
+        })
+        .collect()
+}
+
+// The companions of `path` which do not also belong to `kept`, e.g. the JPEG export of a HEIC
+// Live Photo shares its video with the original.
+pub fn find_unshared(path: &Path, kept: Option<&Path>) -> Vec<PathBuf> {
+    let shared = kept.map(find).unwrap_or_default();
+    find(path)
+        .into_iter()
+        .filter(|companion| !shared.contains(companion))
+        .collect()
+}
+
+// Where `companion` of `from` goes when `from` is renamed to `to`: `a.CR2.xmp` of `a.CR2` becomes
+// `b.CR2.xmp` for `b.CR2`, and `a.xmp` becomes `b.xmp`.
+pub fn renamed(companion: &Path, from: &Path, to: &Path) -> PathBuf {
+    let companion_name = companion
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let from_name = from.file_name().unwrap_or_default().to_string_lossy();
+    let to_name = to.file_name().unwrap_or_default().to_string_lossy();
+    let from_stem = from.file_stem().unwrap_or_default().to_string_lossy();
+    let to_stem = to.file_stem().unwrap_or_default().to_string_lossy();
+    // ASCII lowercasing, like in `find`, keeps the byte offsets.
+    let lowercase = companion_name.to_ascii_lowercase();
+    let name = if lowercase.starts_with(&from_name.to_ascii_lowercase()) {
+        format!("{}{}", to_name, &companion_name[from_name.len()..])
+    } else if lowercase.starts_with(&from_stem.to_ascii_lowercase()) {
+        format!("{}{}", to_stem, &companion_name[from_stem.len()..])
+    } else {
+        companion_name
+    };
+    to.with_file_name(name)
+}
+
+// Which of the RAW and JPEG a camera wrote for the same shot to keep. They match each other but
+// are not duplicates to be deleted blindly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RawJpegPolicy {
+    RawOnly,
+    JpegOnly,
+    Both,
+}
+
+impl RawJpegPolicy {
+    pub const ALL: [RawJpegPolicy; 3] = [
+        RawJpegPolicy::RawOnly,
+        RawJpegPolicy::JpegOnly,
+        RawJpegPolicy::Both,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            RawJpegPolicy::RawOnly => "Keep RAW only",
+            RawJpegPolicy::JpegOnly => "Keep JPEG only",
+            RawJpegPolicy::Both => "Keep both",
+        }
+    }
+}
+
+// Siblings with the same name, e.g. `DSC_0001.NEF` and `DSC_0001.JPG`.
+pub fn is_raw_jpeg_pair(raw: &str, jpeg: &str) -> bool {
+    let (raw, jpeg) = (Path::new(raw), Path::new(jpeg));
+    raw.parent() == jpeg.parent()
+        && lowercase_stem(raw) == lowercase_stem(jpeg)
+        && crate::formats::is_raw(raw)
+        && has_extension(jpeg, &["jpg", "jpeg"])
+}
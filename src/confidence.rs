@@ -0,0 +1,103 @@
+// How likely a pair is the same picture, from 0 to 100. The hash distance alone mixes up bursts
+// and similar shots with real copies, so it is weighted with the other hints at hand: copies tend
+// to have close file sizes and dimensions, the same capture time and similar names.
+use std::path::Path;
+
+// Shots taken within this many seconds count as the same capture time.
+const SAME_CAPTURE_SECONDS: i64 = 2;
+// Beyond this, the capture times tell the images apart.
+const MAX_CAPTURE_SECONDS: i64 = 600;
+
+// Weights of the signals, the hash distance matters most.
+const DISTANCE_WEIGHT: f32 = 4.0;
+const SIZE_WEIGHT: f32 = 1.0;
+const DIMENSIONS_WEIGHT: f32 = 1.0;
+const CAPTURE_TIME_WEIGHT: f32 = 2.0;
+const NAME_WEIGHT: f32 = 1.0;
+
+// Per image, read once.
+#[derive(Debug, Clone, Copy)]
+pub struct Facts {
+    pub size: u64,
+    pub capture_time: Option<i64>,
+    // Latitude and longitude, shown on a map rather than scored.
+    pub location: Option<(f64, f64)>,
+}
+
+pub fn facts(path: &str) -> Facts {
+    let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+    let header = crate::exif::read_header(path).ok();
+    Facts {
+        size,
+        capture_time: header.as_deref().and_then(crate::exif::capture_time),
+        location: header.as_deref().and_then(crate::exif::location),
+    }
+}
+
+fn ratio(a: u64, b: u64) -> f32 {
+    if a.max(b) == 0 {
+        1.0
+    } else {
+        a.min(b) as f32 / a.max(b) as f32
+    }
+}
+
+fn bigrams(name: &str) -> Vec<(char, char)> {
+    let chars: Vec<char> = name.to_lowercase().chars().collect();
+    chars.windows(2).map(|pair| (pair[0], pair[1])).collect()
+}
+
+// Dice coefficient of the character pairs of the file stems, e.g. `IMG_1234` and `IMG_1234 (1)`
+// are close.
+fn name_similarity(a: &str, b: &str) -> f32 {
+    let stem = |path: &str| {
+        Path::new(path)
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string()
+    };
+    let (a, mut b) = (bigrams(&stem(a)), bigrams(&stem(b)));
+    if a.is_empty() || b.is_empty() {
+        return if a == b { 1.0 } else { 0.0 };
+    }
+    let total = a.len() + b.len();
+    let mut common = 0;
+    for bigram in a {
+        if let Some(pos) = b.iter().position(|other| *other == bigram) {
+            b.swap_remove(pos);
+            common += 1;
+        }
+    }
+    2.0 * common as f32 / total as f32
+}
+
+// Images are (path, dimensions, facts). A capture time missing on either side is left out rather
+// than counted against the pair.
+pub fn score(
+    distance: u32,
+    threshold: u32,
+    a: (&str, (u32, u32), Facts),
+    b: (&str, (u32, u32), Facts),
+) -> u8 {
+    let area = |(width, height): (u32, u32)| width as u64 * height as u64;
+    let mut signals = vec![
+        (
+            DISTANCE_WEIGHT,
+            1.0 - (distance as f32 / threshold.max(1) as f32).min(1.0),
+        ),
+        (SIZE_WEIGHT, ratio(a.2.size, b.2.size)),
+        (DIMENSIONS_WEIGHT, ratio(area(a.1), area(b.1))),
+        (NAME_WEIGHT, name_similarity(a.0, b.0)),
+    ];
+    if let (Some(time_a), Some(time_b)) = (a.2.capture_time, b.2.capture_time) {
+        let apart = (time_a - time_b).abs().saturating_sub(SAME_CAPTURE_SECONDS);
+        signals.push((
+            CAPTURE_TIME_WEIGHT,
+            1.0 - (apart as f32 / MAX_CAPTURE_SECONDS as f32).min(1.0),
+        ));
+    }
+    let total: f32 = signals.iter().map(|(weight, _)| weight).sum();
+    let weighted: f32 = signals.iter().map(|(weight, value)| weight * value).sum();
+    (100.0 * weighted / total).round() as u8
+}
@@ -0,0 +1,45 @@
+// Dumps of phone and camera memory cards keep the DCIM layout of the card, e.g.
+// `DCIM/100APPLE/IMG_1234.HEIC`. The copies there are the originals, and iPhones save edits next
+// to them as `IMG_E1234`, which look like the original but are not a duplicate of it.
+use std::path::Path;
+
+// DCF folders are named with a number from 100 to 999 and five more letters, digits or
+// underscores, e.g. `100APPLE`, `101CANON` or `100_FUJI`.
+fn is_dcf_folder(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() == 8
+        && (b'1'..=b'9').contains(&bytes[0])
+        && bytes[1..3].iter().all(u8::is_ascii_digit)
+        && bytes[3..]
+            .iter()
+            .all(|&b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_')
+}
+
+pub fn is_in_dcim(path: &str) -> bool {
+    Path::new(path).components().any(|component| {
+        let name = component.as_os_str().to_string_lossy();
+        name.eq_ignore_ascii_case("DCIM") || is_dcf_folder(&name)
+    })
+}
+
+// `IMG_E1234.JPG` next to `IMG_1234.HEIC`, the extensions may differ.
+pub fn is_edited_variant(edited: &str, original: &str) -> bool {
+    let (edited, original) = (Path::new(edited), Path::new(original));
+    let stem = |path: &Path| {
+        path.file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_ascii_uppercase()
+    };
+    let (edited_stem, original_stem) = (stem(edited), stem(original));
+    let (Some(edited_number), Some(original_number)) = (
+        edited_stem.strip_prefix("IMG_E"),
+        original_stem.strip_prefix("IMG_"),
+    ) else {
+        return false;
+    };
+    edited.parent() == original.parent()
+        && !edited_number.is_empty()
+        && edited_number.bytes().all(|b| b.is_ascii_digit())
+        && edited_number == original_number
+}
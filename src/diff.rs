@@ -0,0 +1,41 @@
+// Absolute difference of the two images of a pair, to see at a glance what sets them apart, e.g.
+// a watermark, a crop or color grading. The bigger one is resized to the size of the smaller one,
+// so that a downscaled copy mostly comes out black.
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, RgbaImage};
+
+// Differences are multiplied by this, small ones would be too dark to notice otherwise.
+const GAIN: u32 = 4;
+
+// The difference image and the mean difference, from 0 for identical pixels to 1.
+pub fn difference(a: &DynamicImage, b: &DynamicImage) -> (RgbaImage, f32) {
+    let (width, height) =
+        if a.width() as u64 * a.height() as u64 <= b.width() as u64 * b.height() as u64 {
+            a.dimensions()
+        } else {
+            b.dimensions()
+        };
+    let resized = |image: &DynamicImage| {
+        if image.dimensions() == (width, height) {
+            image.to_rgb8()
+        } else {
+            image
+                .resize_exact(width, height, FilterType::Triangle)
+                .to_rgb8()
+        }
+    };
+    let (a, b) = (resized(a), resized(b));
+    let mut total = 0u64;
+    let diff = RgbaImage::from_fn(width, height, |x, y| {
+        let (a, b) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let mut pixel = [0, 0, 0, 255];
+        for channel in 0..3 {
+            let delta = a[channel].abs_diff(b[channel]);
+            total += delta as u64;
+            pixel[channel] = (delta as u32 * GAIN).min(255) as u8;
+        }
+        image::Rgba(pixel)
+    });
+    let samples = (width as u64 * height as u64 * 3).max(1);
+    (diff, total as f32 / samples as f32 / 255.0)
+}
@@ -0,0 +1,67 @@
+// Byte-identical files, found before any image is decoded. They are the most common duplicates,
+// and are reported as such whatever the similarity threshold, even when they fail to decode. Only
+// files sharing their size with another one are read, and compared by SHA-256.
+use crate::ScanControl;
+use log::debug;
+use rayon::prelude::*;
+use ring::digest::{Context, SHA256};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+fn sha256(path: &Path) -> std::io::Result<Vec<u8>> {
+    let mut context = Context::new(&SHA256);
+    let mut file = std::fs::File::open(path)?;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let len = file.read(&mut buffer)?;
+        if len == 0 {
+            break;
+        }
+        context.update(&buffer[..len]);
+    }
+    Ok(context.finish().as_ref().to_vec())
+}
+
+// The size and sorted paths of the groups of files with the same content, largest first, from the
+// paths and sizes of the scanned files. Runs on the current rayon pool, files left unread when the
+// scan is cancelled are left out.
+pub fn groups(files: &[(&Path, u64)], control: &ScanControl) -> Vec<(u64, Vec<String>)> {
+    let mut by_size: HashMap<u64, Vec<&Path>> = HashMap::new();
+    for &(path, size) in files {
+        by_size.entry(size).or_default().push(path);
+    }
+    let candidates: Vec<(u64, &Path)> = by_size
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .flat_map(|(size, paths)| paths.into_iter().map(move |path| (size, path)))
+        .collect();
+    let digests: Vec<((u64, Vec<u8>), PathBuf)> = candidates
+        .par_iter()
+        .filter(|_| control.proceed())
+        .filter_map(|&(size, path)| match sha256(path) {
+            Ok(digest) => Some(((size, digest), path.to_path_buf())),
+            Err(err) => {
+                debug!("Failed to read {}: {}", path.display(), err);
+                None
+            }
+        })
+        .collect();
+    let mut by_digest: HashMap<(u64, Vec<u8>), Vec<String>> = HashMap::new();
+    for (key, path) in digests {
+        by_digest
+            .entry(key)
+            .or_default()
+            .push(path.to_string_lossy().to_string());
+    }
+    let mut groups: Vec<(u64, Vec<String>)> = by_digest
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|((size, _), mut paths)| {
+            paths.sort();
+            (size, paths)
+        })
+        .collect();
+    groups.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    groups
+}
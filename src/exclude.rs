@@ -0,0 +1,85 @@
+// Files and directories left out of scans, e.g. cache folders and generated thumbnails. Patterns
+// are globs matched against the path relative to the scanned directory, with `/` separators: `*`
+// and `?` stay within a component, `**` spans any number of them. Like in `.gitignore`, a glob
+// without `/` matches a name at any depth, e.g. `*.thumb.jpg`, and excluding a directory excludes
+// everything in it. Patterns starting with `re:` are regular expressions instead.
+use log::error;
+use regex::{Regex, RegexSet};
+use std::path::Path;
+
+pub struct Patterns(RegexSet);
+
+fn glob_to_regex(glob: &str) -> String {
+    let anchored = glob.contains('/');
+    let glob = glob.trim_start_matches('/');
+    let mut regex = String::from(if anchored { "^" } else { "(?:^|/)" });
+    let mut rest = glob;
+    while let Some(c) = rest.chars().next() {
+        let (piece, len) = if rest.starts_with("**/") {
+            ("(?:.*/)?".to_owned(), 3)
+        } else if rest.starts_with("/**") && rest.len() == 3 {
+            ("(?:/.*)?".to_owned(), 3)
+        } else if rest.starts_with("**") {
+            (".*".to_owned(), 2)
+        } else if c == '*' {
+            ("[^/]*".to_owned(), 1)
+        } else if c == '?' {
+            ("[^/]".to_owned(), 1)
+        } else {
+            (regex::escape(&c.to_string()), c.len_utf8())
+        };
+        regex += &piece;
+        rest = &rest[len..];
+    }
+    // Whatever is inside a matching directory.
+    regex + "(?:/|$)"
+}
+
+fn to_regex(pattern: &str) -> String {
+    match pattern.strip_prefix("re:") {
+        Some(regex) => regex.to_owned(),
+        None => glob_to_regex(pattern),
+    }
+}
+
+// The error of an invalid pattern, for the settings.
+pub fn check(pattern: &str) -> Option<String> {
+    Regex::new(&to_regex(pattern))
+        .err()
+        .map(|err| err.to_string())
+}
+
+impl Patterns {
+    // Invalid patterns are left out. Valid ones can still be too big together, then nothing is
+    // excluded.
+    pub fn new(patterns: &[String]) -> Self {
+        let regexes = patterns
+            .iter()
+            .map(|pattern| pattern.trim())
+            .filter(|pattern| !pattern.is_empty())
+            .filter(|pattern| match check(pattern) {
+                Some(err) => {
+                    error!("Ignoring the exclude pattern {}: {}", pattern, err);
+                    false
+                }
+                None => true,
+            })
+            .map(to_regex);
+        Patterns(RegexSet::new(regexes).unwrap_or_else(|err| {
+            error!("Ignoring the exclude patterns: {}", err);
+            RegexSet::empty()
+        }))
+    }
+
+    pub fn excludes(&self, root: &Path, path: &Path) -> bool {
+        if self.0.is_empty() {
+            return false;
+        }
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        let relative: Vec<_> = relative
+            .iter()
+            .map(|component| component.to_string_lossy())
+            .collect();
+        !relative.is_empty() && self.0.is_match(&relative.join("/"))
+    }
+}
@@ -0,0 +1,303 @@
+// Just enough of EXIF, the TIFF structure cameras store in the APP1 segment of JPEGs, for the
+// embedded preview, the capture time, the orientation and what the pair view shows of each image.
+use std::io::{Read, Seek, SeekFrom, Write};
+
+// The EXIF segment is at most 64 KiB, at the start of the file.
+const HEADER_LEN: u64 = 64 * 1024;
+
+// What often decides which copy to keep, shown next to each image of a pair.
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    // Make and model.
+    pub camera: Option<String>,
+    pub capture_time: Option<i64>,
+    pub iso: Option<u16>,
+    pub location: Option<(f64, f64)>,
+}
+
+// By quarter turns clockwise to display the image upright, the orientations without mirroring.
+pub const ORIENTATIONS: [u16; 4] = [1, 6, 3, 8];
+
+fn read_u16(data: &[u8], offset: usize, little_endian: bool) -> Option<u16> {
+    let bytes: [u8; 2] = data.get(offset..offset + 2)?.try_into().ok()?;
+    Some(if little_endian {
+        u16::from_le_bytes(bytes)
+    } else {
+        u16::from_be_bytes(bytes)
+    })
+}
+
+fn read_u32(data: &[u8], offset: usize, little_endian: bool) -> Option<u32> {
+    let bytes: [u8; 4] = data.get(offset..offset + 4)?.try_into().ok()?;
+    Some(if little_endian {
+        u32::from_le_bytes(bytes)
+    } else {
+        u32::from_be_bytes(bytes)
+    })
+}
+
+// The TIFF structure of the EXIF segment, its offset in the file and its byte order.
+fn tiff(jpeg: &[u8]) -> Option<(&[u8], usize, bool)> {
+    // Walk the marker segments up to the image data, looking for APP1.
+    let mut pos = 2;
+    let tiff = loop {
+        if jpeg.get(pos)? != &0xFF {
+            return None;
+        }
+        let marker = *jpeg.get(pos + 1)?;
+        let len = read_u16(jpeg, pos + 2, false)? as usize;
+        let segment = jpeg.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xE1 && segment.starts_with(b"Exif\0\0") {
+            break (&segment[6..], pos + 10);
+        }
+        // Start of scan: no EXIF before the image data.
+        if marker == 0xDA {
+            return None;
+        }
+        pos += 2 + len;
+    };
+
+    let (tiff, offset) = tiff;
+    let little_endian = match tiff.get(0..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    Some((tiff, offset, little_endian))
+}
+
+// Like `tiff`, but also for TIFF based raw files, e.g. DNG, NEF or CR2, where the whole file is
+// the TIFF structure. Only for reading, raw files are never written to.
+fn metadata(data: &[u8]) -> Option<(&[u8], usize, bool)> {
+    match data.get(0..4)? {
+        b"II*\0" => Some((data, 0, true)),
+        b"MM\0*" => Some((data, 0, false)),
+        _ => tiff(data),
+    }
+}
+
+// Where the value field of the `tag` entry of the IFD at `ifd` is. Values of up to 4 bytes are
+// stored there, larger ones elsewhere with their offset there.
+fn ifd_field(tiff: &[u8], ifd: usize, tag: u16, little_endian: bool) -> Option<usize> {
+    for i in 0..read_u16(tiff, ifd, little_endian)? as usize {
+        let entry = ifd + 2 + i * 12;
+        if read_u16(tiff, entry, little_endian)? == tag {
+            return Some(entry + 8);
+        }
+    }
+    None
+}
+
+// The value, or offset to it, of the `tag` entry of the IFD at `ifd`.
+fn ifd_entry(tiff: &[u8], ifd: usize, tag: u16, little_endian: bool) -> Option<usize> {
+    Some(read_u32(
+        tiff,
+        ifd_field(tiff, ifd, tag, little_endian)?,
+        little_endian,
+    )? as usize)
+}
+
+// An ASCII value, stored in the entry when it fits in 4 bytes and elsewhere otherwise.
+fn ifd_ascii(tiff: &[u8], ifd: usize, tag: u16, little_endian: bool) -> Option<String> {
+    let field = ifd_field(tiff, ifd, tag, little_endian)?;
+    let count = read_u32(tiff, field - 4, little_endian)? as usize;
+    let offset = if count <= 4 {
+        field
+    } else {
+        read_u32(tiff, field, little_endian)? as usize
+    };
+    let text = String::from_utf8_lossy(tiff.get(offset..offset + count)?);
+    let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    (!text.is_empty()).then(|| text.to_owned())
+}
+
+// The JPEG thumbnail cameras store in the second IFD of the EXIF segment, if any.
+pub fn preview(jpeg: &[u8]) -> Option<&[u8]> {
+    let (tiff, _, little_endian) = tiff(jpeg)?;
+    let ifd0 = read_u32(tiff, 4, little_endian)? as usize;
+    let ifd0_entries = read_u16(tiff, ifd0, little_endian)? as usize;
+    let ifd1 = read_u32(tiff, ifd0 + 2 + ifd0_entries * 12, little_endian)? as usize;
+    if ifd1 == 0 {
+        return None;
+    }
+
+    // JPEGInterchangeFormat and JPEGInterchangeFormatLength
+    let offset = ifd_entry(tiff, ifd1, 0x0201, little_endian)?;
+    let len = ifd_entry(tiff, ifd1, 0x0202, little_endian)?;
+    tiff.get(offset..offset + len)
+}
+
+// Days since 1970-01-01 of a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+// DateTimeOriginal, in seconds since the Unix epoch. The time zone is not recorded, which does not
+// matter to compare shots of the same camera.
+pub fn capture_time(jpeg: &[u8]) -> Option<i64> {
+    let (tiff, _, little_endian) = metadata(jpeg)?;
+    let ifd0 = read_u32(tiff, 4, little_endian)? as usize;
+    // ExifIFD, then DateTimeOriginal: "YYYY:MM:DD HH:MM:SS".
+    let exif_ifd = ifd_entry(tiff, ifd0, 0x8769, little_endian)?;
+    let offset = ifd_entry(tiff, exif_ifd, 0x9003, little_endian)?;
+    let text = std::str::from_utf8(tiff.get(offset..offset + 19)?).ok()?;
+    let field = |range: std::ops::Range<usize>| text.get(range)?.parse::<i64>().ok();
+    let days = days_from_civil(field(0..4)?, field(5..7)?, field(8..10)?);
+    Some(days * 86400 + field(11..13)? * 3600 + field(14..16)? * 60 + field(17..19)?)
+}
+
+// Year, month and day of a number of days since 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// A `capture_time` as the camera wrote it, without time zone.
+pub fn format_time(seconds: i64) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86400));
+    let time = seconds.rem_euclid(86400);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+// Camera, from Make and Model, and ISOSpeedRatings, along with the capture time and location.
+pub fn summary(jpeg: &[u8]) -> Summary {
+    let mut summary = Summary {
+        capture_time: capture_time(jpeg),
+        location: location(jpeg),
+        ..Summary::default()
+    };
+    let Some((tiff, _, little_endian)) = metadata(jpeg) else {
+        return summary;
+    };
+    let Some(ifd0) = read_u32(tiff, 4, little_endian).map(|ifd0| ifd0 as usize) else {
+        return summary;
+    };
+    let make = ifd_ascii(tiff, ifd0, 0x010F, little_endian);
+    let model = ifd_ascii(tiff, ifd0, 0x0110, little_endian);
+    summary.camera = match (make, model) {
+        // Models often repeat the make, e.g. "Canon" and "Canon EOS 5D".
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    };
+    summary.iso = ifd_entry(tiff, ifd0, 0x8769, little_endian).and_then(|exif_ifd| {
+        let field = ifd_field(tiff, exif_ifd, 0x8827, little_endian)?;
+        read_u16(tiff, field, little_endian)
+    });
+    summary
+}
+
+// `Summary::default()` when the file cannot be read or has no EXIF.
+pub fn summary_of(path: &str) -> Summary {
+    read_header(path).map_or_else(|_| Summary::default(), |header| summary(&header))
+}
+
+// The Orientation of IFD0, where its value is in the file and the byte order. It is a SHORT, stored
+// in the first bytes of the value field of its entry.
+fn orientation_entry(jpeg: &[u8]) -> Option<(u16, usize, bool)> {
+    let (tiff, offset, little_endian) = tiff(jpeg)?;
+    let ifd0 = read_u32(tiff, 4, little_endian)? as usize;
+    let field = ifd_field(tiff, ifd0, 0x0112, little_endian)?;
+    Some((
+        read_u16(tiff, field, little_endian)?,
+        offset + field,
+        little_endian,
+    ))
+}
+
+pub(crate) fn read_header(path: &str) -> std::io::Result<Vec<u8>> {
+    let mut header = Vec::new();
+    std::fs::File::open(path)?
+        .take(HEADER_LEN)
+        .read_to_end(&mut header)?;
+    Ok(header)
+}
+
+// `None` when the file has no orientation tag, e.g. it is not a JPEG.
+pub fn orientation(path: &str) -> Option<u16> {
+    orientation_entry(&read_header(path).ok()?).map(|(value, _, _)| value)
+}
+
+// Changes the tag in place, the file keeps its size and its pixels. Files without the tag are left
+// alone, adding it would mean rewriting the whole EXIF segment.
+pub fn write_orientation(path: &str, orientation: u16) -> Result<(), String> {
+    let header = read_header(path).map_err(|err| err.to_string())?;
+    let (_, offset, little_endian) =
+        orientation_entry(&header).ok_or("No EXIF orientation tag to change")?;
+    let bytes = if little_endian {
+        orientation.to_le_bytes()
+    } else {
+        orientation.to_be_bytes()
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .map_err(|err| err.to_string())?;
+    file.seek(SeekFrom::Start(offset as u64))
+        .and_then(|_| file.write_all(&bytes))
+        .map_err(|err| err.to_string())
+}
+
+// Latitude and longitude in degrees, negative to the south and west.
+pub fn location(jpeg: &[u8]) -> Option<(f64, f64)> {
+    let (tiff, _, little_endian) = metadata(jpeg)?;
+    let ifd0 = read_u32(tiff, 4, little_endian)? as usize;
+    let gps_ifd = ifd_entry(tiff, ifd0, 0x8825, little_endian)?;
+    // Degrees, minutes and seconds, as three RATIONALs.
+    let degrees = |tag: u16| -> Option<f64> {
+        let offset = ifd_entry(tiff, gps_ifd, tag, little_endian)?;
+        let mut value = 0.0;
+        for (i, unit) in [1.0, 60.0, 3600.0].into_iter().enumerate() {
+            let numerator = read_u32(tiff, offset + i * 8, little_endian)?;
+            let denominator = read_u32(tiff, offset + i * 8 + 4, little_endian)?;
+            if denominator != 0 {
+                value += numerator as f64 / denominator as f64 / unit;
+            }
+        }
+        Some(value)
+    };
+    // The reference is a single letter, stored in the entry.
+    let reference = |tag: u16| {
+        tiff.get(ifd_field(tiff, gps_ifd, tag, little_endian)?)
+            .copied()
+    };
+    let latitude = match reference(1)? {
+        b'S' => -degrees(2)?,
+        _ => degrees(2)?,
+    };
+    let longitude = match reference(3)? {
+        b'W' => -degrees(4)?,
+        _ => degrees(4)?,
+    };
+    // Cameras without a fix write zeroes.
+    if latitude == 0.0 && longitude == 0.0 {
+        return None;
+    }
+    Some((latitude, longitude))
+}
@@ -0,0 +1,410 @@
+use crate::Image;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    DupeGuruXml,
+    DupeGuruCsv,
+    RmlintJson,
+    RmlintSh,
+    // One record per similar pair, for other tools rather than other deduplicators.
+    PairsJson,
+    PairsCsv,
+}
+
+impl ExportFormat {
+    // Picked from the extension, dupeGuru's XML being the fallback.
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("csv") => ExportFormat::DupeGuruCsv,
+            Some("json") => ExportFormat::RmlintJson,
+            Some("sh") => ExportFormat::RmlintSh,
+            _ => ExportFormat::DupeGuruXml,
+        }
+    }
+
+    // The pairs formats, JSON being the fallback.
+    pub fn pairs_from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(ext) if ext.eq_ignore_ascii_case("csv") => ExportFormat::PairsCsv,
+            _ => ExportFormat::PairsJson,
+        }
+    }
+}
+
+pub fn write(
+    format: ExportFormat,
+    out: &mut impl Write,
+    images: &[Option<Image>],
+    pairs: &[(usize, usize)],
+) -> std::io::Result<()> {
+    match format {
+        ExportFormat::DupeGuruXml => write_dupeguru_xml(out, images, pairs),
+        ExportFormat::DupeGuruCsv => write_dupeguru_csv(out, images, pairs),
+        ExportFormat::RmlintJson => write_rmlint_json(out, images, pairs),
+        ExportFormat::RmlintSh => write_rmlint_sh(out, images, pairs),
+        ExportFormat::PairsJson => write_pairs_json(out, images, pairs),
+        ExportFormat::PairsCsv => write_pairs_csv(out, images, pairs),
+    }
+}
+
+// Transitively similar images end up in the same group, e.g. (a, b) and (b, c) give [a, b, c].
+pub fn connected_groups(pairs: &[(usize, usize)], len: usize) -> Vec<Vec<usize>> {
+    fn find(parents: &mut [usize], mut i: usize) -> usize {
+        while parents[i] != i {
+            parents[i] = parents[parents[i]];
+            i = parents[i];
+        }
+        i
+    }
+
+    let mut parents: Vec<usize> = (0..len).collect();
+    for &(a, b) in pairs {
+        let (root_a, root_b) = (find(&mut parents, a), find(&mut parents, b));
+        if root_a != root_b {
+            parents[root_a.max(root_b)] = root_a.min(root_b);
+        }
+    }
+
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); len];
+    let mut in_pair = vec![false; len];
+    for &(a, b) in pairs {
+        in_pair[a] = true;
+        in_pair[b] = true;
+    }
+    for (i, _) in in_pair.iter().enumerate().filter(|(_, &in_pair)| in_pair) {
+        let root = find(&mut parents, i);
+        members[root].push(i);
+    }
+    members.retain(|group| !group.is_empty());
+    members
+}
+
+// Similarity in percent, 100 meaning identical hashes.
+pub fn similarity(a: &Image, b: &Image) -> u32 {
+    let bits = (a.hash.as_bytes().len() * 8).max(1) as u32;
+    100 - a.hash.dist(&b.hash).min(bits) * 100 / bits
+}
+
+fn file_size(path: &str) -> u64 {
+    std::fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+// The image kept in a group: the most pixels, then the biggest file, then the first found.
+pub fn choose_original(group: &[usize], images: &[Option<Image>]) -> usize {
+    *group
+        .iter()
+        .max_by_key(|&&i| {
+            let image = images[i].as_ref().unwrap();
+            (
+                image.dimensions.0 as u64 * image.dimensions.1 as u64,
+                file_size(&image.path),
+                std::cmp::Reverse(i),
+            )
+        })
+        .unwrap()
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_csv(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+// dupeGuru's `.dupeguru` results file, which it can open with "Load Results…".
+pub fn write_dupeguru_xml(
+    out: &mut impl Write,
+    images: &[Option<Image>],
+    pairs: &[(usize, usize)],
+) -> std::io::Result<()> {
+    writeln!(out, "<?xml version='1.0' encoding='utf-8'?>")?;
+    writeln!(out, "<results>")?;
+    for group in connected_groups(pairs, images.len()) {
+        writeln!(out, "  <group>")?;
+        for &i in &group {
+            let image = images[i].as_ref().unwrap();
+            writeln!(
+                out,
+                "    <file path=\"{}\" words=\"\" is_ref=\"n\" marked=\"n\" />",
+                escape_xml(&image.path)
+            )?;
+        }
+        for &(a, b) in pairs {
+            let (Some(first), Some(second)) = (
+                group.iter().position(|&i| i == a),
+                group.iter().position(|&i| i == b),
+            ) else {
+                continue;
+            };
+            writeln!(
+                out,
+                "    <match first=\"{}\" second=\"{}\" percentage=\"{}\" />",
+                first,
+                second,
+                similarity(images[a].as_ref().unwrap(), images[b].as_ref().unwrap())
+            )?;
+        }
+        writeln!(out, "  </group>")?;
+    }
+    writeln!(out, "</results>")
+}
+
+// Same columns as dupeGuru's picture mode CSV export. The match percentage of a file is its best
+// match within the group.
+pub fn write_dupeguru_csv(
+    out: &mut impl Write,
+    images: &[Option<Image>],
+    pairs: &[(usize, usize)],
+) -> std::io::Result<()> {
+    writeln!(out, "Group ID,Filename,Folder,Size (KB),Dimensions,Match %")?;
+    for (group_id, group) in connected_groups(pairs, images.len()).iter().enumerate() {
+        for &i in group {
+            let image = images[i].as_ref().unwrap();
+            let path = Path::new(&image.path);
+            let size_kb = file_size(&image.path) / 1024;
+            let best_match = pairs
+                .iter()
+                .filter_map(|&(a, b)| match (a == i, b == i) {
+                    (true, _) => Some(b),
+                    (_, true) => Some(a),
+                    _ => None,
+                })
+                .map(|other| similarity(image, images[other].as_ref().unwrap()))
+                .max()
+                .unwrap_or(100);
+            writeln!(
+                out,
+                "{},{},{},{},{} x {},{}",
+                group_id,
+                escape_csv(&path.file_name().unwrap_or_default().to_string_lossy()),
+                escape_csv(&path.parent().unwrap_or(Path::new("")).to_string_lossy()),
+                size_kb,
+                image.dimensions.0,
+                image.dimensions.1,
+                best_match
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn file_json(image: &Image) -> serde_json::Value {
+    serde_json::json!({
+        "path": image.path,
+        "size": file_size(&image.path),
+        "width": image.dimensions.0,
+        "height": image.dimensions.1,
+    })
+}
+
+// An array of `{"distance", "similarity", "files": [{"path", "size", "width", "height"}, ..]}`,
+// the distance being the number of differing hash bits and the similarity in percent.
+pub fn write_pairs_json(
+    out: &mut impl Write,
+    images: &[Option<Image>],
+    pairs: &[(usize, usize)],
+) -> std::io::Result<()> {
+    let records: Vec<serde_json::Value> = pairs
+        .iter()
+        .map(|&(a, b)| {
+            let (a, b) = (images[a].as_ref().unwrap(), images[b].as_ref().unwrap());
+            serde_json::json!({
+                "distance": a.hash.dist(&b.hash),
+                "similarity": similarity(a, b),
+                "files": [file_json(a), file_json(b)],
+            })
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut *out, &records).map_err(std::io::Error::other)?;
+    writeln!(out)
+}
+
+// The same as `write_pairs_json`, one line per pair.
+pub fn write_pairs_csv(
+    out: &mut impl Write,
+    images: &[Option<Image>],
+    pairs: &[(usize, usize)],
+) -> std::io::Result<()> {
+    writeln!(
+        out,
+        "Distance,Similarity %,Path A,Size A,Width A,Height A,Path B,Size B,Width B,Height B"
+    )?;
+    for &(a, b) in pairs {
+        let (a, b) = (images[a].as_ref().unwrap(), images[b].as_ref().unwrap());
+        write!(out, "{},{}", a.hash.dist(&b.hash), similarity(a, b))?;
+        for image in [a, b] {
+            write!(
+                out,
+                ",{},{},{},{}",
+                escape_csv(&image.path),
+                file_size(&image.path),
+                image.dimensions.0,
+                image.dimensions.1
+            )?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(unix)]
+fn inode_and_disk(metadata: &std::fs::Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+    (metadata.ino(), metadata.dev())
+}
+
+#[cfg(not(unix))]
+fn inode_and_disk(_metadata: &std::fs::Metadata) -> (u64, u64) {
+    (0, 0)
+}
+
+// rmlint's `--output json` layout: a header, one object per file with the original of each set
+// first, and a footer. It can be applied with `rmlint --replay` on a machine without a GUI.
+pub fn write_rmlint_json(
+    out: &mut impl Write,
+    images: &[Option<Image>],
+    pairs: &[(usize, usize)],
+) -> std::io::Result<()> {
+    let mut objects = vec![serde_json::json!({
+        "description": "rmlint json-dump of lint files",
+        "cwd": std::env::current_dir().unwrap_or_default().to_string_lossy(),
+        "args": "img-dedup",
+        "version": env!("CARGO_PKG_VERSION"),
+        "rev": "img-dedup",
+        "progress": 0,
+        "checksum_type": "img-dedup",
+    })];
+    let groups = connected_groups(pairs, images.len());
+    let (mut duplicates, mut lint_size) = (0, 0);
+    for group in &groups {
+        let original = choose_original(group, images);
+        // All files of a set share the original's checksum, since that's how rmlint groups them.
+        let checksum = hex(images[original].as_ref().unwrap().hash.as_bytes());
+        let ordered =
+            std::iter::once(original).chain(group.iter().copied().filter(|&i| i != original));
+        for i in ordered {
+            let image = images[i].as_ref().unwrap();
+            let metadata = std::fs::metadata(&image.path).ok();
+            let size = metadata.as_ref().map_or(0, |metadata| metadata.len());
+            let (inode, disk_id) = metadata.as_ref().map_or((0, 0), inode_and_disk);
+            let mtime = metadata.as_ref().map_or(0, crate::cache::mtime_millis) as f64 / 1000.0;
+            if i != original {
+                duplicates += 1;
+                lint_size += size;
+            }
+            objects.push(serde_json::json!({
+                "id": i,
+                "type": "duplicate_file",
+                "progress": 100,
+                "checksum": checksum,
+                "path": image.path,
+                "size": size,
+                "depth": Path::new(&image.path).components().count().saturating_sub(1),
+                "inode": inode,
+                "disk_id": disk_id,
+                "is_original": i == original,
+                "mtime": mtime,
+            }));
+        }
+    }
+    objects.push(serde_json::json!({
+        "aborted": false,
+        "progress": 100,
+        "total_files": images.iter().flatten().count(),
+        "ignored_files": 0,
+        "ignored_folders": 0,
+        "duplicates": duplicates,
+        "duplicate_sets": groups.len(),
+        "total_lint_size": lint_size,
+    }));
+    serde_json::to_writer_pretty(&mut *out, &objects).map_err(std::io::Error::other)?;
+    writeln!(out)
+}
+
+fn quote_sh(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+// Mirrors the structure of rmlint's `rmlint.sh`: the same handler functions and flags (`-d` to not
+// ask for confirmation, `-n` for a dry run), with one call per file.
+pub fn write_rmlint_sh(
+    out: &mut impl Write,
+    images: &[Option<Image>],
+    pairs: &[(usize, usize)],
+) -> std::io::Result<()> {
+    write!(
+        out,
+        r#"#!/bin/sh
+# Generated by img-dedup {version}, in the format of rmlint's sh output.
+# Usage: {{script}} [-d] [-n]
+#   -d  do not ask before running
+#   -n  dry run, only print what would be done
+
+DO_ASK=true
+DO_DRY_RUN=
+while getopts "dn" OPTION; do
+    case $OPTION in
+        d) DO_ASK= ;;
+        n) DO_DRY_RUN=true ;;
+        *) exit 1 ;;
+    esac
+done
+
+if [ -n "$DO_ASK" ] && [ -z "$DO_DRY_RUN" ]; then
+    printf 'Remove the duplicates listed in this script? [y/N] '
+    read -r answer
+    [ "$answer" = "y" ] || exit 0
+fi
+
+original_cmd() {{
+    echo "[keeping] $1"
+}}
+
+remove_cmd() {{
+    echo "[removing] $1 (original: $2)"
+    if [ -z "$DO_DRY_RUN" ]; then
+        if [ ! -e "$2" ]; then
+            echo "[skipped] original $2 is gone" >&2
+            return
+        fi
+        rm -f "$1"
+    fi
+}}
+
+"#,
+        version = env!("CARGO_PKG_VERSION")
+    )?;
+    for group in connected_groups(pairs, images.len()) {
+        let original = choose_original(&group, images);
+        let original_path = quote_sh(&images[original].as_ref().unwrap().path);
+        writeln!(out, "original_cmd {}", original_path)?;
+        for &i in group.iter().filter(|&&i| i != original) {
+            writeln!(
+                out,
+                "remove_cmd {} {}",
+                quote_sh(&images[i].as_ref().unwrap().path),
+                original_path
+            )?;
+        }
+        writeln!(out)?;
+    }
+    writeln!(out, "echo 'Done!'")
+}
@@ -0,0 +1,1006 @@
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::vp8::Vp8Decoder;
+use image::{
+    AnimationDecoder, DynamicImage, GenericImageView, GrayImage, ImageBuffer, ImageError,
+    ImageResult, RgbaImage,
+};
+use log::debug;
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Extensions decoded here rather than by the `image` crate.
+// Camera RAW files, hashed from the largest JPEG preview they embed.
+pub const RAW_EXTENSIONS: [&str; 10] = [
+    "cr2", "cr3", "nef", "arw", "dng", "orf", "rw2", "raf", "pef", "srw",
+];
+
+pub const EXTRA_EXTENSIONS: [&str; 11] = [
+    "pdf", "svg", "psd", "psb", "xcf", "icns", "hdr", "exr", "heic", "heif", "jxl",
+];
+
+// Videos, hashed from the average of a few keyframes extracted by ffmpeg.
+pub const VIDEO_EXTENSIONS: [&str; 7] = ["mp4", "m4v", "mov", "mkv", "webm", "avi", "wmv"];
+
+// Animations are hashed from the average of their frames, up to this many, so that neither a blank
+// first frame nor the choice of one frame decides what they match.
+const MAX_ANIMATION_FRAMES: usize = 100;
+
+// Keyframes averaged for videos, spread over their duration. Re-encoded, resized or recompressed
+// copies give about the same ones, trimmed copies do not.
+const VIDEO_KEYFRAMES: u32 = 5;
+
+// Longest side SVGs are rasterized at, plenty for hashing and previews.
+const SVG_SIZE: &str = "512";
+
+fn unsupported(path: &Path, reason: &str) -> ImageError {
+    ImageError::IoError(std::io::Error::other(format!(
+        "{}: {}",
+        path.display(),
+        reason
+    )))
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+// Decodes any supported file, `buffer` being its content. Animations give their first frame,
+// videos their middle keyframe.
+pub fn load(path: &Path, buffer: &[u8]) -> ImageResult<DynamicImage> {
+    match extension(path).as_str() {
+        // The `image` crate only decodes still WebP images.
+        "webp" => image::load_from_memory(buffer).or_else(|err| {
+            webp_frames(buffer)
+                .and_then(|frames| frames.into_iter().next())
+                .ok_or(err)
+        }),
+        "pdf" => load_pdf(path, buffer),
+        "svg" => load_svg(path, buffer),
+        "psd" | "psb" => load_psd(buffer).ok_or_else(|| {
+            unsupported(
+                path,
+                "only 8 and 16 bit RGB and grayscale PSDs are supported",
+            )
+        }),
+        "xcf" => load_xcf(path, buffer),
+        "ico" | "icns" => load_icon(path, buffer).map(|(image, _)| image),
+        "hdr" => load_hdr(buffer),
+        "exr" => load_exr(path, buffer),
+        "heic" | "heif" => load_heic(path, buffer),
+        "jxl" => load_jxl(path, buffer),
+        extension if VIDEO_EXTENSIONS.contains(&extension) => {
+            load_video(path, buffer, 1).map(|(image, _)| image)
+        }
+        extension if RAW_EXTENSIONS.contains(&extension) => load_raw(path, buffer),
+        _ if image::guess_format(buffer).ok() == Some(image::ImageFormat::Jpeg) => {
+            load_jpeg(path, buffer)
+        }
+        _ => image::load_from_memory(buffer),
+    }
+}
+
+// Runs a converter and returns what it wrote to stdout, `None` when it is not installed or fails.
+fn run_converter(program: &str, args: &[&std::ffi::OsStr]) -> Option<Vec<u8>> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        debug!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        return None;
+    }
+    Some(output.stdout)
+}
+
+// Runs a converter writing an image to stdout.
+fn convert_with(program: &str, args: &[&std::ffi::OsStr]) -> Option<DynamicImage> {
+    image::load_from_memory(&run_converter(program, args)?).ok()
+}
+
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|pos| pos + from)
+}
+
+// The JPEG streams (`/DCTDecode` filter) of a PDF, which is how scanners store pages.
+fn pdf_jpeg_streams(pdf: &[u8]) -> Vec<&[u8]> {
+    let mut streams = Vec::new();
+    let mut pos = 0;
+    while let Some(filter) = find(pdf, b"/DCTDecode", pos) {
+        let Some(start) = find(pdf, b"stream", filter) else {
+            break;
+        };
+        let mut start = start + b"stream".len();
+        if pdf.get(start) == Some(&b'\r') {
+            start += 1;
+        }
+        if pdf.get(start) == Some(&b'\n') {
+            start += 1;
+        }
+        let Some(end) = find(pdf, b"endstream", start) else {
+            break;
+        };
+        let stream = &pdf[start..end];
+        // Also skips JPEG streams with another filter on top, e.g. `[/FlateDecode /DCTDecode]`.
+        if stream.starts_with(&[0xFF, 0xD8]) {
+            streams.push(stream);
+        }
+        pos = end;
+    }
+    streams
+}
+
+// The biggest embedded JPEG, which for a scanned document is the page itself. Other PDFs are
+// rendered by poppler's `pdftoppm` or mupdf's `mutool` when installed.
+fn load_pdf(path: &Path, buffer: &[u8]) -> ImageResult<DynamicImage> {
+    if let Some(image) = pdf_jpeg_streams(buffer)
+        .into_iter()
+        .filter_map(|stream| image::load_from_memory(stream).ok())
+        .max_by_key(|image| image.width() as u64 * image.height() as u64)
+    {
+        return Ok(image);
+    }
+
+    with_local_file(path, buffer, |file| {
+        let file = file.as_os_str();
+        convert_with(
+            "pdftoppm",
+            &[
+                "-png".as_ref(),
+                "-singlefile".as_ref(),
+                "-r".as_ref(),
+                "100".as_ref(),
+                file,
+            ],
+        )
+        .or_else(|| {
+            convert_with(
+                "mutool",
+                &[
+                    "draw".as_ref(),
+                    "-q".as_ref(),
+                    "-F".as_ref(),
+                    "png".as_ref(),
+                    "-r".as_ref(),
+                    "100".as_ref(),
+                    "-o".as_ref(),
+                    "-".as_ref(),
+                    file,
+                    "1".as_ref(),
+                ],
+            )
+        })
+    })?
+    .ok_or_else(|| {
+        unsupported(
+            path,
+            "no embedded JPEG, and neither pdftoppm nor mutool could render it",
+        )
+    })
+}
+
+// Rasterized at a fixed size by librsvg's `rsvg-convert`, or the `resvg` or Inkscape command line
+// tools, whichever is installed. Matching against exported copies works at any size since hashes
+// are computed on a downscaled image anyway.
+fn load_svg(path: &Path, buffer: &[u8]) -> ImageResult<DynamicImage> {
+    with_local_file(path, buffer, |file| {
+        let file = file.as_os_str();
+        convert_with(
+            "rsvg-convert",
+            &[
+                "--keep-aspect-ratio".as_ref(),
+                "--width".as_ref(),
+                SVG_SIZE.as_ref(),
+                "--height".as_ref(),
+                SVG_SIZE.as_ref(),
+                file,
+            ],
+        )
+        .or_else(|| {
+            convert_with(
+                "resvg",
+                &["--width".as_ref(), SVG_SIZE.as_ref(), "-c".as_ref(), file],
+            )
+        })
+        .or_else(|| {
+            convert_with(
+                "inkscape",
+                &[
+                    "--export-type=png".as_ref(),
+                    "--export-filename=-".as_ref(),
+                    "--export-width".as_ref(),
+                    SVG_SIZE.as_ref(),
+                    file,
+                ],
+            )
+        })
+    })?
+    .ok_or_else(|| {
+        unsupported(
+            path,
+            "rasterizing SVGs requires rsvg-convert, resvg or inkscape",
+        )
+    })
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.pos)
+    }
+
+    fn bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+        self.pos += len;
+        Some(bytes)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.bytes(2)?.try_into().ok()?))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.bytes(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_be_bytes(self.bytes(8)?.try_into().ok()?))
+    }
+
+    fn skip_section(&mut self, long: bool) -> Option<()> {
+        let len = if long {
+            self.u64()? as usize
+        } else {
+            self.u32()? as usize
+        };
+        self.bytes(len).map(|_| ())
+    }
+}
+
+// At best, two bytes unpack to 128.
+const PACKBITS_MAX_RATIO: usize = 64;
+
+fn unpack_bits(mut packed: &[u8], out: &mut Vec<u8>) -> Option<()> {
+    while let Some((&header, rest)) = packed.split_first() {
+        let header = header as i8;
+        if header >= 0 {
+            let len = header as usize + 1;
+            out.extend_from_slice(rest.get(..len)?);
+            packed = &rest[len..];
+        } else if header != -128 {
+            let value = *rest.first()?;
+            out.extend(std::iter::repeat_n(value, (1 - header as isize) as usize));
+            packed = &rest[1..];
+        } else {
+            packed = rest;
+        }
+    }
+    Some(())
+}
+
+// The flattened composite Photoshop stores after the layers ("maximize compatibility", on by
+// default), which is what the exported JPEG/PNG versions look like.
+fn load_psd(buffer: &[u8]) -> Option<DynamicImage> {
+    let mut reader = Reader {
+        data: buffer,
+        pos: 0,
+    };
+    if reader.bytes(4)? != b"8BPS" {
+        return None;
+    }
+    // Version 2 is the large document format (PSB), with wider lengths.
+    let large = reader.u16()? == 2;
+    reader.bytes(6)?;
+    let channels = reader.u16()? as usize;
+    let height = reader.u32()? as usize;
+    let width = reader.u32()? as usize;
+    let depth = reader.u16()?;
+    let color_mode = reader.u16()?;
+    let color_channels = match color_mode {
+        1 => 1, // Grayscale
+        3 => 3, // RGB
+        _ => return None,
+    };
+    if !(depth == 8 || depth == 16) || channels < color_channels {
+        return None;
+    }
+    // Color mode data, image resources, layers and masks.
+    reader.skip_section(false)?;
+    reader.skip_section(false)?;
+    reader.skip_section(large)?;
+
+    let bytes_per_sample = depth as usize / 8;
+    let row_len = width.checked_mul(bytes_per_sample)?;
+    let used_channels = channels.min(color_channels + 1);
+    let planes_len = used_channels.checked_mul(height)?.checked_mul(row_len)?;
+    let compression = reader.u16()?;
+    // The header is not trusted with the allocations: the data left has to be big enough for the
+    // raw planes, or for the row lengths and the planes at the best PackBits ratio.
+    let fits = match compression {
+        0 => planes_len <= reader.remaining(),
+        1 => {
+            channels
+                .checked_mul(height)?
+                .checked_mul(if large { 4 } else { 2 })?
+                <= reader.remaining()
+                && planes_len / PACKBITS_MAX_RATIO <= reader.remaining()
+        }
+        _ => false,
+    };
+    if !fits {
+        return None;
+    }
+    let mut planes = Vec::with_capacity(planes_len);
+    match compression {
+        // Raw
+        0 => planes.extend_from_slice(reader.bytes(planes_len)?),
+        // PackBits, each row compressed separately, with a table of the compressed row lengths.
+        1 => {
+            let mut row_lengths = Vec::with_capacity(channels * height);
+            for _ in 0..channels * height {
+                row_lengths.push(if large {
+                    reader.u32()? as usize
+                } else {
+                    reader.u16()? as usize
+                });
+            }
+            for len in &row_lengths[..used_channels * height] {
+                unpack_bits(reader.bytes(*len)?, &mut planes)?;
+            }
+        }
+        _ => return None,
+    }
+    if planes.len() < planes_len {
+        return None;
+    }
+
+    // Planar to interleaved, keeping the most significant byte of 16 bit samples.
+    let plane_len = height * row_len;
+    let pixels: Vec<u8> = (0..width * height)
+        .flat_map(|i| (0..used_channels).map(move |c| c * plane_len + i * bytes_per_sample))
+        .map(|offset| planes[offset])
+        .collect();
+    let (width, height) = (width as u32, height as u32);
+    match used_channels {
+        1 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+        2 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8),
+        3 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+        _ => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8),
+    }
+}
+
+// GIMP's format has no flattened copy, so it is flattened by xcftools' `xcf2png` or ImageMagick.
+fn load_xcf(path: &Path, buffer: &[u8]) -> ImageResult<DynamicImage> {
+    with_local_file(path, buffer, |file| {
+        let file = file.as_os_str();
+        convert_with("xcf2png", &[file])
+            .or_else(|| convert_with("convert", &[file, "-flatten".as_ref(), "png:-".as_ref()]))
+    })?
+    .ok_or_else(|| unsupported(path, "flattening XCFs requires xcf2png or ImageMagick"))
+}
+
+// Like `load`, but animations are decoded frame by frame and averaged. Also returns the number of
+// frames: animation frames, video keyframes, or sizes for icons.
+pub fn load_for_hashing(path: &Path, buffer: &[u8]) -> ImageResult<(DynamicImage, u32)> {
+    if is_icon(path) {
+        return load_icon(path, buffer);
+    }
+    if is_video(path) {
+        return load_video(path, buffer, VIDEO_KEYFRAMES);
+    }
+    let averaged =
+        match image::guess_format(buffer).ok() {
+            Some(image::ImageFormat::Gif) => average(
+                GifDecoder::new(Cursor::new(buffer))?
+                    .into_frames()
+                    .map(|frame| frame.map(|frame| DynamicImage::ImageRgba8(frame.into_buffer()))),
+            )?,
+            Some(image::ImageFormat::Png) => {
+                let decoder = PngDecoder::new(Cursor::new(buffer))?;
+                if decoder.is_apng() {
+                    average(decoder.apng().into_frames().map(|frame| {
+                        frame.map(|frame| DynamicImage::ImageRgba8(frame.into_buffer()))
+                    }))?
+                } else {
+                    None
+                }
+            }
+            Some(image::ImageFormat::WebP) if image::load_from_memory(buffer).is_err() => {
+                webp_frames(buffer).and_then(|frames| average(frames.into_iter().map(Ok)).ok()?)
+            }
+            _ => None,
+        };
+    match averaged {
+        Some((image, frames)) => Ok((image, frames)),
+        None => load(path, buffer).map(|image| (image, 1)),
+    }
+}
+
+// Returns the average frame and the number of frames it was computed from.
+fn average(
+    frames: impl Iterator<Item = ImageResult<DynamicImage>>,
+) -> ImageResult<Option<(DynamicImage, u32)>> {
+    let mut sum: Vec<u32> = Vec::new();
+    let mut dimensions = (0, 0);
+    let mut count = 0;
+    for frame in frames.take(MAX_ANIMATION_FRAMES) {
+        let frame = frame?.into_rgba8();
+        if count == 0 {
+            dimensions = frame.dimensions();
+            sum = vec![0; frame.len()];
+        } else if frame.dimensions() != dimensions {
+            continue;
+        }
+        for (sum, &value) in sum.iter_mut().zip(frame.iter()) {
+            *sum += value as u32;
+        }
+        count += 1;
+    }
+    if count == 0 {
+        return Ok(None);
+    }
+    let pixels = sum.into_iter().map(|sum| (sum / count) as u8).collect();
+    Ok(RgbaImage::from_raw(dimensions.0, dimensions.1, pixels)
+        .map(|image| (DynamicImage::ImageRgba8(image), count)))
+}
+
+fn read_u24(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 3)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+// RIFF chunks as (id, payload), padded to an even length.
+pub fn riff_chunks(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    std::iter::from_fn(move || {
+        let id = data.get(..4)?;
+        let len = u32::from_le_bytes(data.get(4..8)?.try_into().ok()?) as usize;
+        let payload = data.get(8..8 + len)?;
+        data = data.get(8 + len + len % 2..).unwrap_or_default();
+        Some((id, payload))
+    })
+}
+
+// The composited frames of an animated WebP. Like the `image` crate, only lossy frames are
+// decoded, and only their luma. Frames are drawn over the previous ones, ignoring the blending
+// and disposal modes, which is close enough for hashing.
+fn webp_frames(buffer: &[u8]) -> Option<Vec<DynamicImage>> {
+    if buffer.get(..4)? != b"RIFF" || buffer.get(8..12)? != b"WEBP" {
+        return None;
+    }
+    let mut canvas: Option<GrayImage> = None;
+    let mut frames = Vec::new();
+    for (id, payload) in riff_chunks(&buffer[12..]) {
+        match id {
+            b"VP8X" => {
+                let width = read_u24(payload, 4)? + 1;
+                let height = read_u24(payload, 7)? + 1;
+                canvas = Some(GrayImage::new(width, height));
+            }
+            b"ANMF" => {
+                let canvas = canvas.as_mut()?;
+                let (x, y) = (read_u24(payload, 0)? * 2, read_u24(payload, 3)? * 2);
+                let Some((_, vp8)) = riff_chunks(payload.get(16..)?).find(|(id, _)| *id == b"VP8 ")
+                else {
+                    continue;
+                };
+                let Ok(frame) = Vp8Decoder::new(Cursor::new(vp8)).decode_frame().cloned() else {
+                    continue;
+                };
+                let Some(frame) =
+                    GrayImage::from_raw(frame.width as u32, frame.height as u32, frame.ybuf)
+                else {
+                    continue;
+                };
+                image::imageops::overlay(canvas, &frame, x, y);
+                frames.push(DynamicImage::ImageLuma8(canvas.clone()));
+                if frames.len() == MAX_ANIMATION_FRAMES {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    (!frames.is_empty()).then_some(frames)
+}
+
+pub fn is_raw(path: &Path) -> bool {
+    RAW_EXTENSIONS.contains(&extension(path).as_str())
+}
+
+pub fn is_video(path: &Path) -> bool {
+    VIDEO_EXTENSIONS.contains(&extension(path).as_str())
+}
+
+pub fn is_icon(path: &Path) -> bool {
+    matches!(extension(path).as_str(), "ico" | "icns")
+}
+
+// Icons bundle the same picture at several sizes. The largest one is always used, whichever the
+// decoder would pick, so that the same icon hashes the same in every container. Also returns the
+// number of sizes.
+fn load_icon(path: &Path, buffer: &[u8]) -> ImageResult<(DynamicImage, u32)> {
+    if extension(path) == "icns" {
+        return load_icns(buffer)
+            .ok_or_else(|| unsupported(path, "no PNG or RLE icon found in the ICNS file"));
+    }
+    match load_ico(buffer) {
+        Some(icon) => Ok(icon),
+        None => image::load_from_memory(buffer).map(|image| (image, 1)),
+    }
+}
+
+fn load_ico(buffer: &[u8]) -> Option<(DynamicImage, u32)> {
+    if buffer.get(..4)? != [0, 0, 1, 0] {
+        return None;
+    }
+    let count = u16::from_le_bytes(buffer.get(4..6)?.try_into().ok()?) as usize;
+    let entry = (0..count)
+        .filter_map(|i| buffer.get(6 + i * 16..6 + (i + 1) * 16))
+        // A stored size of 0 means 256. Among equal sizes, the deepest colours win.
+        .max_by_key(|entry| {
+            let size = |byte: u8| if byte == 0 { 256 } else { byte as u32 };
+            (size(entry[0]) * size(entry[1]), entry[6])
+        })?;
+    let len = u32::from_le_bytes(entry[8..12].try_into().ok()?) as usize;
+    let offset = u32::from_le_bytes(entry[12..16].try_into().ok()?) as usize;
+    let data = buffer.get(offset..offset + len)?;
+    if data.starts_with(b"\x89PNG") {
+        let image = image::load_from_memory_with_format(data, image::ImageFormat::Png).ok()?;
+        return Some((image, count as u32));
+    }
+    // The `image` crate has no API to pick an entry, so BMP entries are decoded from a single
+    // entry ICO built around them, which takes care of their masks.
+    let mut single = vec![0, 0, 1, 0, 1, 0];
+    single.extend_from_slice(&entry[..12]);
+    single.extend_from_slice(&22u32.to_le_bytes());
+    single.extend_from_slice(data);
+    let image = image::load_from_memory_with_format(&single, image::ImageFormat::Ico).ok()?;
+    Some((image, count as u32))
+}
+
+// Apple's PackBits variant: a header byte below 0x80 copies the next `header + 1` bytes, otherwise
+// the next byte is repeated `header - 125` times.
+fn icns_unpack(mut packed: &[u8], len: usize) -> Option<(Vec<u8>, &[u8])> {
+    let mut out = Vec::with_capacity(len);
+    while out.len() < len {
+        let (&header, rest) = packed.split_first()?;
+        if header < 0x80 {
+            let count = header as usize + 1;
+            out.extend_from_slice(rest.get(..count)?);
+            packed = &rest[count..];
+        } else {
+            out.extend(std::iter::repeat_n(*rest.first()?, header as usize - 125));
+            packed = &rest[1..];
+        }
+    }
+    out.truncate(len);
+    Some((out, packed))
+}
+
+// Planar channels, each compressed on its own.
+fn icns_rle(data: &[u8], size: u32, channels: usize) -> Option<Vec<Vec<u8>>> {
+    let len = (size * size) as usize;
+    // Uncompressed when the data is exactly the size of the pixels.
+    if data.len() == len * channels {
+        return Some(data.chunks(len).map(<[u8]>::to_vec).collect());
+    }
+    let mut planes = Vec::new();
+    let mut rest = data;
+    for _ in 0..channels {
+        let (plane, remaining) = icns_unpack(rest, len)?;
+        planes.push(plane);
+        rest = remaining;
+    }
+    Some(planes)
+}
+
+// ICNS entry type -> size and mask type for the RGB ones, whose alpha is stored apart.
+const ICNS_RLE: [([u8; 4], u32, Option<[u8; 4]>); 6] = [
+    (*b"is32", 16, Some(*b"s8mk")),
+    (*b"il32", 32, Some(*b"l8mk")),
+    (*b"ih32", 48, Some(*b"h8mk")),
+    (*b"it32", 128, Some(*b"t8mk")),
+    (*b"ic04", 16, None),
+    (*b"ic05", 32, None),
+];
+
+fn load_icns(buffer: &[u8]) -> Option<(DynamicImage, u32)> {
+    if buffer.get(..4)? != b"icns" {
+        return None;
+    }
+    let mut entries = HashMap::new();
+    let mut pos = 8;
+    while let Some(header) = buffer.get(pos..pos + 8) {
+        let len = u32::from_be_bytes(header[4..].try_into().ok()?) as usize;
+        if len < 8 {
+            break;
+        }
+        entries.insert(&header[..4], buffer.get(pos + 8..pos + len)?);
+        pos += len;
+    }
+
+    let mut candidates: Vec<(u32, DynamicImage)> = Vec::new();
+    let mut sizes = 0;
+    for (kind, data) in &entries {
+        if let Some(&(_, size, mask)) = ICNS_RLE.iter().find(|(k, _, _)| k[..] == **kind) {
+            sizes += 1;
+            let pixels = match mask {
+                // `it32` data starts with four zero bytes.
+                Some(mask) => {
+                    let data = if *kind == b"it32" {
+                        data.get(4..)?
+                    } else {
+                        data
+                    };
+                    let mut planes = icns_rle(data, size, 3)?;
+                    let alpha = entries
+                        .get(&mask[..])
+                        .filter(|alpha| alpha.len() == (size * size) as usize)
+                        .map_or_else(|| vec![255; (size * size) as usize], |alpha| alpha.to_vec());
+                    planes.push(alpha);
+                    planes
+                }
+                None => {
+                    let mut planes = icns_rle(data.strip_prefix(b"ARGB")?, size, 4)?;
+                    planes.rotate_left(1);
+                    planes
+                }
+            };
+            let rgba = (0..(size * size) as usize)
+                .flat_map(|i| pixels.iter().map(move |plane| plane[i]))
+                .collect();
+            candidates.push((
+                size,
+                DynamicImage::ImageRgba8(RgbaImage::from_raw(size, size, rgba)?),
+            ));
+        } else if data.starts_with(b"\x89PNG") {
+            sizes += 1;
+            if let Ok(image) = image::load_from_memory_with_format(data, image::ImageFormat::Png) {
+                candidates.push((image.width(), image));
+            }
+        } else if data.starts_with(b"\0\0\0\x0cjP") {
+            // JPEG 2000, which the `image` crate does not decode.
+            sizes += 1;
+        }
+    }
+    let (_, image) = candidates.into_iter().max_by_key(|(size, _)| *size)?;
+    Some((image, sizes))
+}
+
+// Maps linear HDR values to 8 bit sRGB with the global Reinhard operator, exposed so that the
+// log-average luminance lands on middle grey. The `image` crate clips everything above 1 instead,
+// which turns bright areas flat white and makes the hash depend on the exposure.
+fn tone_map(width: u32, height: u32, pixels: &[[f32; 3]]) -> Option<DynamicImage> {
+    let luminance = |[r, g, b]: [f32; 3]| 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let log_sum: f64 = pixels
+        .iter()
+        .map(|&pixel| (luminance(pixel).max(0.0) as f64 + 1e-4).ln())
+        .sum();
+    let exposure = 0.18 / (log_sum / pixels.len().max(1) as f64).exp() as f32;
+    let rgb = pixels
+        .iter()
+        .flat_map(|&pixel| {
+            let scaled = luminance(pixel).max(0.0) * exposure;
+            let ratio = if scaled > 0.0 {
+                scaled / (1.0 + scaled) / luminance(pixel)
+            } else {
+                0.0
+            };
+            pixel.map(|value| {
+                let linear = (value * ratio).clamp(0.0, 1.0);
+                let encoded = if linear <= 0.0031308 {
+                    linear * 12.92
+                } else {
+                    1.055 * linear.powf(1.0 / 2.4) - 0.055
+                };
+                (encoded * 255.0).round() as u8
+            })
+        })
+        .collect();
+    image::RgbImage::from_raw(width, height, rgb).map(DynamicImage::ImageRgb8)
+}
+
+// Radiance RGBE.
+fn load_hdr(buffer: &[u8]) -> ImageResult<DynamicImage> {
+    let decoder = image::codecs::hdr::HdrDecoder::new(Cursor::new(buffer))?;
+    let metadata = decoder.metadata();
+    let pixels: Vec<[f32; 3]> = decoder
+        .read_image_hdr()?
+        .into_iter()
+        .map(|pixel| pixel.0)
+        .collect();
+    tone_map(metadata.width, metadata.height, &pixels).ok_or_else(|| {
+        ImageError::Decoding(image::error::DecodingError::new(
+            image::ImageFormat::Hdr.into(),
+            "truncated pixel data",
+        ))
+    })
+}
+
+// Portable float map: text header, then rows of little or big endian floats, bottom to top.
+fn parse_pfm(data: &[u8]) -> Option<DynamicImage> {
+    let mut fields = Vec::new();
+    let mut pos = 0;
+    while fields.len() < 4 {
+        while data.get(pos)?.is_ascii_whitespace() {
+            pos += 1;
+        }
+        let start = pos;
+        while !data.get(pos)?.is_ascii_whitespace() {
+            pos += 1;
+        }
+        fields.push(std::str::from_utf8(&data[start..pos]).ok()?);
+    }
+    // A single whitespace character ends the header.
+    let pixels = data.get(pos + 1..)?;
+    let channels = match fields[0] {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return None,
+    };
+    let width: u32 = fields[1].parse().ok()?;
+    let height: u32 = fields[2].parse().ok()?;
+    let little_endian = fields[3].parse::<f32>().ok()? < 0.0;
+    // Checked before allocating, the header of a truncated or hostile file can claim anything.
+    let count = (width as usize).checked_mul(height as usize)?;
+    let pixels = pixels.get(..count.checked_mul(channels * 4)?)?;
+    if count == 0 {
+        return None;
+    }
+    let mut rgb = Vec::with_capacity(count);
+    for row in pixels.chunks(width as usize * channels * 4).rev() {
+        for pixel in row.chunks(channels * 4) {
+            let value = |i: usize| {
+                let bytes = pixel[i * 4..i * 4 + 4].try_into().unwrap();
+                if little_endian {
+                    f32::from_le_bytes(bytes)
+                } else {
+                    f32::from_be_bytes(bytes)
+                }
+            };
+            rgb.push(if channels == 3 {
+                [value(0), value(1), value(2)]
+            } else {
+                [value(0); 3]
+            });
+        }
+    }
+    tone_map(width, height, &rgb)
+}
+
+// OpenEXR, which the `image` crate does not decode. ImageMagick converts it to a float map without
+// clipping, so that it gets the same tone mapping as Radiance files.
+fn load_exr(path: &Path, buffer: &[u8]) -> ImageResult<DynamicImage> {
+    with_local_file(path, buffer, |file| {
+        run_converter("convert", &[file.as_os_str(), "pfm:-".as_ref()])
+    })?
+    .and_then(|pfm| parse_pfm(&pfm))
+    .ok_or_else(|| unsupported(path, "decoding OpenEXR requires ImageMagick"))
+}
+
+// The `image` crate only decodes baseline and progressive Huffman coded JPEGs, and wants CMYK ones
+// to have an Adobe segment. Everything else, e.g. the arithmetic coded or 12 bit JPEGs of some
+// archives, goes through libjpeg's djpeg or ImageMagick.
+fn load_jpeg(path: &Path, buffer: &[u8]) -> ImageResult<DynamicImage> {
+    let err = match image::load_from_memory_with_format(buffer, image::ImageFormat::Jpeg) {
+        Ok(image) => return Ok(image),
+        Err(err) => err,
+    };
+    if let Some(image) = load_cmyk_jpeg(buffer) {
+        return Ok(image);
+    }
+    with_local_file(path, buffer, |file| {
+        let file = file.as_os_str();
+        convert_with("djpeg", &[file]).or_else(|| {
+            convert_with(
+                "convert",
+                &[
+                    file,
+                    "-colorspace".as_ref(),
+                    "sRGB".as_ref(),
+                    "png:-".as_ref(),
+                ],
+            )
+        })
+    })?
+    .ok_or(err)
+}
+
+// CMYK without the Adobe segment, as written by some print workflows. Without it the ink values
+// are stored as they are, while Adobe's are inverted: the decoder is given an Adobe segment so
+// that it accepts the file, and its inversion is accounted for in the conversion to RGB.
+fn load_cmyk_jpeg(buffer: &[u8]) -> Option<DynamicImage> {
+    let mut patched = buffer.get(..2)?.to_vec();
+    patched.extend_from_slice(b"\xFF\xEE\x00\x0EAdobe\x00\x64\x00\x00\x00\x00\x00");
+    patched.extend_from_slice(&buffer[2..]);
+    let mut decoder = jpeg_decoder::Decoder::new(Cursor::new(patched));
+    let cmyk = decoder.decode().ok()?;
+    let info = decoder.info()?;
+    if info.pixel_format != jpeg_decoder::PixelFormat::CMYK32 {
+        return None;
+    }
+    let rgb = cmyk
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let k = pixel[3] as u16;
+            [0, 1, 2].map(|i| (pixel[i] as u16 * k / 255) as u8)
+        })
+        .collect();
+    image::RgbImage::from_raw(info.width as u32, info.height as u32, rgb)
+        .map(DynamicImage::ImageRgb8)
+}
+
+// HEIF stills, e.g. iPhone photos, through libheif's heif-convert, which only writes to files, or
+// ImageMagick.
+fn load_heic(path: &Path, buffer: &[u8]) -> ImageResult<DynamicImage> {
+    with_local_file(path, buffer, |file| {
+        let tmp = temp_path("png");
+        let converted = run_converter("heif-convert", &[file.as_os_str(), tmp.as_os_str()])
+            .and_then(|_| image::open(&tmp).ok());
+        let _ = std::fs::remove_file(&tmp);
+        converted.or_else(|| convert_with("convert", &[file.as_os_str(), "png:-".as_ref()]))
+    })?
+    .ok_or_else(|| unsupported(path, "decoding HEIF requires heif-convert or ImageMagick"))
+}
+
+// Duration in seconds, from ffprobe.
+fn video_duration(path: &Path) -> Option<f64> {
+    let output = run_converter(
+        "ffprobe",
+        &[
+            "-v".as_ref(),
+            "error".as_ref(),
+            "-show_entries".as_ref(),
+            "format=duration".as_ref(),
+            "-of".as_ref(),
+            "csv=p=0".as_ref(),
+            path.as_os_str(),
+        ],
+    )?;
+    String::from_utf8_lossy(&output).trim().parse().ok()
+}
+
+// The first keyframe from `seconds` on, as BMP, which is quick to write at full size.
+fn video_keyframe(path: &Path, seconds: f64) -> Option<DynamicImage> {
+    let seconds = format!("{:.3}", seconds);
+    convert_with(
+        "ffmpeg",
+        &[
+            "-v".as_ref(),
+            "error".as_ref(),
+            "-skip_frame".as_ref(),
+            "nokey".as_ref(),
+            "-ss".as_ref(),
+            seconds.as_ref(),
+            "-i".as_ref(),
+            path.as_os_str(),
+            "-frames:v".as_ref(),
+            "1".as_ref(),
+            "-f".as_ref(),
+            "image2pipe".as_ref(),
+            "-vcodec".as_ref(),
+            "bmp".as_ref(),
+            "-".as_ref(),
+        ],
+    )
+}
+
+// A temporary file name no other decode uses, even of the same file in another thread.
+fn temp_path(extension: &str) -> PathBuf {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    std::env::temp_dir().join(format!(
+        "img-dedup-{}-{}.{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        extension
+    ))
+}
+
+// Command line tools only read local files, files on remote storage are written to a temporary
+// file for them.
+fn with_local_file<T>(path: &Path, buffer: &[u8], f: impl FnOnce(&Path) -> T) -> ImageResult<T> {
+    // Never looked up locally, a mirror with the same layout would be decoded instead.
+    if !crate::storage::is_remote(&path.to_string_lossy()) {
+        return Ok(f(path));
+    }
+    let tmp = temp_path(&extension(path));
+    std::fs::write(&tmp, buffer)?;
+    let result = f(&tmp);
+    let _ = std::fs::remove_file(&tmp);
+    Ok(result)
+}
+
+// The average of `count` keyframes evenly spread over the video, and how many were found. ffmpeg
+// seeks in files, remote videos, which are only in `buffer`, are written to a temporary one.
+fn load_video(path: &Path, buffer: &[u8], count: u32) -> ImageResult<(DynamicImage, u32)> {
+    let keyframes: Vec<DynamicImage> =
+        with_local_file(path, buffer, |file| match video_duration(file) {
+            Some(duration) => (0..count)
+                .filter_map(|i| video_keyframe(file, duration * (i as f64 + 0.5) / count as f64))
+                .collect(),
+            None => video_keyframe(file, 0.0).into_iter().collect(),
+        })?;
+    average(keyframes.into_iter().map(Ok))?
+        .ok_or_else(|| unsupported(path, "decoding videos requires ffmpeg"))
+}
+
+// JPEG XL, decoded by jxl-oxide, or libjxl's djxl, which like heif-convert only writes to files,
+// or ImageMagick for what it cannot decode. Recompressed JPEGs decode to the original pixels, so
+// they match the JPEG they come from.
+fn load_jxl(path: &Path, buffer: &[u8]) -> ImageResult<DynamicImage> {
+    if let Some(image) = decode_jxl(buffer) {
+        return Ok(image);
+    }
+    with_local_file(path, buffer, |file| {
+        let tmp = temp_path("png");
+        let converted = run_converter("djxl", &[file.as_os_str(), tmp.as_os_str()])
+            .and_then(|_| image::open(&tmp).ok());
+        let _ = std::fs::remove_file(&tmp);
+        converted.or_else(|| convert_with("convert", &[file.as_os_str(), "png:-".as_ref()]))
+    })?
+    .ok_or_else(|| unsupported(path, "decoding JPEG XL requires djxl or ImageMagick"))
+}
+
+// The first frame, oriented.
+fn decode_jxl(buffer: &[u8]) -> Option<DynamicImage> {
+    let image = jxl_oxide::JxlImage::builder()
+        .read(Cursor::new(buffer))
+        .map_err(|err| debug!("jxl-oxide failed: {}", err))
+        .ok()?;
+    let render = image.render_frame(0).ok()?;
+    let mut stream = render.stream();
+    let (width, height) = (stream.width(), stream.height());
+    let channels = stream.channels() as usize;
+    let mut pixels = vec![
+        0u8;
+        (width as usize)
+            .checked_mul(height as usize)?
+            .checked_mul(channels)?
+    ];
+    stream.write_to_buffer(&mut pixels);
+    match channels {
+        1 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+        2 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageLumaA8),
+        3 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+        4 => ImageBuffer::from_raw(width, height, pixels).map(DynamicImage::ImageRgba8),
+        _ => None,
+    }
+}
+
+// RAW files embed JPEG previews, the largest one being about the size of the sensor on most
+// cameras. Decoding the sensor data itself would need a RAW converter and give the same hash.
+fn load_raw(path: &Path, buffer: &[u8]) -> ImageResult<DynamicImage> {
+    let mut largest: Option<(u64, &[u8])> = None;
+    let mut pos = 0;
+    while let Some(start) = find(buffer, &[0xFF, 0xD8, 0xFF], pos) {
+        pos = start + 3;
+        // Only the header is read. The sensor data of some formats is a lossless JPEG, which the
+        // decoder rejects.
+        let jpeg = &buffer[start..];
+        let Ok(decoder) = image::codecs::jpeg::JpegDecoder::new(Cursor::new(jpeg)) else {
+            continue;
+        };
+        let (width, height) = image::ImageDecoder::dimensions(&decoder);
+        let pixels = width as u64 * height as u64;
+        if largest.is_none_or(|(largest, _)| pixels > largest) {
+            largest = Some((pixels, jpeg));
+        }
+    }
+    let (_, jpeg) =
+        largest.ok_or_else(|| unsupported(path, "no JPEG preview found in the RAW file"))?;
+    image::load_from_memory_with_format(jpeg, image::ImageFormat::Jpeg)
+}
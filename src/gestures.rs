@@ -0,0 +1,55 @@
+// Horizontal swipes on touch screens, to triage pairs without aiming at the buttons. Mouse drags
+// are left alone, they would trash files by accident. Vertical drags scroll, with the kinetic
+// scrolling of egui.
+use eframe::egui;
+use egui::Pos2;
+
+// In points, shorter moves are taps or scrolls.
+const MIN_DISTANCE: f32 = 80.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+}
+
+#[derive(Default)]
+pub struct Swipe {
+    start: Option<Pos2>,
+    last: Option<Pos2>,
+}
+
+impl Swipe {
+    // Called once per frame, returns where the swipe ending this frame started and its direction.
+    pub fn update(&mut self, ctx: &egui::Context) -> Option<(Pos2, Direction)> {
+        let input = ctx.input();
+        if input.pointer.any_pressed() {
+            self.start = input.pointer.press_origin().filter(|_| input.any_touches());
+            self.last = self.start;
+        }
+        // The position is gone once the finger is lifted.
+        if let Some(pos) = input
+            .pointer
+            .interact_pos()
+            .filter(|_| self.start.is_some())
+        {
+            self.last = Some(pos);
+        }
+        if !input.pointer.any_released() {
+            return None;
+        }
+        let (start, end) = (self.start.take()?, self.last.take()?);
+        let delta = end - start;
+        if delta.x.abs() < MIN_DISTANCE || delta.x.abs() < 2.0 * delta.y.abs() {
+            return None;
+        }
+        Some((
+            start,
+            if delta.x < 0.0 {
+                Direction::Left
+            } else {
+                Direction::Right
+            },
+        ))
+    }
+}
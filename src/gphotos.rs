@@ -0,0 +1,317 @@
+// Which local images are already in Google Photos, before archiving or deleting them locally. The
+// Library API only lists what an app uploaded itself, so the items to compare against are picked
+// in the Google Photos Picker, in the browser. Signing in is the OAuth flow of installed apps, with
+// a loopback redirect and PKCE, and a client of the user's own from `IMG_DEDUP_GOOGLE_CLIENT_ID`
+// and `IMG_DEDUP_GOOGLE_CLIENT_SECRET`. Thumbnails are hashed like local images, by the caller.
+use log::{debug, info};
+use ring::rand::SecureRandom;
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::time::{Duration, Instant};
+
+const AUTH_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+const TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+const PICKER_API: &str = "https://photospicker.googleapis.com/v1";
+const SCOPE: &str = "https://www.googleapis.com/auth/photospicker.mediaitems.readonly";
+const TIMEOUT: Duration = Duration::from_secs(60);
+// To sign in, then to pick the items.
+const USER_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+// Longest side of the thumbnails hashed, plenty for perceptual hashes.
+const THUMBNAIL_SIZE: u32 = 512;
+
+#[derive(Deserialize)]
+struct Token {
+    access_token: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PollingConfig {
+    // E.g. `5s`.
+    poll_interval: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Session {
+    id: String,
+    picker_uri: String,
+    polling_config: Option<PollingConfig>,
+    #[serde(default)]
+    media_items_set: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MediaFile {
+    base_url: String,
+    mime_type: String,
+    filename: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MediaItem {
+    media_file: MediaFile,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MediaItems {
+    #[serde(default)]
+    media_items: Vec<MediaItem>,
+    next_page_token: Option<String>,
+}
+
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = text
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+fn base64url(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut encoded = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..=chunk.len() {
+            encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+        }
+    }
+    encoded
+}
+
+fn env(name: &str) -> Result<String, String> {
+    std::env::var(name)
+        .ok()
+        .filter(|value| !value.is_empty())
+        .ok_or_else(|| format!("{} is not set", name))
+}
+
+// The code the browser is redirected to the loopback address with.
+fn wait_for_code(listener: &TcpListener) -> Result<String, String> {
+    listener
+        .set_nonblocking(true)
+        .map_err(|err| err.to_string())?;
+    let started = Instant::now();
+    let mut stream = loop {
+        match listener.accept() {
+            Ok((stream, _)) => break stream,
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                if started.elapsed() > USER_TIMEOUT {
+                    return Err("Timed out waiting for the sign in".to_owned());
+                }
+                std::thread::sleep(Duration::from_millis(200));
+            }
+            Err(err) => return Err(err.to_string()),
+        }
+    };
+    stream
+        .set_nonblocking(false)
+        .map_err(|err| err.to_string())?;
+    // `GET /?code=...&scope=... HTTP/1.1`
+    let mut request = String::new();
+    BufReader::new(&stream)
+        .read_line(&mut request)
+        .map_err(|err| err.to_string())?;
+    let query = request
+        .split_whitespace()
+        .nth(1)
+        .and_then(|target| target.split_once('?'))
+        .map_or("", |(_, query)| query);
+    let param = |name: &str| {
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| decode(value))
+    };
+    let _ = stream.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nConnection: close\r\n\r\n\
+          You can close this tab and go back to Image dedup.",
+    );
+    match (param("code"), param("error")) {
+        (Some(code), _) => Ok(code),
+        (None, Some(error)) => Err(format!("Sign in failed: {}", error)),
+        (None, None) => Err("Sign in failed".to_owned()),
+    }
+}
+
+fn sign_in() -> Result<String, String> {
+    let client_id = env("IMG_DEDUP_GOOGLE_CLIENT_ID")?;
+    let client_secret = env("IMG_DEDUP_GOOGLE_CLIENT_SECRET")?;
+    let mut verifier = [0u8; 32];
+    ring::rand::SystemRandom::new()
+        .fill(&mut verifier)
+        .map_err(|_| "No randomness for the sign in")?;
+    let verifier = base64url(&verifier);
+    let challenge =
+        base64url(ring::digest::digest(&ring::digest::SHA256, verifier.as_bytes()).as_ref());
+
+    let listener = TcpListener::bind("127.0.0.1:0").map_err(|err| err.to_string())?;
+    let redirect_uri = format!(
+        "http://127.0.0.1:{}",
+        listener.local_addr().map_err(|err| err.to_string())?.port()
+    );
+    let url = format!(
+        "{}?client_id={}&redirect_uri={}&response_type=code&scope={}&code_challenge={}\
+         &code_challenge_method=S256",
+        AUTH_URL,
+        encode(&client_id),
+        encode(&redirect_uri),
+        encode(SCOPE),
+        challenge
+    );
+    webbrowser::open(&url).map_err(|err| err.to_string())?;
+    let code = wait_for_code(&listener)?;
+
+    let token: Token = ureq::post(TOKEN_URL)
+        .timeout(TIMEOUT)
+        .send_form(&[
+            ("client_id", &client_id),
+            ("client_secret", &client_secret),
+            ("code", &code),
+            ("code_verifier", &verifier),
+            ("grant_type", "authorization_code"),
+            ("redirect_uri", &redirect_uri),
+        ])
+        .map_err(|err| err.to_string())?
+        .into_json()
+        .map_err(|err| err.to_string())?;
+    info!("Signed in to Google Photos");
+    Ok(token.access_token)
+}
+
+fn get<T: serde::de::DeserializeOwned>(token: &str, url: &str) -> Result<T, String> {
+    ureq::get(url)
+        .set("Authorization", &format!("Bearer {}", token))
+        .timeout(TIMEOUT)
+        .call()
+        .map_err(|err| err.to_string())?
+        .into_json()
+        .map_err(|err| err.to_string())
+}
+
+// Opens the Picker and waits for the items picked in it.
+fn pick(token: &str) -> Result<Vec<MediaItem>, String> {
+    let session: Session = ureq::post(&format!("{}/sessions", PICKER_API))
+        .set("Authorization", &format!("Bearer {}", token))
+        .timeout(TIMEOUT)
+        .send_string("{}")
+        .map_err(|err| err.to_string())?
+        .into_json()
+        .map_err(|err| err.to_string())?;
+    webbrowser::open(&session.picker_uri).map_err(|err| err.to_string())?;
+    let interval = session
+        .polling_config
+        .and_then(|config| config.poll_interval)
+        .and_then(|interval| interval.trim_end_matches('s').parse::<f64>().ok())
+        .map_or(Duration::from_secs(5), Duration::from_secs_f64);
+    let url = format!("{}/sessions/{}", PICKER_API, session.id);
+    let started = Instant::now();
+    loop {
+        std::thread::sleep(interval);
+        let polled: Session = get(token, &url)?;
+        if polled.media_items_set {
+            break;
+        }
+        if started.elapsed() > USER_TIMEOUT {
+            return Err("Timed out waiting for the items to be picked".to_owned());
+        }
+    }
+
+    let mut items = Vec::new();
+    let mut page_token: Option<String> = None;
+    loop {
+        let mut url = format!(
+            "{}/mediaItems?sessionId={}&pageSize=100",
+            PICKER_API, session.id
+        );
+        if let Some(page_token) = &page_token {
+            url += &format!("&pageToken={}", encode(page_token));
+        }
+        let page: MediaItems = get(token, &url)?;
+        items.extend(page.media_items);
+        match page.next_page_token {
+            Some(next) => page_token = Some(next),
+            None => break,
+        }
+    }
+    let _ = ureq::delete(&url)
+        .set("Authorization", &format!("Bearer {}", token))
+        .timeout(TIMEOUT)
+        .call();
+    Ok(items)
+}
+
+// The file name and hash of each picked photo, `progress` is told how far along it is.
+pub fn fetch(
+    hash: impl Fn(&image::DynamicImage) -> img_hash::ImageHash,
+    progress: impl Fn(String),
+) -> Result<Vec<(String, img_hash::ImageHash)>, String> {
+    progress("Waiting for the sign in, in the browser".to_owned());
+    let token = sign_in()?;
+    progress("Waiting for photos to be picked, in the browser".to_owned());
+    let items = pick(&token)?;
+    let photos: Vec<MediaFile> = items
+        .into_iter()
+        .map(|item| item.media_file)
+        .filter(|file| file.mime_type.starts_with("image/"))
+        .collect();
+    let mut hashes = Vec::new();
+    for (i, file) in photos.iter().enumerate() {
+        progress(format!("Hashing {} of {} photos", i + 1, photos.len()));
+        let url = format!("{}=w{}-h{}", file.base_url, THUMBNAIL_SIZE, THUMBNAIL_SIZE);
+        let mut buffer = Vec::new();
+        let result = ureq::get(&url)
+            .set("Authorization", &format!("Bearer {}", token))
+            .timeout(TIMEOUT)
+            .call()
+            .map_err(|err| err.to_string())
+            .and_then(|response| {
+                std::io::Read::read_to_end(&mut response.into_reader(), &mut buffer)
+                    .map_err(|err| err.to_string())
+            })
+            .and_then(|_| image::load_from_memory(&buffer).map_err(|err| err.to_string()));
+        match result {
+            Ok(image) => hashes.push((file.filename.clone(), hash(&image))),
+            Err(err) => debug!("Skipping {}: {}", file.filename, err),
+        }
+    }
+    Ok(hashes)
+}
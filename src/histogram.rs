@@ -0,0 +1,95 @@
+// Exposure histograms of the inspected image, to tell which copy was brightened, darkened or
+// clipped when both have the same resolution.
+use eframe::egui;
+use egui::{Color32, Pos2, Rect, Shape, Stroke};
+use image::RgbaImage;
+
+const BINS: usize = 256;
+pub const SIZE: egui::Vec2 = egui::vec2(256.0, 100.0);
+
+pub struct Histogram {
+    pub red: [u32; BINS],
+    pub green: [u32; BINS],
+    pub blue: [u32; BINS],
+    // Rec. 709 luma.
+    pub luma: [u32; BINS],
+    pub pixels: u64,
+}
+
+impl Histogram {
+    pub fn of(image: &RgbaImage) -> Self {
+        let mut histogram = Histogram {
+            red: [0; BINS],
+            green: [0; BINS],
+            blue: [0; BINS],
+            luma: [0; BINS],
+            pixels: 0,
+        };
+        for pixel in image.pixels() {
+            let [r, g, b, _] = pixel.0;
+            histogram.red[r as usize] += 1;
+            histogram.green[g as usize] += 1;
+            histogram.blue[b as usize] += 1;
+            let luma = 0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32;
+            histogram.luma[luma.round() as usize] += 1;
+            histogram.pixels += 1;
+        }
+        histogram
+    }
+
+    // Shares of pure black and pure white pixels.
+    pub fn clipped(&self) -> (f32, f32) {
+        let share = |count: u32| count as f32 / self.pixels.max(1) as f32;
+        (share(self.luma[0]), share(self.luma[BINS - 1]))
+    }
+
+    pub fn mean_luma(&self) -> f32 {
+        let sum: u64 = self
+            .luma
+            .iter()
+            .enumerate()
+            .map(|(value, count)| value as u64 * *count as u64)
+            .sum();
+        sum as f32 / self.pixels.max(1) as f32
+    }
+}
+
+fn curve(rect: Rect, counts: &[u32; BINS], max: u32, color: Color32) -> Shape {
+    let points = counts
+        .iter()
+        .enumerate()
+        .map(|(value, count)| {
+            Pos2::new(
+                rect.left() + rect.width() * value as f32 / (BINS - 1) as f32,
+                rect.bottom() - rect.height() * (*count as f32 / max.max(1) as f32).min(1.0),
+            )
+        })
+        .collect();
+    Shape::line(points, Stroke::new(1.0, color))
+}
+
+// Paints the histogram in `rect`, the luma in white over the channels.
+pub fn paint(painter: &egui::Painter, rect: Rect, histogram: &Histogram) {
+    painter.rect_filled(rect, 2.0, Color32::from_black_alpha(180));
+    // The clipped ends would flatten everything else.
+    let max = [
+        &histogram.red,
+        &histogram.green,
+        &histogram.blue,
+        &histogram.luma,
+    ]
+    .iter()
+    .flat_map(|counts| counts[1..BINS - 1].iter())
+    .copied()
+    .max()
+    .unwrap_or(0);
+    let rect = rect.shrink(4.0);
+    for (counts, color) in [
+        (&histogram.red, Color32::from_rgb(230, 60, 60)),
+        (&histogram.green, Color32::from_rgb(60, 200, 60)),
+        (&histogram.blue, Color32::from_rgb(80, 120, 255)),
+        (&histogram.luma, Color32::WHITE),
+    ] {
+        painter.add(curve(rect, counts, max, color));
+    }
+}
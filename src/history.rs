@@ -0,0 +1,117 @@
+// A summary of each scan, kept across runs to plot the trends in the Statistics section and see
+// the cleanup effort pay off. One JSON object per line, like the journal, but a scan's line is
+// updated as its duplicates are resolved, so the file is rewritten: it stays small.
+use crate::paths;
+use eframe::egui;
+use egui::plot::{Legend, Line, Plot, PlotPoints};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use ubyte::ToByteUnit;
+
+const HISTORY_FILE_NAME: &str = "history.jsonl";
+const PLOT_HEIGHT: f32 = 160.0;
+
+// Saves happen from the autosave threads.
+static LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    // Seconds since the Unix epoch when the scan started, which identifies it.
+    pub time: u64,
+    pub root: String,
+    pub total_bytes: u64,
+    pub images: usize,
+    pub duplicates: usize,
+    // What trashing the suggested copies would free.
+    pub reclaimable_bytes: u64,
+    // Freed by trashing or replacing duplicates since the scan.
+    pub reclaimed_bytes: u64,
+}
+
+fn history_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(HISTORY_FILE_NAME))
+}
+
+pub fn read() -> Vec<Record> {
+    let Some(content) = history_path().and_then(|path| std::fs::read_to_string(path).ok()) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+// Replaces the record of the same scan, if any.
+pub fn save(record: &Record) -> std::io::Result<()> {
+    let path = history_path().ok_or_else(|| std::io::Error::other("No data directory"))?;
+    let _lock = LOCK.lock().unwrap();
+    let mut records = read();
+    records.retain(|other| (other.time, &other.root) != (record.time, &record.root));
+    records.push(record.clone());
+    let mut content = String::new();
+    for record in &records {
+        content += &serde_json::to_string(record).map_err(std::io::Error::other)?;
+        content.push('\n');
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("jsonl.tmp");
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, &path)
+}
+
+// X is in days since the epoch.
+fn date(days: f64) -> String {
+    let (year, month, day) = crate::exif::civil_from_days(days.round() as i64);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+fn days(record: &Record) -> f64 {
+    record.time as f64 / 86400.0
+}
+
+// Space, then duplicate pairs, over time. `records` are of the same directory, oldest first.
+pub fn show(ui: &mut egui::Ui, records: &[&Record]) {
+    let line = |name: &str, value: &dyn Fn(&Record) -> f64| {
+        let points: PlotPoints = records
+            .iter()
+            .map(|record| [days(record), value(record)])
+            .collect();
+        Line::new(points).name(name)
+    };
+    Plot::new("space_history")
+        .height(PLOT_HEIGHT)
+        .legend(Legend::default())
+        .include_y(0.0)
+        .allow_scroll(false)
+        .x_axis_formatter(|x, _| date(x))
+        .y_axis_formatter(|y, _| (y.max(0.0) as u64).bytes().to_string())
+        .label_formatter(|name, point| {
+            format!(
+                "{}\n{}\n{}",
+                name,
+                date(point.x),
+                (point.y.max(0.0) as u64).bytes()
+            )
+        })
+        .show(ui, |plot| {
+            plot.line(line("Total", &|record| record.total_bytes as f64));
+            plot.line(line("Reclaimable", &|record| {
+                record.reclaimable_bytes as f64
+            }));
+            plot.line(line("Reclaimed", &|record| record.reclaimed_bytes as f64));
+        });
+    Plot::new("duplicates_history")
+        .height(PLOT_HEIGHT)
+        .legend(Legend::default())
+        .include_y(0.0)
+        .allow_scroll(false)
+        .x_axis_formatter(|x, _| date(x))
+        .label_formatter(|name, point| format!("{}\n{}\n{:.0}", name, date(point.x), point.y))
+        .show(ui, |plot| {
+            plot.line(line("Duplicate pairs", &|record| record.duplicates as f64));
+        });
+}
@@ -0,0 +1,73 @@
+use crate::settings::Hooks;
+use log::{error, info};
+use std::process::Command;
+
+#[derive(Debug, Clone, Copy)]
+pub enum HookEvent {
+    Keep,
+    Trash,
+    // Hardlinked, symlinked, moved or cloned over, see `replace.rs`.
+    Replace,
+}
+
+impl HookEvent {
+    fn name(self) -> &'static str {
+        match self {
+            HookEvent::Keep => "keep",
+            HookEvent::Trash => "trash",
+            HookEvent::Replace => "replace",
+        }
+    }
+
+    fn command(self, hooks: &Hooks) -> &str {
+        match self {
+            HookEvent::Keep => &hooks.on_keep,
+            HookEvent::Trash => &hooks.on_trash,
+            HookEvent::Replace => &hooks.on_replace,
+        }
+    }
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        // `$0` is the script name for `sh -c`, so the real arguments start at `$1`.
+        cmd.arg("-c").arg(command).arg("img-dedup-hook");
+        cmd
+    }
+}
+
+// Fire and forget: the hook runs in the background and only its failure is logged, so that a
+// slow hook never blocks the UI.
+pub fn run(hooks: &Hooks, event: HookEvent, path: &str, group: usize, hash: &str) {
+    let command = event.command(hooks).trim();
+    if command.is_empty() {
+        return;
+    }
+    let mut cmd = shell(command);
+    cmd.arg(path)
+        .arg(group.to_string())
+        .arg(hash)
+        .env("IMG_DEDUP_EVENT", event.name())
+        .env("IMG_DEDUP_PATH", path)
+        .env("IMG_DEDUP_GROUP", group.to_string())
+        .env("IMG_DEDUP_HASH", hash);
+    info!("Running {} hook for {}: {}", event.name(), path, command);
+    match cmd.spawn() {
+        Ok(mut child) => {
+            let command = command.to_owned();
+            rayon::spawn(move || match child.wait() {
+                Ok(status) if !status.success() => {
+                    error!("Hook `{}` exited with {}", command, status)
+                }
+                Err(err) => error!("Failed to wait for hook `{}`: {}", command, err),
+                Ok(_) => {}
+            });
+        }
+        Err(err) => error!("Failed to run hook `{}`: {}", command, err),
+    }
+}
@@ -0,0 +1,331 @@
+// Conversion of images with an embedded ICC profile to sRGB before hashing, so that wide gamut and
+// sRGB exports of the same photo hash the same. Only RGB matrix/TRC profiles are handled, which is
+// what cameras and editors embed (Display P3, Adobe RGB, ProPhoto...). Other profiles, e.g. CMYK
+// or LUT based ones, are ignored and the pixels hashed as they are.
+use image::{DynamicImage, GenericImageView, RgbImage, RgbaImage};
+use log::debug;
+use std::io::Read;
+
+// XYZ (D50, the ICC profile connection space) to linear sRGB, Bradford adapted.
+const XYZ_D50_TO_SRGB: [[f32; 3]; 3] = [
+    [3.133_856, -1.616_867, -0.490_614_6],
+    [-0.978_768_4, 1.916_141_5, 0.033_454],
+    [0.071_945_3, -0.228_991_4, 1.405_242_7],
+];
+
+// Resolution of the table used to encode linear values back to sRGB.
+const ENCODE_STEPS: usize = 4096;
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_s15_fixed16(data: &[u8], offset: usize) -> Option<f32> {
+    Some(read_u32(data, offset)? as i32 as f32 / 65536.0)
+}
+
+// The profile embedded in a JPEG, PNG, TIFF or WebP file, if any.
+pub fn extract(buffer: &[u8]) -> Option<Vec<u8>> {
+    match image::guess_format(buffer).ok()? {
+        image::ImageFormat::Jpeg => extract_jpeg(buffer),
+        image::ImageFormat::Png => extract_png(buffer),
+        image::ImageFormat::Tiff => extract_tiff(buffer),
+        image::ImageFormat::WebP => crate::formats::riff_chunks(buffer.get(12..)?)
+            .find(|(id, _)| *id == b"ICCP")
+            .map(|(_, profile)| profile.to_vec()),
+        _ => None,
+    }
+}
+
+// Profiles larger than a segment are split over several APP2 segments, numbered from 1.
+fn extract_jpeg(jpeg: &[u8]) -> Option<Vec<u8>> {
+    let mut chunks = Vec::new();
+    let mut pos = 2;
+    loop {
+        if jpeg.get(pos)? != &0xFF {
+            break;
+        }
+        let marker = *jpeg.get(pos + 1)?;
+        // Start of scan: no more metadata before the image data.
+        if marker == 0xDA {
+            break;
+        }
+        let len = read_u16(jpeg, pos + 2)? as usize;
+        let segment = jpeg.get(pos + 4..pos + 2 + len)?;
+        if marker == 0xE2 && segment.starts_with(b"ICC_PROFILE\0") && segment.len() > 14 {
+            chunks.push((segment[12], &segment[14..]));
+        }
+        pos += 2 + len;
+    }
+    if chunks.is_empty() {
+        return None;
+    }
+    chunks.sort_by_key(|(seq, _)| *seq);
+    Some(
+        chunks
+            .into_iter()
+            .flat_map(|(_, chunk)| chunk)
+            .copied()
+            .collect(),
+    )
+}
+
+// In the `iCCP` chunk: the profile name, a null byte, the compression method and the zlib data.
+fn extract_png(png: &[u8]) -> Option<Vec<u8>> {
+    let mut pos = 8;
+    while let Some(len) = read_u32(png, pos) {
+        let kind = png.get(pos + 4..pos + 8)?;
+        if kind == b"IDAT" {
+            break;
+        }
+        if kind == b"iCCP" {
+            let data = png.get(pos + 8..pos + 8 + len as usize)?;
+            let name_end = data.iter().position(|&byte| byte == 0)?;
+            let mut profile = Vec::new();
+            flate2::read::ZlibDecoder::new(data.get(name_end + 2..)?)
+                .read_to_end(&mut profile)
+                .ok()?;
+            return Some(profile);
+        }
+        pos += 12 + len as usize;
+    }
+    None
+}
+
+// The `InterColorProfile` tag of the first IFD, where RAW converters put the profile of their
+// exports.
+fn extract_tiff(tiff: &[u8]) -> Option<Vec<u8>> {
+    let little_endian = match tiff.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 4)?.try_into().ok()?;
+        Some(if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+    let ifd = u32_at(4)? as usize;
+    (0..u16_at(ifd)? as usize).find_map(|i| {
+        let entry = ifd + 2 + i * 12;
+        if u16_at(entry)? != 0x8773 {
+            return None;
+        }
+        let len = u32_at(entry + 4)? as usize;
+        let offset = u32_at(entry + 8)? as usize;
+        tiff.get(offset..offset + len).map(<[u8]>::to_vec)
+    })
+}
+
+// Tone response curve, from encoded to linear values.
+enum Curve {
+    Gamma(f32),
+    Table(Vec<f32>),
+    // ICC parametric curve: function type and parameters g, a, b, c, d, e, f.
+    Parametric(u16, [f32; 7]),
+}
+
+impl Curve {
+    fn parse(data: &[u8]) -> Option<Curve> {
+        match data.get(..4)? {
+            b"curv" => {
+                let count = read_u32(data, 8)? as usize;
+                match count {
+                    0 => Some(Curve::Gamma(1.0)),
+                    1 => Some(Curve::Gamma(read_u16(data, 12)? as f32 / 256.0)),
+                    _ => (0..count)
+                        .map(|i| Some(read_u16(data, 12 + i * 2)? as f32 / 65535.0))
+                        .collect::<Option<_>>()
+                        .map(Curve::Table),
+                }
+            }
+            b"para" => {
+                let kind = read_u16(data, 8)?;
+                let count = [1, 3, 4, 5, 7].get(kind as usize)?;
+                let mut params = [0.0; 7];
+                for (i, param) in params.iter_mut().take(*count).enumerate() {
+                    *param = read_s15_fixed16(data, 12 + i * 4)?;
+                }
+                Some(Curve::Parametric(kind, params))
+            }
+            _ => None,
+        }
+    }
+
+    fn eval(&self, x: f32) -> f32 {
+        match self {
+            Curve::Gamma(gamma) => x.powf(*gamma),
+            Curve::Table(table) => {
+                let pos = x * (table.len() - 1) as f32;
+                let i = (pos as usize).min(table.len() - 2);
+                let t = pos - i as f32;
+                table[i] * (1.0 - t) + table[i + 1] * t
+            }
+            &Curve::Parametric(kind, [g, a, b, c, d, e, f]) => match kind {
+                0 => x.powf(g),
+                1 if x >= -b / a => (a * x + b).powf(g),
+                1 => 0.0,
+                2 if x >= -b / a => (a * x + b).powf(g) + c,
+                2 => c,
+                3 if x >= d => (a * x + b).powf(g),
+                3 => c * x,
+                _ if x >= d => (a * x + b).powf(g) + e,
+                _ => c * x + f,
+            },
+        }
+    }
+
+    // Linear values of the encoded values from 0 to `max`.
+    fn table(&self, max: u32) -> Vec<f32> {
+        (0..=max)
+            .map(|i| self.eval(i as f32 / max as f32).clamp(0.0, 1.0))
+            .collect()
+    }
+}
+
+fn srgb_decode(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn srgb_encode(x: f32) -> f32 {
+    if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Everything needed to convert pixels to sRGB.
+pub struct Transform {
+    curves: [Curve; 3],
+    // Linear RGB to linear sRGB.
+    matrix: [[f32; 3]; 3],
+}
+
+impl Transform {
+    // `None` for unsupported profiles, and for sRGB-like ones, which need no conversion.
+    pub fn new(profile: &[u8]) -> Option<Transform> {
+        if profile.get(16..20)? != b"RGB " {
+            return None;
+        }
+        let tag = |signature: &[u8]| {
+            let count = read_u32(profile, 128)? as usize;
+            (0..count).find_map(|i| {
+                let entry = 132 + i * 12;
+                if profile.get(entry..entry + 4)? != signature {
+                    return None;
+                }
+                let offset = read_u32(profile, entry + 4)? as usize;
+                let len = read_u32(profile, entry + 8)? as usize;
+                profile.get(offset..offset + len)
+            })
+        };
+        let xyz = |signature: &[u8]| -> Option<[f32; 3]> {
+            let data = tag(signature).filter(|data| data.starts_with(b"XYZ "))?;
+            Some([
+                read_s15_fixed16(data, 8)?,
+                read_s15_fixed16(data, 12)?,
+                read_s15_fixed16(data, 16)?,
+            ])
+        };
+        let columns = [xyz(b"rXYZ")?, xyz(b"gXYZ")?, xyz(b"bXYZ")?];
+        let curves = [
+            Curve::parse(tag(b"rTRC")?)?,
+            Curve::parse(tag(b"gTRC")?)?,
+            Curve::parse(tag(b"bTRC")?)?,
+        ];
+        let matrix: [[f32; 3]; 3] = std::array::from_fn(|row| {
+            std::array::from_fn(|col| {
+                (0..3)
+                    .map(|k| XYZ_D50_TO_SRGB[row][k] * columns[col][k])
+                    .sum()
+            })
+        });
+
+        let is_identity = matrix.iter().enumerate().all(|(row, values)| {
+            values
+                .iter()
+                .enumerate()
+                .all(|(col, value)| (value - if row == col { 1.0 } else { 0.0 }).abs() < 0.01)
+        });
+        let is_srgb_curve = curves.iter().all(|curve| {
+            curve
+                .table(u8::MAX as u32)
+                .iter()
+                .enumerate()
+                .all(|(i, value)| (value - srgb_decode(i as f32 / 255.0)).abs() < 0.5 / 255.0)
+        });
+        if is_identity && is_srgb_curve {
+            return None;
+        }
+        Some(Transform { curves, matrix })
+    }
+
+    // Converts interleaved samples ranging up to `max` to 8 bit sRGB. 16 bit images are converted
+    // from their full precision, which matters for the dark tones of linear masters.
+    fn apply<T: Copy + Into<u32>>(&self, pixels: &[T], channels: usize, max: u32) -> Vec<u8> {
+        let tables = self.curves.each_ref().map(|curve| curve.table(max));
+        let encode: Vec<u8> = (0..=ENCODE_STEPS)
+            .map(|i| (srgb_encode(i as f32 / ENCODE_STEPS as f32) * 255.0).round() as u8)
+            .collect();
+        let mut out = Vec::with_capacity(pixels.len());
+        for pixel in pixels.chunks_exact(channels) {
+            let linear = [0, 1, 2].map(|c| tables[c][pixel[c].into() as usize]);
+            for row in &self.matrix {
+                let value: f32 = row.iter().zip(linear).map(|(m, x)| m * x).sum();
+                out.push(encode[(value.clamp(0.0, 1.0) * ENCODE_STEPS as f32) as usize]);
+            }
+            if let Some(&alpha) = pixel.get(3) {
+                out.push(((alpha.into() * 255 + max / 2) / max) as u8);
+            }
+        }
+        out
+    }
+}
+
+// Converts `image`, decoded from `buffer`, to 8 bit sRGB according to the profile embedded in
+// `buffer`. Grayscale images are left alone.
+pub fn to_srgb(image: DynamicImage, buffer: &[u8]) -> DynamicImage {
+    if !image.color().has_color() {
+        return image;
+    }
+    let Some(transform) = extract(buffer).and_then(|profile| Transform::new(&profile)) else {
+        return image;
+    };
+    debug!("Converting an image with an ICC profile to sRGB");
+    let (width, height) = image.dimensions();
+    let converted = match &image {
+        DynamicImage::ImageRgb16(rgb) => transform.apply(rgb, 3, u16::MAX as u32),
+        DynamicImage::ImageRgba16(rgba) => transform.apply(rgba, 4, u16::MAX as u32),
+        _ if image.color().has_alpha() => transform.apply(&image.to_rgba8(), 4, u8::MAX as u32),
+        _ => transform.apply(&image.to_rgb8(), 3, u8::MAX as u32),
+    };
+    if image.color().has_alpha() {
+        DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, converted).unwrap())
+    } else {
+        DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, converted).unwrap())
+    }
+}
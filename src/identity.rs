@@ -0,0 +1,176 @@
+// Pairs at a distance of (almost) zero may be the very same picture. They are checked one at a time
+// on a background thread, so that it does not compete with the scan: first byte for byte, which
+// is safe enough to act on without review, then by decoding both, which tells apart the files
+// that only differ in their metadata, e.g. after editing EXIF dates or XMP keywords, from
+// re-encoded copies. Copies which should be the same file but are not, with a few bytes changed in
+// place or one failing to decode, are flagged as possibly corrupted rather than as duplicates.
+use crate::links::Link;
+use crate::Message;
+use eframe::egui;
+use image::{DynamicImage, GenericImageView, ImageResult};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::mpsc::{Receiver, Sender};
+
+const BLOCK_SIZE: usize = 1024 * 1024;
+
+// Hashes this close are worth decoding both images.
+pub const MAX_DISTANCE: u32 = 2;
+
+// Files of the same size differing in at most this share of their bytes had bits flipped, e.g. by
+// a failing disk, rather than being saved again.
+const MAX_FLIPPED_RATIO: f64 = 0.001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Identity {
+    Checking,
+    Different,
+    // The files differ but not their pixels: only metadata is lost when removing one.
+    SamePixels,
+    // Byte for byte, safe to act on without review.
+    SameFile,
+    // Already sharing their data, removing one frees no space.
+    Linked(Link),
+    // Same size with a few bytes changed, or only one of them decodes: one copy may be damaged.
+    Corrupted { a_decodes: bool, b_decodes: bool },
+}
+
+// What happens to the pairs verified identical byte for byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VerifiedAction {
+    Review,
+    // The copy whose path sorts last becomes a hardlink to the other.
+    Hardlink,
+    // The copy whose path sorts last is trashed.
+    Trash,
+    // The copy whose path sorts last becomes a clone of the other, see `ReplaceMode::Clone`.
+    Clone,
+}
+
+impl VerifiedAction {
+    pub const ALL: [VerifiedAction; 4] = [
+        VerifiedAction::Review,
+        VerifiedAction::Hardlink,
+        VerifiedAction::Trash,
+        VerifiedAction::Clone,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            VerifiedAction::Review => "review",
+            VerifiedAction::Hardlink => "hardlink",
+            VerifiedAction::Trash => "trash",
+            VerifiedAction::Clone => "clone",
+        }
+    }
+}
+
+impl std::str::FromStr for VerifiedAction {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        VerifiedAction::ALL
+            .into_iter()
+            .find(|action| action.name() == s)
+            .ok_or(())
+    }
+}
+
+pub struct Check {
+    // Scan generation, see `MatchCommand::Reset`.
+    pub generation: u64,
+    pub pair: (usize, usize),
+    pub paths: (String, String),
+}
+
+// Streamed, so that big files differing early are not read whole.
+fn same_bytes(a: &Path, b: &Path) -> std::io::Result<bool> {
+    let (a, b) = (std::fs::File::open(a)?, std::fs::File::open(b)?);
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+    let mut a = BufReader::with_capacity(BLOCK_SIZE, a);
+    let mut b = BufReader::with_capacity(BLOCK_SIZE, b);
+    loop {
+        let (a_block, b_block) = (a.fill_buf()?, b.fill_buf()?);
+        if a_block.is_empty() || b_block.is_empty() {
+            return Ok(a_block.is_empty() && b_block.is_empty());
+        }
+        let len = a_block.len().min(b_block.len());
+        if a_block[..len] != b_block[..len] {
+            return Ok(false);
+        }
+        a.consume(len);
+        b.consume(len);
+    }
+}
+
+fn same_pixels(a: &DynamicImage, b: &DynamicImage) -> bool {
+    a.dimensions() == b.dimensions()
+        && if a.color() == b.color() {
+            a.as_bytes() == b.as_bytes()
+        } else {
+            // E.g. an opaque RGBA PNG and its RGB copy.
+            a.to_rgba16() == b.to_rgba16()
+        }
+}
+
+fn bits_flipped(a: &[u8], b: &[u8]) -> bool {
+    let differing = a.iter().zip(b).filter(|(a, b)| a != b).count();
+    a.len() == b.len() && differing as f64 <= a.len() as f64 * MAX_FLIPPED_RATIO
+}
+
+fn check(a: &str, b: &str) -> ImageResult<Identity> {
+    if let Some(link) = crate::links::link(Path::new(a), Path::new(b)) {
+        return Ok(Identity::Linked(link));
+    }
+
+    if same_bytes(Path::new(a), Path::new(b)).map_err(image::ImageError::IoError)? {
+        return Ok(Identity::SameFile);
+    }
+    let read = |path: &str| std::fs::read(path).map_err(image::ImageError::IoError);
+    let (a_buffer, b_buffer) = (read(a)?, read(b)?);
+    let a = crate::formats::load(Path::new(a), &a_buffer);
+    let b = crate::formats::load(Path::new(b), &b_buffer);
+    Ok(match (a, b) {
+        (Ok(a), Ok(b)) if same_pixels(&a, &b) => Identity::SamePixels,
+        (Ok(_), Ok(_)) if bits_flipped(&a_buffer, &b_buffer) => Identity::Corrupted {
+            a_decodes: true,
+            b_decodes: true,
+        },
+        (Ok(_), Ok(_)) => Identity::Different,
+        (Err(err), Err(_)) => return Err(err),
+        (a, b) => Identity::Corrupted {
+            a_decodes: a.is_ok(),
+            b_decodes: b.is_ok(),
+        },
+    })
+}
+
+pub fn spawn(sender: Sender<Message>, ctx: egui::Context) -> Sender<Check> {
+    let (check_sender, check_receiver) = std::sync::mpsc::channel();
+    std::thread::Builder::new()
+        .name("verifier".to_owned())
+        .spawn(move || run(check_receiver, sender, ctx))
+        .unwrap();
+    check_sender
+}
+
+fn run(checks: Receiver<Check>, sender: Sender<Message>, ctx: egui::Context) {
+    for Check {
+        generation,
+        pair: (i, j),
+        paths: (a, b),
+    } in checks
+    {
+        let identity = check(&a, &b).unwrap_or_else(|err| {
+            debug!("Failed to compare {} and {}: {}", a, b, err);
+            Identity::Different
+        });
+        let _ = sender.send(Message::IdentityChecked(generation, i, j, identity));
+        ctx.request_repaint();
+    }
+}
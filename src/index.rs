@@ -0,0 +1,137 @@
+use crate::cache::{self, CacheEntry, HashCache, HashCacheMode};
+use crate::settings::ScanOptions;
+use log::{error, info};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::sync::Mutex;
+
+const INDEX_FORMAT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub path: String,
+    #[serde(flatten)]
+    pub entry: CacheEntry,
+}
+
+// Portable dump of the central hash cache. Paths are kept as they were on the machine that did
+// the hashing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Index {
+    pub version: u32,
+    pub hash_size: u32,
+    pub entries: Vec<IndexEntry>,
+}
+
+impl Index {
+    pub fn read(path: &Path) -> Result<Index, String> {
+        let content = std::fs::read(path).map_err(|err| err.to_string())?;
+        let index: Index = serde_json::from_slice(&content).map_err(|err| err.to_string())?;
+        if index.version != INDEX_FORMAT_VERSION || index.hash_size != crate::HASH_SIZE {
+            return Err(format!(
+                "Unsupported index (version {}, hash size {})",
+                index.version, index.hash_size
+            ));
+        }
+        Ok(index)
+    }
+}
+
+pub fn export(file: &Path) -> Result<usize, String> {
+    let cache = HashCache::load(HashCacheMode::Central);
+    let mut entries: Vec<IndexEntry> = cache
+        .entries()
+        .map(|(path, entry)| IndexEntry {
+            path: path.clone(),
+            entry: entry.clone(),
+        })
+        .collect();
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let count = entries.len();
+
+    let index = Index {
+        version: INDEX_FORMAT_VERSION,
+        hash_size: crate::HASH_SIZE,
+        entries,
+    };
+    let content = serde_json::to_vec_pretty(&index).map_err(|err| err.to_string())?;
+    std::fs::write(file, content).map_err(|err| err.to_string())?;
+    Ok(count)
+}
+
+// Merged into the central cache, entries from the file win over existing ones.
+pub fn import(file: &Path) -> Result<usize, String> {
+    let index = Index::read(file)?;
+    let count = index.entries.len();
+    let mut cache = HashCache::load(HashCacheMode::Central);
+    for IndexEntry { path, entry } in index.entries {
+        cache.insert(Path::new(&path), entry);
+    }
+    cache.save();
+    Ok(count)
+}
+
+// Headless scan filling the central cache, e.g. to hash a NAS on the NAS itself and export the
+// result.
+pub fn build(root: &Path, options: &ScanOptions) -> usize {
+    // The central cache is keyed by absolute path.
+    let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+    let excluded = crate::exclude::Patterns::new(&options.exclude);
+    let files: Vec<_> = options
+        .walk(&root)
+        .into_iter()
+        .filter_entry(|entry| !excluded.excludes(&root, entry.path()))
+        .filter_map(|e| e.ok())
+        .filter(crate::is_known_image)
+        .collect();
+    info!("Found {} images in {}", files.len(), root.display());
+
+    let cache = Mutex::new(HashCache::load(HashCacheMode::Central));
+    files.par_iter().for_each(|entry| {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            return;
+        };
+        if !options.size_in_range(metadata.len())
+            || cache
+                .lock()
+                .unwrap()
+                .get(path, &metadata, options.hash_alg, options.color_management)
+                .is_some()
+            || image::image_dimensions(path)
+                .is_ok_and(|dimensions| crate::too_small(dimensions, options))
+        {
+            return;
+        }
+
+        match std::fs::read(path)
+            .map_err(image::ImageError::IoError)
+            .and_then(|buffer| {
+                crate::decode_for_hashing(path, &buffer, options.hash_alg, options.color_management)
+            }) {
+            Ok((image, dimensions, frames)) => {
+                let hash = crate::hash_image(&image, options.hash_alg);
+                info!("Hashed {}", path.display());
+                cache.lock().unwrap().insert(
+                    path,
+                    CacheEntry {
+                        size: metadata.len(),
+                        mtime: cache::mtime_millis(&metadata),
+                        hash_alg: options.hash_alg,
+                        hash: hash.to_base64(),
+                        dimensions,
+                        frames,
+                        color_management: options.color_management,
+                        decoder: cache::DECODER_VERSION,
+                    },
+                );
+            }
+            Err(err) => error!("Failed to decode image {:?}: {}", path, err),
+        }
+    });
+
+    let mut cache = cache.into_inner().unwrap();
+    cache.save();
+    files.len()
+}
@@ -0,0 +1,224 @@
+use crate::paths;
+use crate::quarantine;
+use crate::settings::HashAlgorithm;
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const JOURNAL_FILE_NAME: &str = "journal.jsonl";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalAction {
+    Trash,
+    // Deleted for good, there is nothing to restore.
+    Delete,
+    Restore,
+    Hardlink,
+    Symlink,
+    Move,
+    Clone,
+    // The EXIF orientation was changed, see `exif::write_orientation`.
+    Orient,
+}
+
+// One line of the journal: what happened to which file, with enough of the image to put it
+// back into the results when it is restored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    // Seconds since the Unix epoch.
+    pub time: u64,
+    pub action: JournalAction,
+    pub path: String,
+    pub hash_alg: HashAlgorithm,
+    // Base64, as produced by `ImageHash::to_base64`.
+    pub hash: String,
+    pub dimensions: (u32, u32),
+    // The image it was a duplicate of, for trashed and replaced files.
+    pub kept: Option<String>,
+    // Companion files trashed along with it, see `companions.rs`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub companions: Vec<String>,
+    // Where it was moved instead of the trash, see `quarantine.rs`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moved_to: Option<String>,
+    // Companion files of the kept image renamed along with it by a move, as (from, to).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub renamed: Vec<(String, String)>,
+    // The name a replaced file was trashed under, see `replace::backup`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup: Option<String>,
+    // The EXIF orientation before and after, for reoriented files.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<(u16, u16)>,
+}
+
+impl JournalEntry {
+    pub fn new(
+        action: JournalAction,
+        path: &str,
+        hash_alg: HashAlgorithm,
+        hash: String,
+        dimensions: (u32, u32),
+        kept: Option<&str>,
+    ) -> Self {
+        JournalEntry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs()),
+            action,
+            path: path.to_owned(),
+            hash_alg,
+            hash,
+            dimensions,
+            kept: kept.map(str::to_owned),
+            companions: Vec::new(),
+            moved_to: None,
+            renamed: Vec::new(),
+            backup: None,
+            orientation: None,
+        }
+    }
+}
+
+fn journal_path() -> Option<PathBuf> {
+    paths::data_dir().map(|dir| dir.join(JOURNAL_FILE_NAME))
+}
+
+// Append only, one JSON object per line, so that it is never rewritten and stays readable with
+// standard tools.
+pub fn append(entry: &JournalEntry) {
+    let Some(path) = journal_path() else {
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let result = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| {
+            let line = serde_json::to_string(entry).map_err(std::io::Error::other)?;
+            writeln!(file, "{}", line)
+        });
+    if let Err(err) = result {
+        error!("Failed to write to the journal {}: {}", path.display(), err);
+    }
+}
+
+pub fn read() -> Vec<JournalEntry> {
+    let Some(content) = journal_path().and_then(|path| std::fs::read_to_string(path).ok()) else {
+        return Vec::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(err) => {
+                error!("Ignoring invalid journal line: {}", err);
+                None
+            }
+        })
+        .collect()
+}
+
+// Files trashed by this app and not restored since, most recent first.
+pub fn trashed() -> Vec<JournalEntry> {
+    let mut latest: HashMap<String, JournalEntry> = HashMap::new();
+    for entry in read() {
+        latest.insert(entry.path.clone(), entry);
+    }
+    let mut trashed: Vec<JournalEntry> = latest
+        .into_values()
+        .filter(|entry| entry.action == JournalAction::Trash)
+        .collect();
+    trashed.sort_by(|a, b| b.time.cmp(&a.time).then_with(|| a.path.cmp(&b.path)));
+    trashed
+}
+
+// Puts the files back where they were, from the trash or the quarantine folder.
+pub fn restore(entries: &[JournalEntry]) -> Result<(), String> {
+    let (moved, trashed): (Vec<JournalEntry>, Vec<JournalEntry>) = entries
+        .iter()
+        .cloned()
+        .partition(|entry| entry.moved_to.is_some());
+    if !trashed.is_empty() {
+        restore_from_trash(&trashed)?;
+    }
+    for entry in &moved {
+        let moved_to = entry.moved_to.as_deref().unwrap_or_default();
+        quarantine::move_file(Path::new(moved_to), Path::new(&entry.path))
+            .map_err(|err| format!("Failed to restore {}: {}", entry.path, err))?;
+        // Missing companions do not prevent the image from coming back.
+        for companion in &entry.companions {
+            let from = quarantine::companion_destination(moved_to, companion);
+            if let Err(err) = quarantine::move_file(&from, Path::new(companion)) {
+                error!("Failed to restore {}: {}", companion, err);
+            }
+        }
+    }
+    for entry in entries {
+        append(&JournalEntry::new(
+            JournalAction::Restore,
+            &entry.path,
+            entry.hash_alg,
+            entry.hash.clone(),
+            entry.dimensions,
+            None,
+        ));
+    }
+    Ok(())
+}
+
+// Only Windows and freedesktop systems let applications browse the trash.
+#[cfg(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+))]
+fn restore_from_trash(entries: &[JournalEntry]) -> Result<(), String> {
+    let items = trash::os_limited::list().map_err(|err| err.to_string())?;
+    // The same path may have been trashed several times, the latest one is ours.
+    let latest = |path: &str| {
+        items
+            .iter()
+            .filter(|item| item.original_path().to_string_lossy() == path)
+            .max_by_key(|item| item.time_deleted)
+    };
+    let mut to_restore = Vec::new();
+    for entry in entries {
+        let item = latest(&entry.path)
+            .ok_or_else(|| format!("{} is no longer in the trash", entry.path))?;
+        to_restore.push(item.clone());
+        // Missing companions do not prevent the image from coming back.
+        to_restore.extend(
+            entry
+                .companions
+                .iter()
+                .filter_map(|path| latest(path))
+                .cloned(),
+        );
+    }
+    trash::os_limited::restore_all(to_restore).map_err(|err| err.to_string())
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )
+)))]
+fn restore_from_trash(_entries: &[JournalEntry]) -> Result<(), String> {
+    Err("Restoring from the trash is not supported on this platform".to_owned())
+}
@@ -0,0 +1,132 @@
+// Geometry of the main window and of the inner windows, saved on exit and restored on launch. It is
+// kept apart from the settings, which are only saved on demand, since it changes on every run.
+use crate::paths;
+use eframe::egui;
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+const LAYOUT_FILE_NAME: &str = "layout.toml";
+
+// eframe does not tell whether the window is maximized, one this close to the size of the monitor
+// is taken as such.
+const MAXIMIZED_RATIO: f32 = 0.9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn pos(self) -> egui::Pos2 {
+        egui::pos2(self.x, self.y)
+    }
+
+    pub fn size(self) -> egui::Vec2 {
+        egui::vec2(self.width, self.height)
+    }
+
+    // Inner windows are placed by their outer corner but sized by their content, without the title
+    // bar.
+    pub fn of_window(outer: egui::Rect, content: egui::Rect) -> Self {
+        Rect {
+            x: outer.min.x,
+            y: outer.min.y,
+            width: content.width(),
+            height: content.height(),
+        }
+    }
+}
+
+pub fn place<'open>(window: egui::Window<'open>, rect: Option<Rect>) -> egui::Window<'open> {
+    match rect {
+        Some(rect) => window.default_pos(rect.pos()).default_size(rect.size()),
+        None => window,
+    }
+}
+
+// In points.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Layout {
+    pub maximized: bool,
+    // Of the window when not maximized, so that it is restored to it.
+    pub window: Option<Rect>,
+    // Width of the results list, next to the selected pair.
+    pub results_split: f32,
+    pub inspector: Option<Rect>,
+    pub disk_usage: Option<Rect>,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            maximized: true,
+            window: None,
+            results_split: 350.0,
+            inspector: None,
+            disk_usage: None,
+        }
+    }
+}
+
+impl Layout {
+    pub fn load() -> Self {
+        let Some(path) = paths::config_dir().map(|dir| dir.join(LAYOUT_FILE_NAME)) else {
+            return Layout::default();
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(content) => match toml::from_str(&content) {
+                Ok(layout) => {
+                    info!("Loaded the window layout from {}", path.display());
+                    layout
+                }
+                Err(err) => {
+                    error!(
+                        "Failed to parse the window layout {}: {}",
+                        path.display(),
+                        err
+                    );
+                    Layout::default()
+                }
+            },
+            Err(_) => Layout::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let dir = paths::config_dir().ok_or("No configuration directory on this platform")?;
+        std::fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+        let content = toml::Value::try_from(self)
+            .and_then(|value| toml::to_string_pretty(&value))
+            .map_err(|err| err.to_string())?;
+        std::fs::write(dir.join(LAYOUT_FILE_NAME), content).map_err(|err| err.to_string())
+    }
+
+    pub fn apply(&self, options: &mut eframe::NativeOptions) {
+        options.maximized = self.maximized;
+        if let Some(window) = self.window {
+            options.initial_window_pos = Some(window.pos());
+            options.initial_window_size = Some(window.size());
+        }
+    }
+
+    pub fn update_window(&mut self, info: &eframe::WindowInfo) {
+        if info.fullscreen {
+            return;
+        }
+        self.maximized = info.monitor_size.is_some_and(|monitor| {
+            info.size.x >= monitor.x * MAXIMIZED_RATIO && info.size.y >= monitor.y * MAXIMIZED_RATIO
+        });
+        if let (false, Some(position)) = (self.maximized, info.position) {
+            self.window = Some(Rect {
+                x: position.x,
+                y: position.y,
+                width: info.size.x,
+                height: info.size.y,
+            });
+        }
+    }
+}
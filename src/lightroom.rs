@@ -0,0 +1,77 @@
+// Lightroom Classic catalogs (`.lrcat`) are SQLite databases. Deleting a file Lightroom knows about
+// leaves a "missing photo" behind, along with its edits, so such files are only trashed after
+// confirmation, and their list can be saved to clean up the catalog afterwards.
+use log::info;
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::Command;
+
+// Paths are split between the root folders added to the catalog, the folders below them (with a
+// trailing slash) and the files. A RAW shot with a JPEG is a single file whose sidecar extensions
+// list `JPG`.
+const QUERY: &str = "SELECT root.absolutePath || folder.pathFromRoot || file.baseName, \
+                     file.extension, file.sidecarExtensions \
+                     FROM AgLibraryFile file \
+                     JOIN AgLibraryFolder folder ON file.folder = folder.id_local \
+                     JOIN AgLibraryRootFolder root ON folder.rootFolder = root.id_local";
+
+// Lightroom stores forward slashes on every platform.
+#[cfg(windows)]
+fn native(path: String) -> String {
+    path.replace('/', "\\")
+}
+
+#[cfg(not(windows))]
+fn native(path: String) -> String {
+    path
+}
+
+// Catalog and scanned paths are compared resolved, e.g. through a symlinked folder. Missing files,
+// e.g. trashed ones, are resolved through their folder.
+pub fn canonical(path: &str) -> String {
+    let path = Path::new(path);
+    let resolved =
+        std::fs::canonicalize(path).or_else(|err| match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) => std::fs::canonicalize(dir).map(|dir| dir.join(name)),
+            _ => Err(err),
+        });
+    resolved
+        .as_deref()
+        .unwrap_or(path)
+        .to_string_lossy()
+        .to_string()
+}
+
+// The files referenced by the catalog, read with the `sqlite3` command line tool. Read-only, so it
+// works while Lightroom has the catalog open.
+pub fn referenced_files(catalog: &Path) -> Result<HashSet<String>, String> {
+    let output = Command::new("sqlite3")
+        .args(["-readonly", "-noheader", "-separator", "\t"])
+        .arg(catalog)
+        .arg(QUERY)
+        .output()
+        .map_err(|err| format!("Failed to run sqlite3, is it installed? {}", err))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+    let mut files = HashSet::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut columns = line.split('\t');
+        let (Some(base), Some(extension)) = (columns.next(), columns.next()) else {
+            continue;
+        };
+        let sidecars = columns.next().unwrap_or_default();
+        for extension in std::iter::once(extension).chain(sidecars.split(',')) {
+            let extension = extension.trim();
+            if !extension.is_empty() {
+                files.insert(canonical(&native(format!("{}.{}", base, extension))));
+            }
+        }
+    }
+    info!(
+        "Loaded {} referenced files from the catalog {}",
+        files.len(),
+        catalog.display()
+    );
+    Ok(files)
+}
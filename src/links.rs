@@ -0,0 +1,128 @@
+// Files which already share their data, so that removing one frees no space: hardlinks of the same
+// inode, and clones sharing all their extents (reflinks, see `ReplaceMode::Clone`). Other tools
+// report them as duplicates.
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Link {
+    Hardlink,
+    Clone,
+}
+
+impl Link {
+    pub fn name(self) -> &'static str {
+        match self {
+            Link::Hardlink => "hardlinks",
+            Link::Clone => "clones",
+        }
+    }
+}
+
+#[cfg(unix)]
+fn same_inode(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_inode(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+#[cfg(target_os = "linux")]
+mod fiemap {
+    use std::os::unix::io::AsRawFd;
+    use std::path::Path;
+
+    // Files with more extents than this are not compared.
+    const MAX_EXTENTS: usize = 64;
+    // `_IOWR('f', 11, struct fiemap)` from `linux/fs.h`.
+    const FS_IOC_FIEMAP: u32 = 0xC020_660B;
+    const FIEMAP_FLAG_SYNC: u32 = 0x1;
+    const FIEMAP_EXTENT_LAST: u32 = 0x1;
+    const FIEMAP_EXTENT_SHARED: u32 = 0x2000;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct Extent {
+        logical: u64,
+        physical: u64,
+        length: u64,
+        reserved64: [u64; 2],
+        flags: u32,
+        reserved: [u32; 3],
+    }
+
+    #[repr(C)]
+    struct Fiemap {
+        start: u64,
+        length: u64,
+        flags: u32,
+        mapped_extents: u32,
+        extent_count: u32,
+        reserved: u32,
+        extents: [Extent; MAX_EXTENTS],
+    }
+
+    // (logical offset, physical offset, length, flags) of every extent, `None` when unknown.
+    fn extents(path: &Path) -> Option<Vec<(u64, u64, u64, u32)>> {
+        let file = std::fs::File::open(path).ok()?;
+        let mut map = Fiemap {
+            start: 0,
+            length: u64::MAX,
+            flags: FIEMAP_FLAG_SYNC,
+            mapped_extents: 0,
+            extent_count: MAX_EXTENTS as u32,
+            reserved: 0,
+            extents: [Extent::default(); MAX_EXTENTS],
+        };
+        if unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut map) } == -1 {
+            return None;
+        }
+        let extents = &map.extents[..map.mapped_extents as usize];
+        // The file has more extents than fetched.
+        if extents.last()?.flags & FIEMAP_EXTENT_LAST == 0 {
+            return None;
+        }
+        Some(
+            extents
+                .iter()
+                .map(|extent| (extent.logical, extent.physical, extent.length, extent.flags))
+                .collect(),
+        )
+    }
+
+    pub fn share_extents(a: &Path, b: &Path) -> bool {
+        let (Some(a), Some(b)) = (extents(a), extents(b)) else {
+            return false;
+        };
+        a.len() == b.len()
+            && a.iter()
+                .zip(&b)
+                .all(|(a, b)| a.3 & FIEMAP_EXTENT_SHARED != 0 && (a.0, a.1, a.2) == (b.0, b.1, b.2))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn share_extents(a: &Path, b: &Path) -> bool {
+    fiemap::share_extents(a, b)
+}
+
+// APFS does not tell which files a clone shares its data with.
+#[cfg(not(target_os = "linux"))]
+fn share_extents(_a: &Path, _b: &Path) -> bool {
+    false
+}
+
+pub fn link(a: &Path, b: &Path) -> Option<Link> {
+    if same_inode(a, b) {
+        Some(Link::Hardlink)
+    } else if share_extents(a, b) {
+        Some(Link::Clone)
+    } else {
+        None
+    }
+}
@@ -3,39 +3,231 @@ use clipboard::ClipboardProvider;
 use egui::Button;
 use egui::Slider;
 use egui::Vec2;
-use egui::{Color32, Widget};
+use egui::{Color32, Widget, WidgetInfo, WidgetType};
 use image::error::{LimitError, LimitErrorKind};
-use image::ImageError;
+use image::{GenericImageView, ImageDecoder, ImageError};
 use img_hash::HasherConfig;
 use log::{debug, error, info};
-use std::path::PathBuf;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::TryRecvError;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use ubyte::{ByteUnit, ToByteUnit};
 use walkdir::DirEntry;
-use walkdir::WalkDir;
 
 use eframe::egui;
 
-const KNOWN_EXTENSIONS: [&str; 12] = [
-    "png", "jpg", "jpeg", "gif", "bmp", "ico", "tiff", "webp", "avif", "pnm", "dds", "tga",
+mod bands;
+mod bursts;
+mod cache;
+mod cli;
+mod companions;
+mod confidence;
+mod dcim;
+mod diff;
+mod exact;
+mod exclude;
+mod exif;
+mod export;
+mod formats;
+mod gestures;
+mod gphotos;
+mod histogram;
+mod history;
+mod hooks;
+mod icc;
+mod identity;
+mod index;
+mod journal;
+mod layout;
+mod lightroom;
+mod links;
+mod manifest;
+mod map;
+mod matcher;
+mod messaging;
+mod netcache;
+mod palette;
+mod paths;
+mod phash;
+mod quarantine;
+mod queue;
+mod replace;
+mod rules;
+mod s3;
+mod search;
+mod sequence;
+mod session;
+mod settings;
+mod snapshot;
+mod sort;
+mod ssh;
+mod storage;
+mod thumbnail;
+mod treemap;
+mod update;
+mod volumes;
+
+use cache::{CacheEntry, HashCache, HashCacheMode};
+use clap::Parser;
+use cli::Cli;
+use companions::RawJpegPolicy;
+use hooks::HookEvent;
+use identity::{Identity, VerifiedAction};
+use matcher::MatchCommand;
+use palette::Command;
+use replace::ReplaceMode;
+use session::{Session, SessionImage};
+use settings::{HashAlgorithm, ScanOptions, Settings};
+
+const KNOWN_EXTENSIONS: [&str; 13] = [
+    "png", "jpg", "jpeg", "gif", "bmp", "ico", "tiff", "tif", "webp", "avif", "pnm", "dds", "tga",
 ];
 
-const MIN_IMAGE_SIZE: u64 = 10 * 1024; // 10 KiB
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+// Hashes are `HASH_SIZE`x`HASH_SIZE` bits.
+const HASH_SIZE: u32 = 16;
+
+// Hashing only needs a small image, so formats that can decode at a reduced size are decoded at
+// about this size (the smallest JPEG DCT scale not below it).
+const REDUCED_DECODE_SIZE: u16 = 256;
+
+// Background of the paths matching the search.
+const SEARCH_HIGHLIGHT: Color32 = Color32::from_rgb(110, 90, 0);
+
+// Directories listed in the wasted space chart.
+const WASTED_SPACE_ROWS: usize = 20;
+
+// Pairs after the selected one whose textures are loaded ahead. Those of the other pairs, apart
+// from the previous one, are dropped to keep VRAM use independent of the size of the library.
+const PREFETCH_PAIRS: usize = 2;
+// Longest side of the images compared for the diff of a pair.
+const DIFF_SIZE: u32 = 1024;
+
+// Scale of the inspected image.
+const INSPECTOR_MIN_ZOOM: f32 = 0.1;
+const INSPECTOR_MAX_ZOOM: f32 = 8.0;
+// Of the images of the pair view, relative to their size there.
+const PAIR_MAX_ZOOM: f32 = 32.0;
+
+// Upper bound on the time spent handling messages per frame, to keep the UI responsive.
+const MESSAGE_BUDGET: Duration = Duration::from_millis(10);
+// How long the similarity threshold has to stay put before the pairs are matched again.
+const THRESHOLD_SETTLE: Duration = Duration::from_millis(400);
 
 pub struct Image {
     path: String,
     hash: img_hash::ImageHash,
-    texture: egui::TextureHandle,
+    dimensions: (u32, u32),
+    // Animation frames or video keyframes, hashed from their average, or icon sizes, hashed from the
+    // largest. 0 when unknown.
+    frames: u32,
+    // `None` until loaded, e.g. for images recovered from a session.
+    texture: Option<egui::TextureHandle>,
 }
 
+// Messages of a scan start with its generation, those of a previous one are dropped.
 enum Message {
-    WalkDirFinished(usize),
-    AddImage(ByteUnit, Result<Image, (String, ImageError)>),
+    WalkDirFinished(u64, usize),
+    AddImage(u64, ByteUnit, Result<Image, (String, ImageError)>),
     RemoveImage(usize),
+    // Scan generation and the indices of the two images.
+    PairFound(u64, usize, usize),
+    // The size it was requested at, see `thumbnail::size_for`.
+    SetTexture(usize, u32, egui::TextureHandle),
+    ExifSummary(usize, exif::Summary),
+    // Full resolution, for the inspector.
+    SetFullTexture(usize, egui::TextureHandle, Box<histogram::Histogram>),
+    TextureUnavailable(usize),
+    IndexLoaded(usize),
+    CacheMaintained(Result<String, String>),
+    CatalogLoaded(Result<HashSet<String>, String>),
+    // Scan generation, the indices of the two images and how identical they are.
+    IdentityChecked(u64, usize, usize, Identity),
+    TreemapBuilt(treemap::Node),
+    // Reclaimable bytes per directory, largest first.
+    WastedSpace(Vec<(String, u64)>),
+    // Scan generation and all the pairs within the threshold, replacing the found ones.
+    Rematched(u64, Vec<(usize, usize)>),
+    // Scan generation and the groups of images shot within the capture window, see `bursts.rs`.
+    CaptureGroups(u64, Vec<Vec<usize>>),
+    // Where the manifest was written, the number of files in it and the ones which could not be
+    // read.
+    ManifestWritten(PathBuf, Result<(usize, Vec<(String, String)>), String>),
+    CloudProgress(String),
+    // File name and hash of the photos picked in Google Photos.
+    CloudHashed(Result<Vec<(String, img_hash::ImageHash)>, String>),
+    // Size and paths of the groups of byte-identical files, found before hashing.
+    IdenticalFiles(u64, Vec<(u64, Vec<String>)>),
+    HistoryRecorded(history::Record),
+    // The difference image of a pair and the mean difference, see `diff.rs`.
+    DiffComputed((usize, usize), Result<(egui::TextureHandle, f32), String>),
+    // Scan generation, the key sorted by and the sort keys of images, see `MyApp::sort_results`.
+    SortKeys(u64, sort::SortKey, Vec<(usize, sort::ImageKey)>),
+    // Scan generation, the confidence of the pairs and the facts read to score them.
+    PairsScored(
+        u64,
+        Vec<((usize, usize), u8)>,
+        Vec<(usize, confidence::Facts)>,
+    ),
+}
+
+// What the user did while reviewing, kept to be undone. Images are referred to by path since
+// their index changes when they are removed and added back.
+#[derive(Debug, Clone)]
+enum ReviewAction {
+    Trash(journal::JournalEntry),
+    // Hardlink, symlink, move or clone, see `replace_image`.
+    Replace(journal::JournalEntry),
+    Orient(journal::JournalEntry),
+    // "Keep both", the pair left the results.
+    Dismiss(String, String),
+    // Marked for deletion, or unmarked when false.
+    Mark(String, bool),
+}
+
+// Shared with the threads of a scan, to stop or pause it from the UI.
+#[derive(Default)]
+struct ScanControl {
+    cancelled: AtomicBool,
+    paused: AtomicBool,
+    // Every file was hashed, or the scan stopped after a cancel.
+    finished: AtomicBool,
+}
+
+impl ScanControl {
+    // Waits while the scan is paused, then tells whether to go on, i.e. it was not cancelled.
+    fn proceed(&self) -> bool {
+        while self.paused.load(Ordering::Relaxed) && !self.cancelled.load(Ordering::Relaxed) {
+            std::thread::sleep(Duration::from_millis(200));
+        }
+        !self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+// The listed pairs with the pairs of each group following each other, see `MyApp::grouping`.
+#[derive(Default)]
+struct Grouping {
+    // What it was computed from: the listed pairs, in the order of `similar_images`, and their
+    // indices in it, and whether pairs are grouped.
+    pairs: Vec<(usize, usize)>,
+    indices: Vec<usize>,
+    grouped: bool,
+    // Indices in `similar_images` of the listed pairs, in the order of the list.
+    listed: Vec<usize>,
+    // The group of each listed pair, as the row of its first pair, and the images of each group.
+    group_of: Vec<usize>,
+    group_images: HashMap<usize, Vec<usize>>,
 }
 
 struct MyApp {
-    picked_path: Option<String>,
+    // The scanned directories, empty before the first scan.
+    roots: Vec<String>,
     // Since `similar_images` holds indices to the `images` field, we do not want to remove items
     // from `images` when the user deletes an image, since it would invalidate the content of
     // `similar_images`. But we also do not want to consider this 'deleted' image for future
@@ -52,314 +244,5790 @@ struct MyApp {
     found_paths: Option<usize>,
     errors: Vec<(String, String)>,
     analyzed_bytes: ByteUnit,
-    similarity_threshold: u32,
     clipboard: ClipboardContext,
+    settings: Settings,
+    // Directory passed on the command line, scanned on the first frame.
+    pending_scan: Option<PathBuf>,
+    // E.g. `s3://bucket/photos`, see `storage.rs`.
+    remote_url: String,
+    // Sized from the scan options, kept alive until the next scan so that queued work finishes.
+    scan_pool: Option<Arc<rayon::ThreadPool>>,
+    // Cancelling stops the running scan: no more files are walked or queued, those being hashed
+    // finish. Pausing holds the scan threads before the next file, without losing any progress.
+    scan_control: Arc<ScanControl>,
+    // The scan was stopped before the end, it can be resumed from the session.
+    scan_cancelled: bool,
+    new_profile_name: String,
+    update_receiver: std::sync::mpsc::Receiver<update::Release>,
+    available_update: Option<update::Release>,
+    // Indices of images whose texture is being loaded in the background, or was loaded, and the
+    // size it was requested at.
+    texture_requests: HashMap<usize, u32>,
+    // E.g. images from an index made on another machine.
+    unavailable_textures: HashSet<usize>,
+    // Images with a texture, so that evicting them does not go through all images.
+    textured: HashSet<usize>,
+    cache_report: Option<String>,
+    thumbnail_cache_report: Option<String>,
+    autosave_dirty: bool,
+    last_autosave: Instant,
+    // Writing the last autosave, joined on exit before the autosave is removed.
+    autosave_writer: Option<std::thread::JoinHandle<()>>,
+    // Found on startup when the previous run did not exit cleanly.
+    recoverable_session: Option<Session>,
+    // Seconds since the Unix epoch, identifies the scan in the history.
+    scan_started: u64,
+    // Freed by trashing or replacing duplicates since the scan started.
+    reclaimed_bytes: u64,
+    // The history is recorded with the next autosave once the scan is complete, and again after
+    // duplicates are trashed, replaced or restored.
+    history_dirty: bool,
+    // Summaries of the past scans, plotted in the Statistics section.
+    history: Vec<history::Record>,
+    // Byte-identical files with their size, whatever the threshold, see `exact.rs`.
+    identical_files: Vec<(u64, Vec<String>)>,
+    // The group of each of them in `identical_files`.
+    identical_group: HashMap<String, usize>,
+    // From the journal, shown in the trash panel.
+    trashed: Vec<journal::JournalEntry>,
+    undo_stack: Vec<ReviewAction>,
+    redo_stack: Vec<ReviewAction>,
+    // Image opened in the inspector, shown at full resolution once loaded.
+    inspected: Option<usize>,
+    full_texture: Option<egui::TextureHandle>,
+    histogram: Option<histogram::Histogram>,
+    show_histogram: bool,
+    // Scale of the full resolution image, changed by pinching or Ctrl+scroll.
+    inspector_zoom: f32,
+    // Scroll offset and viewport of the inspector last frame, to zoom around the pointer.
+    inspector_view: Option<(Vec2, egui::Rect)>,
+    // Quarter turns clockwise of the inspected image, only on screen until written as its EXIF
+    // orientation, which is `None` without the tag.
+    inspector_rotation: usize,
+    inspector_orientation: Option<u16>,
+    swipe: gestures::Swipe,
+    layout: layout::Layout,
+    matcher: std::sync::mpsc::Sender<MatchCommand>,
+    // Incremented for each scan, see `MatchCommand::Reset`.
+    scan_generation: u64,
+    // Patterns of the frame sequences already paired, see `sequence.rs`.
+    sequence_pairs: HashSet<(String, String)>,
+    // Files referenced by the Lightroom catalog, canonicalized, see `lightroom.rs`.
+    catalog_files: HashSet<String>,
+    // Trashes of catalog files waiting for the user's confirmation, as (image, kept image).
+    pending_trash: Vec<(usize, usize)>,
+    // Same for a move of a kept image over its duplicate, when either is in the catalog.
+    pending_move: Option<(usize, usize)>,
+    // Of the pairs at a distance of about zero, see `identity.rs`.
+    identities: HashMap<(usize, usize), Identity>,
+    verifier: std::sync::mpsc::Sender<identity::Check>,
+    treemap_open: bool,
+    // `None` while it is being built.
+    treemap: Option<treemap::Node>,
+    // Subdirectory shown, relative to the scanned directory.
+    treemap_zoom: Vec<String>,
+    wasted_space: Option<Vec<(String, u64)>>,
+    // Pairs added by the last grouping by capture time, `None` while it runs or before.
+    capture_pairs: Option<usize>,
+    // Pairs added by capture time, kept when the pairs are matched again.
+    capture_time_pairs: HashSet<(usize, usize)>,
+    // The thresholds and tolerance the pairs were matched with, see `ScanOptions::matching`, and
+    // when they last changed since. The pairs are matched again once they settle.
+    matched_with: (u32, u32, Vec<settings::FolderThreshold>, u32),
+    threshold_changed: Option<Instant>,
+    // Pairs the user kept both images of, left out when matching again.
+    dismissed_pairs: HashSet<(usize, usize)>,
+    grouping_by_capture_time: bool,
+    // What the Google Photos comparison is doing, `None` once done.
+    cloud_progress: Option<String>,
+    // Each image with the Google Photos item it matches, if any.
+    cloud_matches: Option<Vec<(usize, Option<String>)>>,
+    // The comparison window was closed while in progress, it shows again with the matches.
+    cloud_hidden: bool,
+    // Between two saved sessions, with the file names of both.
+    session_diff: Option<(String, String, session::SessionDiff)>,
+    // Only the pairs with an image in this directory are listed.
+    results_filter: Option<String>,
+    search: String,
+    // Index in the matching pairs of the one jumped to.
+    search_match: usize,
+    scroll_to_match: bool,
+    sort: sort::SortKey,
+    sort_descending: bool,
+    // Bumped whenever `similar_images` changes. They are sorted again when it differs from the
+    // one they were last sorted at, `None` to sort them again anyway.
+    pairs_generation: u64,
+    sorted_generation: Option<u64>,
+    // Computed once per image on a worker, since it may need the file metadata, see `sort_results`.
+    sort_keys: HashMap<usize, sort::ImageKey>,
+    sort_keys_pending: HashSet<usize>,
+    image_facts: HashMap<usize, confidence::Facts>,
+    // EXIF shown in the pair view, read when the image is first shown there.
+    exif_summaries: HashMap<usize, exif::Summary>,
+    confidences: HashMap<(usize, usize), u8>,
+    // Pairs being scored on a worker, see `score_pairs`.
+    scoring: HashSet<(usize, usize)>,
+    // Tab of the results list, `None` for all pairs.
+    band: Option<bands::Band>,
+    // Only pairs whose hashes are this far apart, bounds included, are listed when set, e.g. to
+    // review the obvious duplicates before the borderline ones.
+    distance_range: Option<(u32, u32)>,
+    palette: palette::Palette,
+    // Grouping of the results list, only computed again when the listed pairs change.
+    grouping: Rc<Grouping>,
+    // Pair shown next to the results list, and its row in the list.
+    selected_pair: Option<(usize, usize)>,
+    selected_row: usize,
+    // Zoom and center, in texture coordinates, shared by both images of the selected pair so that
+    // they show the same part of the picture whatever their resolution.
+    pair_zoom: f32,
+    pair_center: egui::Pos2,
+    // Whether the pointer was over one of them last frame, when the wheel zooms instead of
+    // scrolling.
+    pair_hovered: bool,
+    // Pair whose difference image is shown, and the image once computed.
+    diff_pair: Option<(usize, usize)>,
+    diff: Option<(egui::TextureHandle, f32)>,
+    // Images marked for deletion, by a keep rule or by hand.
+    marked: HashSet<usize>,
+    // Outcome of the last "Trash marked files".
+    batch_summary: Option<String>,
 }
 
 impl MyApp {
-    fn new() -> Self {
+    fn new(cli: Cli, settings: Settings, layout: layout::Layout, ctx: &egui::Context) -> Self {
         let (sender, receiver) = std::sync::mpsc::channel();
-        MyApp {
-            picked_path: None,
+        let (update_sender, update_receiver) = std::sync::mpsc::channel();
+        if settings.check_for_updates {
+            std::thread::spawn(move || {
+                if let Some(release) = update::check() {
+                    let _ = update_sender.send(release);
+                }
+            });
+        }
+        let thumbnail_cache_size = settings.thumbnail_cache_size;
+        std::thread::spawn(move || thumbnail::trim_cache(thumbnail_cache_size));
+        let recoverable_session = Session::autosave_path()
+            .filter(|path| path.is_file())
+            .and_then(|path| match Session::read(&path) {
+                Ok(session) => Some(session),
+                Err(err) => {
+                    error!("Failed to read the autosave {}: {}", path.display(), err);
+                    None
+                }
+            });
+        let matched_with = settings.scan.matching();
+        let mut app = MyApp {
+            roots: Vec::new(),
             images_receiver: receiver,
-            images_sender: sender,
+            images_sender: sender.clone(),
             similar_images: Vec::new(),
             images: Vec::new(),
             found_paths: None,
             errors: Vec::new(),
             analyzed_bytes: 0.bytes(),
-            similarity_threshold: 40,
             clipboard: ClipboardProvider::new().unwrap(),
+            settings,
+            pending_scan: cli.path,
+            remote_url: String::new(),
+            scan_pool: None,
+            scan_control: Arc::default(),
+            scan_cancelled: false,
+            new_profile_name: String::new(),
+            update_receiver,
+            available_update: None,
+            texture_requests: HashMap::new(),
+            textured: HashSet::new(),
+            unavailable_textures: HashSet::new(),
+            cache_report: None,
+            thumbnail_cache_report: None,
+            autosave_dirty: false,
+            last_autosave: Instant::now(),
+            autosave_writer: None,
+            recoverable_session,
+            scan_started: 0,
+            reclaimed_bytes: 0,
+            history_dirty: false,
+            history: history::read(),
+            identical_files: Vec::new(),
+            identical_group: HashMap::new(),
+            trashed: journal::trashed(),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            inspected: None,
+            full_texture: None,
+            histogram: None,
+            show_histogram: false,
+            inspector_zoom: 1.0,
+            inspector_view: None,
+            inspector_rotation: 0,
+            inspector_orientation: None,
+            swipe: gestures::Swipe::default(),
+            layout,
+            matcher: matcher::spawn(sender.clone(), ctx.clone()),
+            scan_generation: 0,
+            sequence_pairs: HashSet::new(),
+            catalog_files: HashSet::new(),
+            pending_trash: Vec::new(),
+            pending_move: None,
+            identities: HashMap::new(),
+            verifier: identity::spawn(sender.clone(), ctx.clone()),
+            treemap_open: false,
+            treemap: None,
+            treemap_zoom: Vec::new(),
+            wasted_space: None,
+            capture_pairs: None,
+            capture_time_pairs: HashSet::new(),
+            matched_with,
+            dismissed_pairs: HashSet::new(),
+            threshold_changed: None,
+            grouping_by_capture_time: false,
+            cloud_progress: None,
+            cloud_matches: None,
+            cloud_hidden: false,
+            session_diff: None,
+            results_filter: None,
+            search: String::new(),
+            search_match: 0,
+            scroll_to_match: false,
+            sort: sort::SortKey::Found,
+            sort_descending: false,
+            pairs_generation: 0,
+            sorted_generation: None,
+            sort_keys: HashMap::new(),
+            sort_keys_pending: HashSet::new(),
+            image_facts: HashMap::new(),
+            exif_summaries: HashMap::new(),
+            confidences: HashMap::new(),
+            scoring: HashSet::new(),
+            band: None,
+            distance_range: None,
+            palette: palette::Palette::default(),
+            grouping: Rc::default(),
+            selected_pair: None,
+            selected_row: 0,
+            pair_zoom: 1.0,
+            pair_center: egui::pos2(0.5, 0.5),
+            pair_hovered: false,
+            diff_pair: None,
+            diff: None,
+            marked: HashSet::new(),
+            batch_summary: None,
+        };
+        app.load_catalog(ctx);
+        app
+    }
+
+    fn load_catalog(&mut self, ctx: &egui::Context) {
+        self.catalog_files.clear();
+        if self.settings.lightroom_catalog.is_empty() {
+            return;
+        }
+        let catalog = PathBuf::from(&self.settings.lightroom_catalog);
+        let sender = self.images_sender.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let files = lightroom::referenced_files(&catalog);
+            let _ = sender.send(Message::CatalogLoaded(files));
+            ctx.request_repaint();
+        });
+    }
+
+    fn in_catalog(&self, idx: usize) -> bool {
+        self.images[idx].as_ref().is_some_and(|image| {
+            self.catalog_files
+                .contains(&lightroom::canonical(&image.path))
+        })
+    }
+
+    // Whether no other copy of the image would be left on the archive volume.
+    fn last_on_archive(&self, idx: usize) -> bool {
+        let on_archive = |idx: usize| {
+            self.images[idx]
+                .as_ref()
+                .is_some_and(|image| volumes::is_on(&self.settings.archive_volume, &image.path))
+        };
+        on_archive(idx)
+            && !self
+                .similar_images
+                .iter()
+                .filter_map(|&(i, j)| match (i == idx, j == idx) {
+                    (true, _) => Some(j),
+                    (_, true) => Some(i),
+                    _ => None,
+                })
+                .any(on_archive)
+    }
+
+    fn trash_warning(&self, idx: usize) -> Option<&'static str> {
+        let remote = self.images[idx]
+            .as_ref()
+            .is_some_and(|image| storage::is_remote(&image.path));
+        if remote {
+            Some("Remote storage has no trash, it will be deleted for good")
+        } else if self.in_catalog(idx) {
+            Some("The Lightroom catalog will show it as missing along with its edits")
+        } else if self.last_on_archive(idx) {
+            Some("No copy would be left on the archive volume")
+        } else if self.settings.delete_permanently {
+            Some("It will be deleted for good, without going through the trash")
+        } else {
+            None
+        }
+    }
+
+    // Files referenced by the Lightroom catalog, or the last copy on the archive volume, wait for
+    // confirmation in `pending_trash`.
+    fn trash_or_confirm(&mut self, idx: usize, kept_idx: usize) {
+        if self.trash_warning(idx).is_some() {
+            if !self
+                .pending_trash
+                .iter()
+                .any(|&(pending, _)| pending == idx)
+            {
+                self.pending_trash.push((idx, kept_idx));
+            }
+        } else if let Some(entry) = self.trash_image(idx, Some(kept_idx)) {
+            self.push_action(ReviewAction::Trash(entry));
+        }
+    }
+
+    // One path per line, for the trashed files still referenced by the catalog and their
+    // companions, to be removed from it with "Remove from Catalog" or a script.
+    fn save_removed_catalog_files(&mut self, path: &Path) {
+        let removed: Vec<&str> = self
+            .trashed
+            .iter()
+            .flat_map(|entry| std::iter::once(&entry.path).chain(&entry.companions))
+            .filter(|path| self.catalog_files.contains(&lightroom::canonical(path)))
+            .map(String::as_str)
+            .collect();
+        let mut content = removed.join("\n");
+        content.push('\n');
+        match std::fs::write(path, content) {
+            Ok(()) => info!(
+                "Saved {} removed catalog files to {}",
+                removed.len(),
+                path.display()
+            ),
+            Err(err) => {
+                error!("Failed to save {}: {}", path.display(), err);
+                self.errors
+                    .push((path.to_string_lossy().to_string(), err.to_string()));
+            }
         }
     }
 
-    fn prep_for_analyze(&mut self, path: PathBuf) {
-        self.picked_path = Some(path.to_string_lossy().to_string());
+    fn prep_for_analyze(&mut self, roots: &[PathBuf]) {
+        self.roots = roots
+            .iter()
+            .map(|root| root.to_string_lossy().to_string())
+            .collect();
         self.images.clear();
         self.similar_images.clear();
         self.errors.clear();
         self.analyzed_bytes = 0.bytes();
+        self.found_paths = None;
+        self.scan_cancelled = false;
+        self.texture_requests.clear();
+        self.textured.clear();
+        self.unavailable_textures.clear();
+        self.scan_generation += 1;
+        self.sequence_pairs.clear();
+        self.pending_trash.clear();
+        self.pending_move = None;
+        self.identities.clear();
+        self.wasted_space = None;
+        self.capture_pairs = None;
+        self.capture_time_pairs.clear();
+        self.matched_with = self.settings.scan.matching();
+        self.threshold_changed = None;
+        self.dismissed_pairs.clear();
+        self.results_filter = None;
+        self.sorted_generation = None;
+        self.sort_keys.clear();
+        self.sort_keys_pending.clear();
+        self.image_facts.clear();
+        self.exif_summaries.clear();
+        self.confidences.clear();
+        self.scoring.clear();
+        self.identical_files.clear();
+        self.identical_group.clear();
+        self.marked.clear();
+        self.batch_summary = None;
+        self.diff_pair = None;
+        self.diff = None;
+        self.scan_started = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        self.reclaimed_bytes = 0;
+        self.history_dirty = true;
+        let _ = self.matcher.send(MatchCommand::Reset(self.scan_generation));
     }
-}
-
-fn analyze(sender: std::sync::mpsc::Sender<Message>, path: PathBuf, ctx: egui::Context) {
-    let mut paths_count = 0usize;
-    WalkDir::new(path)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| {
-            e.file_type().is_file()
-                && e.path().extension().is_some()
-                && KNOWN_EXTENSIONS
-                    .iter()
-                    .any(|x| x == &e.path().extension().unwrap())
-        })
-        .for_each(|entry| {
-            paths_count += 1;
-            let ctx = ctx.clone();
-            let sender = sender.clone();
-            rayon::spawn(move || analyze_image(entry, sender, ctx));
-        });
-    let _ = sender.send(Message::WalkDirFinished(paths_count));
-}
 
-fn analyze_image(entry: DirEntry, sender: std::sync::mpsc::Sender<Message>, ctx: egui::Context) {
-    let path = entry.path();
-
-    match entry.metadata() {
-        Ok(metadata) if metadata.len() < MIN_IMAGE_SIZE => {
-            let _ = sender.send(Message::AddImage(
-                metadata.len().bytes(),
-                Err((
-                    path.to_string_lossy().to_string(),
-                    ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)),
-                )),
-            ));
-            return;
+    // Images from the index are matched against the current results like scanned ones.
+    fn load_index(&mut self, path: PathBuf) {
+        if self.roots.is_empty() {
+            self.roots = vec![path.to_string_lossy().to_string()];
         }
-        _ => {}
+        let sender = self.images_sender.clone();
+        let hash_alg = self.settings.scan.hash_alg;
+        let generation = self.scan_generation;
+        std::thread::spawn(move || {
+            let index = match index::Index::read(&path) {
+                Ok(index) => index,
+                Err(err) => {
+                    error!("Failed to read the index {}: {}", path.display(), err);
+                    let _ = sender.send(Message::AddImage(
+                        generation,
+                        0.bytes(),
+                        Err((
+                            path.to_string_lossy().to_string(),
+                            ImageError::IoError(std::io::Error::other(err)),
+                        )),
+                    ));
+                    return;
+                }
+            };
+            let mut count = 0;
+            for index::IndexEntry { path, entry } in index.entries {
+                if entry.hash_alg != hash_alg {
+                    continue;
+                }
+                let Ok(hash) = img_hash::ImageHash::from_base64(&entry.hash) else {
+                    continue;
+                };
+                count += 1;
+                let _ = sender.send(Message::AddImage(
+                    generation,
+                    entry.size.bytes(),
+                    Ok(Image {
+                        path,
+                        hash,
+                        dimensions: entry.dimensions,
+                        frames: entry.frames,
+                        texture: None,
+                    }),
+                ));
+            }
+            info!("Loaded {} entries from {}", count, path.display());
+            let _ = sender.send(Message::IndexLoaded(count));
+        });
     }
 
-    info!("Hashing {}", path.display());
-    let buffer = match std::fs::read(path) {
-        Err(err) => {
-            error!("Failed to open {:?}: {}", path, err);
-            let _ = sender.send(Message::AddImage(
-                0.bytes(),
-                Err((path.to_string_lossy().to_string(), ImageError::IoError(err))),
-            ));
+    // Makes the thresholds a live filter over the results: once they stop moving, all the hashes
+    // are matched again on the matcher thread, see `Message::Rematched`.
+    fn rematch_on_threshold_change(&mut self, ctx: &egui::Context) {
+        let matching = self.settings.scan.matching();
+        if matching != self.matched_with {
+            self.matched_with = matching;
+            self.threshold_changed = Some(Instant::now());
+        }
+        let Some(changed) = self.threshold_changed else {
+            return;
+        };
+        if changed.elapsed() < THRESHOLD_SETTLE {
+            ctx.request_repaint_after(THRESHOLD_SETTLE - changed.elapsed());
             return;
         }
-        Ok(buffer) => buffer,
-    };
-    let image = match image::load_from_memory(&buffer) {
-        Err(err) => {
-            error!("Failed to decode image {:?}: {}", path, err);
-            let _ = sender.send(Message::AddImage(
-                buffer.len().bytes(),
-                Err((path.to_string_lossy().to_string(), err)),
-            ));
+        self.threshold_changed = None;
+        if self.images.is_empty() {
             return;
         }
-        Ok(img) => img
-            // .resize(1600, 1200, img_hash::FilterType::Lanczos3)
-            .to_rgba8(),
-    };
-
-    let hasher = HasherConfig::new()
-        .hash_size(16, 16)
-        .hash_alg(img_hash::HashAlg::DoubleGradient)
-        .to_hasher();
-
-    let hash = hasher.hash_image(&image);
-
-    debug!("{} hashed", path.display());
-
-    let (width, height) = image.dimensions();
-    let texture = ctx.load_texture(
-        path.to_string_lossy(),
-        egui::ColorImage::from_rgba_unmultiplied([width as usize, height as usize], &image),
-        Default::default(),
-    );
-
-    let _ = sender.send(Message::AddImage(
-        buffer.len().bytes(),
-        Ok(Image {
-            hash,
-            path: path.to_string_lossy().to_string(),
-            texture,
-        }),
-    ));
-    ctx.request_repaint();
-}
+        info!(
+            "Matching again with a threshold of {}",
+            self.settings.scan.similarity_threshold
+        );
+        let _ = self
+            .matcher
+            .send(MatchCommand::Rematch(self.settings.scan.max_threshold()));
+    }
 
-impl eframe::App for MyApp {
-    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            if Button::new("Open directory…")
-                .min_size(egui::Vec2 { x: 150.0, y: 50.0 })
-                .ui(ui)
-                .clicked()
-            {
-                if let Some(path) = rfd::FileDialog::new().pick_folder() {
-                    self.prep_for_analyze(path.clone());
-                    let ctx = ctx.clone();
-                    let sender = self.images_sender.clone();
-                    rayon::spawn(move || analyze(sender, path, ctx));
+    fn handle_messages(&mut self) {
+        let start = Instant::now();
+        while start.elapsed() < MESSAGE_BUDGET {
+            match self.images_receiver.try_recv() {
+                Ok(message) => self.handle_message(message),
+                Err(TryRecvError::Empty) => break,
+                Err(_err) => {
+                    todo!();
                 }
             }
-            ui.add(
-                Slider::new(&mut self.similarity_threshold, 0..=100).text("similarity threshold"),
-            );
+        }
+    }
 
-            let scanned = self.images.len() + self.errors.len();
-            let similar = self.similar_images.len();
-            if let Some(total) = self.found_paths {
-                ui.label(format!(
-                    "Analyzed {}/{} ({:.2})",
-                    scanned, total, self.analyzed_bytes
-                ));
-                ui.add(egui::ProgressBar::new(scanned as f32 / total as f32).show_percentage());
-                ui.label(format!("Similar: {}/{}", similar, total * (total - 1) / 2));
-            } else {
-                ui.label(format!(
-                    "Analyzed {}/? ({:.2})",
-                    scanned, self.analyzed_bytes
-                ));
-                ui.label(format!("Similar: {}/?", similar));
+    fn handle_message(&mut self, message: Message) {
+        match message {
+            // From a cancelled scan, or a scan of roots since removed.
+            Message::WalkDirFinished(generation, _)
+            | Message::AddImage(generation, _, _)
+            | Message::IdenticalFiles(generation, _)
+                if generation != self.scan_generation => {}
+            Message::WalkDirFinished(_, paths_count) => {
+                self.found_paths = Some(paths_count);
+                self.autosave_dirty = true;
             }
-
-            if !self.errors.is_empty() {
-                ui.collapsing(format!("Errors ({})", self.errors.len()), |ui| {
-                    for (path, err) in &self.errors {
-                        ui.horizontal(|ui| {
-                            ui.label(format!("{} {}", path, err));
-                            if ui.button("📋").clicked() {
-                                self.clipboard
-                                    .set_contents(format!("{} {}", path, err))
-                                    .unwrap();
-                            }
-                        });
+            Message::AddImage(_, byte_count, Err((path, err))) => {
+                self.errors.push((path, err.to_string()));
+                self.analyzed_bytes += byte_count;
+                self.autosave_dirty = true;
+            }
+            Message::AddImage(_, byte_count, Ok(image)) => {
+                self.add_image(image);
+                self.analyzed_bytes += byte_count;
+            }
+            Message::PairFound(generation, i, j) => {
+                // The pair may be from a previous scan, or an image may have been removed since.
+                let alive = |idx: usize| matches!(self.images.get(idx), Some(Some(_)));
+                if generation == self.scan_generation
+                    && alive(i)
+                    && alive(j)
+                    && self.within_threshold(i, j)
+                    && self.aspect_ratios_match(i, j)
+                    && self.is_new_sequence_pair(i, j)
+                {
+                    self.similar_images.push((i, j));
+                    self.pairs_generation += 1;
+                    self.queue_identity_check((i, j));
+                    self.autosave_dirty = true;
+                }
+            }
+            Message::DiffComputed(pair, result) => {
+                if self.diff_pair != Some(pair) {
+                    return;
+                }
+                match result {
+                    Ok(diff) => self.diff = Some(diff),
+                    Err(err) => {
+                        error!("Failed to compute the difference of {:?}: {}", pair, err);
+                        self.errors.push(("Show diff".to_owned(), err));
+                        self.diff_pair = None;
                     }
-                });
+                }
             }
-
-            if let Some(picked_path) = &self.picked_path {
-                ui.horizontal(|ui| {
-                    ui.label("Picked directory:");
-                    ui.monospace(picked_path);
-                });
-
-                match self.images_receiver.try_recv() {
-                    Err(TryRecvError::Empty) => {}
-                    Err(_err) => {
-                        todo!();
+            Message::SetTexture(idx, size, texture) => {
+                // Unless a bigger one is on its way.
+                let outdated = self
+                    .texture_requests
+                    .get(&idx)
+                    .is_some_and(|&requested| requested > size);
+                if let Some(Some(image)) = self.images.get_mut(idx).filter(|_| !outdated) {
+                    image.texture = Some(texture);
+                    self.textured.insert(idx);
+                }
+            }
+            Message::ExifSummary(idx, summary) => {
+                self.exif_summaries.insert(idx, summary);
+            }
+            Message::SetFullTexture(idx, texture, histogram) => {
+                if self.inspected == Some(idx) {
+                    self.full_texture = Some(texture);
+                    self.histogram = Some(*histogram);
+                }
+            }
+            Message::TextureUnavailable(idx) => {
+                self.unavailable_textures.insert(idx);
+            }
+            Message::IndexLoaded(count) => {
+                self.found_paths = self.found_paths.map(|x| x + count);
+            }
+            Message::CacheMaintained(report) => {
+                self.cache_report = Some(report.unwrap_or_else(|err| err));
+            }
+            Message::SortKeys(generation, key, keys) => {
+                if generation == self.scan_generation && key == self.sort {
+                    for (idx, _) in &keys {
+                        self.sort_keys_pending.remove(idx);
                     }
-                    Ok(Message::WalkDirFinished(paths_count)) => {
-                        self.found_paths = Some(paths_count);
+                    self.sort_keys.extend(keys);
+                    self.sorted_generation = None;
+                }
+            }
+            Message::PairsScored(generation, scores, facts) => {
+                if generation == self.scan_generation {
+                    for pair in scores.iter().map(|(pair, _)| pair) {
+                        self.scoring.remove(pair);
                     }
-                    Ok(Message::AddImage(byte_count, Err((path, err)))) => {
-                        self.errors.push((path, err.to_string()));
-                        self.analyzed_bytes += byte_count;
+                    self.image_facts.extend(facts);
+                    self.confidences.extend(scores);
+                    // Sorted again, e.g. by confidence.
+                    self.sorted_generation = None;
+                }
+            }
+            Message::IdentityChecked(generation, i, j, identity) => {
+                if generation == self.scan_generation {
+                    self.identities.insert((i, j), identity);
+                    let action = self.settings.verified_action;
+                    if identity == Identity::SameFile && action != VerifiedAction::Review {
+                        self.apply_verified_action((i, j), action);
                     }
-                    Ok(Message::AddImage(byte_count, Ok(image))) => {
-                        let image_idx = self.images.len();
-                        self.images
-                            .iter()
-                            .enumerate()
-                            .for_each(|(i, other)| match other {
-                                Some(Image { hash, .. })
-                                    if hash.dist(&image.hash) < self.similarity_threshold =>
-                                {
-                                    self.similar_images.push((image_idx, i));
-                                }
-                                _ => {}
-                            });
-                        self.images.push(Some(image));
-                        self.analyzed_bytes += byte_count;
+                }
+            }
+            Message::TreemapBuilt(tree) => {
+                self.treemap = Some(tree);
+            }
+            Message::WastedSpace(directories) => {
+                self.wasted_space = Some(directories);
+            }
+            Message::Rematched(generation, pairs) => {
+                if generation != self.scan_generation {
+                    return;
+                }
+                let previous: HashSet<(usize, usize)> =
+                    self.similar_images.iter().copied().collect();
+                let alive = |idx: usize| matches!(self.images.get(idx), Some(Some(_)));
+                let kept: Vec<(usize, usize)> = self
+                    .similar_images
+                    .iter()
+                    .copied()
+                    .filter(|&(i, j)| {
+                        self.capture_time_pairs.contains(&(i, j)) && alive(i) && alive(j)
+                    })
+                    .collect();
+                // Images may have been removed since the matcher got the command.
+                let pairs: Vec<(usize, usize)> = pairs
+                    .into_iter()
+                    .filter(|&(i, j)| alive(i) && alive(j))
+                    .collect();
+                self.sequence_pairs.clear();
+                let mut similar_images = Vec::new();
+                for (i, j) in pairs {
+                    let known = |pairs: &HashSet<(usize, usize)>| {
+                        pairs.contains(&(i, j)) || pairs.contains(&(j, i))
+                    };
+                    if !known(&self.capture_time_pairs)
+                        && !known(&self.dismissed_pairs)
+                        && self.within_threshold(i, j)
+                        && self.aspect_ratios_match(i, j)
+                        && self.is_new_sequence_pair(i, j)
+                    {
+                        similar_images.push((i, j));
                     }
-
-                    Ok(Message::RemoveImage(rm_idx)) => {
-                        info!(
-                            "Removing {}, images.len()={}, similar_images.len()={}",
-                            rm_idx,
-                            self.images.len(),
-                            self.similar_images.len()
-                        );
-                        self.images[rm_idx] = None;
-                        self.similar_images
-                            .retain(|(i, j)| *i != rm_idx && *j != rm_idx);
-
-                        info!(
-                            "Removed {}, images.len()={}, similar_images.len()={}",
-                            rm_idx,
-                            self.images.len(),
-                            self.similar_images.len()
-                        );
-                        self.found_paths = self.found_paths.map(|x| x - 1);
+                }
+                similar_images.extend(kept);
+                info!(
+                    "Matched again: {} pairs, {} before",
+                    similar_images.len(),
+                    previous.len()
+                );
+                self.similar_images = similar_images;
+                self.pairs_generation += 1;
+                for pair in self.similar_images.clone() {
+                    if !previous.contains(&pair) {
+                        self.queue_identity_check(pair);
+                    }
+                }
+                self.sorted_generation = None;
+                self.autosave_dirty = true;
+            }
+            Message::CaptureGroups(generation, groups) => {
+                if generation != self.scan_generation {
+                    return;
+                }
+                self.grouping_by_capture_time = false;
+                let alive = |idx: usize| matches!(self.images.get(idx), Some(Some(_)));
+                let known: HashSet<(usize, usize)> = self
+                    .similar_images
+                    .iter()
+                    .flat_map(|&(i, j)| [(i, j), (j, i)])
+                    .collect();
+                let pairs: Vec<(usize, usize)> = groups
+                    .iter()
+                    .flat_map(|group| group[1..].iter().map(|&other| (group[0], other)))
+                    .filter(|&(i, j)| alive(i) && alive(j) && !known.contains(&(i, j)))
+                    .collect();
+                let added = pairs.len();
+                self.pairs_generation += 1;
+                for pair in pairs {
+                    self.similar_images.push(pair);
+                    self.capture_time_pairs.insert(pair);
+                    self.queue_identity_check(pair);
+                }
+                info!("Paired {} images by capture time", added);
+                self.capture_pairs = Some(added);
+                self.sorted_generation = None;
+                self.autosave_dirty = true;
+            }
+            Message::ManifestWritten(path, Ok((written, failed))) => {
+                info!(
+                    "Wrote the checksums of {} files to {}",
+                    written,
+                    path.display()
+                );
+                for (file, err) in failed {
+                    error!("Failed to checksum {}: {}", file, err);
+                    self.errors.push((file, err));
+                }
+            }
+            Message::ManifestWritten(path, Err(err)) => {
+                error!("Failed to write {}: {}", path.display(), err);
+                self.errors.push((path.to_string_lossy().to_string(), err));
+            }
+            Message::CloudProgress(progress) => {
+                self.cloud_progress = Some(progress);
+            }
+            Message::CloudHashed(Ok(hashes)) => {
+                let threshold = self.settings.scan.similarity_threshold;
+                let matches = self
+                    .images
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, image)| {
+                        let image = image.as_ref()?;
+                        let found = hashes
+                            .iter()
+                            .find(|(_, hash)| image.hash.dist(hash) < threshold)
+                            .map(|(filename, _)| filename.clone());
+                        Some((idx, found))
+                    })
+                    .collect();
+                self.cloud_matches = Some(matches);
+                self.cloud_progress = None;
+                self.cloud_hidden = false;
+            }
+            Message::IdenticalFiles(_, groups) => {
+                self.identical_group = groups
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(group, (_, paths))| {
+                        paths.iter().map(move |path| (path.clone(), group))
+                    })
+                    .collect();
+                self.identical_files = groups;
+            }
+            Message::HistoryRecorded(record) => {
+                self.history
+                    .retain(|other| (other.time, &other.root) != (record.time, &record.root));
+                self.history.push(record);
+            }
+            Message::CloudHashed(Err(err)) => {
+                error!("Failed to compare with Google Photos: {}", err);
+                self.errors.push(("Google Photos".to_owned(), err));
+                self.cloud_progress = None;
+                self.cloud_hidden = false;
+            }
+            Message::CatalogLoaded(Ok(files)) => {
+                self.catalog_files = files;
+            }
+            Message::CatalogLoaded(Err(err)) => {
+                error!(
+                    "Failed to read the Lightroom catalog {}: {}",
+                    self.settings.lightroom_catalog, err
+                );
+                self.errors
+                    .push((self.settings.lightroom_catalog.clone(), err));
+            }
+            Message::RemoveImage(rm_idx) => {
+                info!(
+                    "Removing {}, images.len()={}, similar_images.len()={}",
+                    rm_idx,
+                    self.images.len(),
+                    self.similar_images.len()
+                );
+                self.images[rm_idx] = None;
+                self.marked.remove(&rm_idx);
+                let _ = self.matcher.send(MatchCommand::Remove(rm_idx));
+                let partners: Vec<usize> = self
+                    .similar_images
+                    .iter()
+                    .filter_map(|&(i, j)| match (i == rm_idx, j == rm_idx) {
+                        (true, _) => Some(j),
+                        (_, true) => Some(i),
+                        _ => None,
+                    })
+                    .collect();
+                self.similar_images
+                    .retain(|(i, j)| *i != rm_idx && *j != rm_idx);
+                // Textures are only kept for images still in a pair.
+                for partner in partners {
+                    if !self
+                        .similar_images
+                        .iter()
+                        .any(|&(i, j)| i == partner || j == partner)
+                    {
+                        if let Some(Some(image)) = self.images.get_mut(partner) {
+                            image.texture = None;
+                        }
+                        self.textured.remove(&partner);
+                        self.texture_requests.remove(&partner);
                     }
                 }
 
-                egui::ScrollArea::vertical().show(ui, |ui| {
-                    for (i, j) in &self.similar_images {
-                        let a = self.images[*i].as_ref().unwrap();
-                        let b = self.images[*j].as_ref().unwrap();
+                info!(
+                    "Removed {}, images.len()={}, similar_images.len()={}",
+                    rm_idx,
+                    self.images.len(),
+                    self.similar_images.len()
+                );
+                self.found_paths = self.found_paths.map(|x| x - 1);
+                self.autosave_dirty = true;
+            }
+        }
+    }
+
+    fn queue_identity_check(&mut self, (i, j): (usize, usize)) {
+        let (Some(a), Some(b)) = (&self.images[i], &self.images[j]) else {
+            return;
+        };
+        if a.hash.dist(&b.hash) > identity::MAX_DISTANCE {
+            return;
+        }
+        // Already compared by the pre-pass, only links remain to be told apart.
+        let group = |path: &String| self.identical_group.get(path);
+        if group(&a.path).is_some()
+            && group(&a.path) == group(&b.path)
+            && links::link(Path::new(&a.path), Path::new(&b.path)).is_none()
+        {
+            let _ = self.images_sender.send(Message::IdentityChecked(
+                self.scan_generation,
+                i,
+                j,
+                Identity::SameFile,
+            ));
+            self.identities.insert((i, j), Identity::Checking);
+            return;
+        }
+        // Reading and decoding whole videos would hold up the images behind them.
+        if formats::is_video(Path::new(&a.path)) || formats::is_video(Path::new(&b.path)) {
+            return;
+        }
+        let _ = self.verifier.send(identity::Check {
+            generation: self.scan_generation,
+            pair: (i, j),
+            paths: (a.path.clone(), b.path.clone()),
+        });
+        self.identities.insert((i, j), Identity::Checking);
+    }
 
-                        ui.horizontal(|ui| {
-                            let max_width = ui.available_width() / 2.0 - 10.0;
+    fn apply_verified_action(&mut self, (i, j): (usize, usize), action: VerifiedAction) {
+        if !self.pair_intact((i, j)) {
+            return;
+        }
+        let (Some(a), Some(b)) = (&self.images[i], &self.images[j]) else {
+            return;
+        };
+        // The copy the suggestion keeps, otherwise the first path.
+        let duplicate = match self.suggested_keeper((i, j)) {
+            Some(_) => self.redundant_image((i, j)),
+            None if a.path > b.path => i,
+            None => j,
+        };
+        let kept = if duplicate == i { j } else { i };
+        info!(
+            "{} is identical to {}, applying {}",
+            a.path,
+            b.path,
+            action.name()
+        );
+        let mode = match action {
+            VerifiedAction::Review => return,
+            VerifiedAction::Trash => return self.trash_or_confirm(duplicate, kept),
+            VerifiedAction::Hardlink => ReplaceMode::Hardlink,
+            VerifiedAction::Clone => ReplaceMode::Clone,
+        };
+        if let Some(entry) = self.replace_image(duplicate, kept, mode) {
+            self.push_action(ReviewAction::Replace(entry));
+        }
+    }
 
-                            for (idx, img) in [(i, a), (j, b)] {
-                                ui.vertical(|ui| {
-                                    ui.horizontal(|ui| {
-                                        // TODO: inline in struct?
-                                        ui.label(format!(
-                                            "{} ({}x{})",
-                                            img.path,
-                                            img.texture.size_vec2().x,
-                                            img.texture.size_vec2().y
-                                        ));
-                                        if ui.button("📋").clicked() {
-                                            self.clipboard.set_contents(img.path.clone()).unwrap();
-                                        }
-                                    });
+    // Requests the sort keys of the images of the pairs which have none yet. They come back in
+    // `Message::SortKeys`, and the pairs are sorted again then.
+    fn request_sort_keys(&mut self, ctx: &egui::Context) {
+        if !self.sort.orders_images() {
+            return;
+        }
+        let mut missing = Vec::new();
+        for &(i, j) in &self.similar_images {
+            for idx in [i, j] {
+                let Some(image) = &self.images[idx] else {
+                    continue;
+                };
+                if !self.sort_keys.contains_key(&idx) && self.sort_keys_pending.insert(idx) {
+                    missing.push((idx, image.path.clone(), image.dimensions));
+                }
+            }
+        }
+        if missing.is_empty() {
+            return;
+        }
+        let (sort, generation) = (self.sort, self.scan_generation);
+        let sender = self.images_sender.clone();
+        let ctx = ctx.clone();
+        rayon::spawn(move || {
+            let keys = missing
+                .into_iter()
+                .filter_map(|(idx, path, dimensions)| {
+                    Some((idx, sort::image_key(sort, &path, dimensions)?))
+                })
+                .collect();
+            let _ = sender.send(Message::SortKeys(generation, sort, keys));
+            ctx.request_repaint();
+        });
+    }
+
+    // Sorted again whenever pairs are added or removed, found order is the order of the images
+    // completing the pairs.
+    fn sort_results(&mut self, ctx: &egui::Context) {
+        self.request_sort_keys(ctx);
+        let mut keyed = Vec::with_capacity(self.similar_images.len());
+        for (i, j) in self.similar_images.clone() {
+            let (Some(a), Some(b)) = (&self.images[i], &self.images[j]) else {
+                continue;
+            };
+            // Keys of the pair itself.
+            let pair_key = match self.sort {
+                sort::SortKey::Distance => a.hash.dist(&b.hash),
+                sort::SortKey::Confidence => self.confidences.get(&(i, j)).map_or(0, |&c| c as u32),
+                _ => 0,
+            };
+            let (key_i, key_j) = (self.sort_keys.get(&i), self.sort_keys.get(&j));
+            let key = if self.sort_descending {
+                key_i.max(key_j)
+            } else {
+                key_i.min(key_j)
+            };
+            keyed.push(((key.cloned(), pair_key, i.max(j), i.min(j)), (i, j)));
+        }
+        keyed.sort_by(|a, b| a.0.cmp(&b.0));
+        if self.sort_descending {
+            keyed.reverse();
+        }
+        self.similar_images = keyed.into_iter().map(|(_, pair)| pair).collect();
+        self.sorted_generation = Some(self.pairs_generation);
+    }
 
-                                    let texture_width = img.texture.size_vec2().x;
-                                    let w = f32::clamp(texture_width, 0.0, max_width);
+    // Commands without a button always on screen, the others run along with their button.
+    fn run_command(&mut self, ctx: &egui::Context, command: Command) {
+        let threshold = &mut self.settings.scan.similarity_threshold;
+        match command {
+            Command::DiskUsage => self.open_treemap(ctx),
+            Command::WastedSpace => self.compute_wasted_space(ctx),
+            Command::PairByCaptureTime => self.pair_by_capture_time(ctx),
+            Command::RaiseThreshold => *threshold = (*threshold + palette::THRESHOLD_STEP).min(100),
+            Command::LowerThreshold => {
+                *threshold = threshold.saturating_sub(palette::THRESHOLD_STEP)
+            }
+            Command::ShowAll => self.band = None,
+            Command::ShowBand(band) => self.band = Some(band),
+            Command::SortBy(key) if key != self.sort => {
+                self.sort = key;
+                self.sort_keys.clear();
+                self.sort_keys_pending.clear();
+                self.sorted_generation = None;
+            }
+            Command::ReverseSort => {
+                self.sort_descending = !self.sort_descending;
+                self.sorted_generation = None;
+            }
+            Command::ClearFilter => self.results_filter = None,
+            Command::SaveSettings => {
+                if let Err(err) = self.settings.save() {
+                    error!("Failed to save settings: {}", err);
+                    self.errors.push(("settings".to_owned(), err));
+                }
+            }
+            _ => {}
+        }
+    }
 
-                                    let h = f32::clamp(
-                                        w / img.texture.aspect_ratio(),
-                                        0.0,
-                                        img.texture.size_vec2().y,
-                                    );
+    fn image_facts(&mut self, idx: usize) -> Option<confidence::Facts> {
+        if !self.image_facts.contains_key(&idx) {
+            let facts = confidence::facts(&self.images[idx].as_ref()?.path);
+            self.image_facts.insert(idx, facts);
+        }
+        self.image_facts.get(&idx).copied()
+    }
 
-                                    let display_img_size = Vec2::new(w, h);
-                                    ui.image(&img.texture, display_img_size);
-                                    if egui::Button::new("🗑 Move to trash")
-                                        .fill(Color32::RED)
-                                        .ui(ui)
-                                        .clicked()
-                                    {
-                                        info!("Moving {} to trash", img.path);
-                                        match trash::delete(&img.path) {
-                                            Ok(_) => {
-                                                let res = self
-                                                    .images_sender
-                                                    .send(Message::RemoveImage(*idx));
-                                                debug!("Deleting {}: {:?}", idx, res);
-                                            }
-                                            Err(err) => {
-                                                error!(
-                                                    "Failed to move the file to the trash: {} {}",
-                                                    img.path, err
-                                                );
-                                                self.errors
-                                                    // TODO: Maybe use Rc
-                                                    .push((img.path.clone(), err.to_string()));
-                                            }
-                                        }
-                                    }
-                                });
-                            }
-                        });
-                        egui::Separator::default().spacing(50.0).ui(ui);
-                    }
-                });
+    // Scored once per pair, on a worker since it reads the start of the files. The scores come
+    // back in `Message::PairsScored`.
+    fn score_pairs(&mut self, ctx: &egui::Context) {
+        let mut unscored = Vec::new();
+        for &(i, j) in &self.similar_images {
+            if self.confidences.contains_key(&(i, j)) || self.scoring.contains(&(i, j)) {
+                continue;
             }
+            let (Some(a), Some(b)) = (&self.images[i], &self.images[j]) else {
+                continue;
+            };
+            let side = |idx: usize, image: &Image| {
+                let facts = self.image_facts.get(&idx).copied();
+                (idx, image.path.clone(), image.dimensions, facts)
+            };
+            unscored.push(((i, j), a.hash.dist(&b.hash), side(i, a), side(j, b)));
+        }
+        if unscored.is_empty() {
+            return;
+        }
+        self.scoring.extend(unscored.iter().map(|(pair, ..)| *pair));
+        let threshold = self.settings.scan.similarity_threshold;
+        let generation = self.scan_generation;
+        let sender = self.images_sender.clone();
+        let ctx = ctx.clone();
+        rayon::spawn(move || {
+            let mut read: HashMap<usize, confidence::Facts> = HashMap::new();
+            let mut facts_of = |(idx, path, _, known): &(usize, String, (u32, u32), Option<_>)| {
+                known
+                    .unwrap_or_else(|| *read.entry(*idx).or_insert_with(|| confidence::facts(path)))
+            };
+            let mut scores = Vec::with_capacity(unscored.len());
+            for (pair, distance, a, b) in &unscored {
+                let (facts_a, facts_b) = (facts_of(a), facts_of(b));
+                let score = confidence::score(
+                    *distance,
+                    threshold,
+                    (&a.1, a.2, facts_a),
+                    (&b.1, b.2, facts_b),
+                );
+                scores.push((*pair, score));
+            }
+            let _ = sender.send(Message::PairsScored(
+                generation,
+                scores,
+                read.into_iter().collect(),
+            ));
+            ctx.request_repaint();
         });
     }
-}
 
-fn main() {
-    env_logger::init();
+    fn below_min_confidence(&self, pair: (usize, usize)) -> bool {
+        self.confidences
+            .get(&pair)
+            .is_some_and(|&confidence| confidence < self.settings.min_confidence)
+    }
+
+    fn outside_distance_range(&self, (i, j): (usize, usize)) -> bool {
+        let (Some((min, max)), Some(a), Some(b)) =
+            (self.distance_range, &self.images[i], &self.images[j])
+        else {
+            return false;
+        };
+        let distance = a.hash.dist(&b.hash);
+        distance < min || distance > max
+    }
+
+    // The pairs of the results list, grouped when `group_pairs` is set: the pairs of a group follow
+    // each other, so that the arrow keys go through one group before the next.
+    fn grouping(&mut self) -> Rc<Grouping> {
+        let indices: Vec<usize> = (0..self.similar_images.len())
+            .filter(|&pair| self.is_listed(self.similar_images[pair]))
+            .collect();
+        let pairs: Vec<(usize, usize)> = indices
+            .iter()
+            .map(|&pair| self.similar_images[pair])
+            .collect();
+        let grouped = self.settings.group_pairs;
+        if self.grouping.grouped == grouped
+            && self.grouping.indices == indices
+            && self.grouping.pairs == pairs
+        {
+            return self.grouping.clone();
+        }
+        let mut listed = indices.clone();
+        let mut group_of: Vec<usize> = (0..listed.len()).collect();
+        let mut group_images: HashMap<usize, Vec<usize>> = HashMap::new();
+        if grouped {
+            let mut image_group = HashMap::new();
+            for (group, members) in export::connected_groups(&pairs, self.images.len())
+                .into_iter()
+                .enumerate()
+            {
+                for &idx in &members {
+                    image_group.insert(idx, group);
+                }
+                group_images.insert(group, members);
+            }
+            let mut first_row = HashMap::new();
+            for (row, (i, _)) in pairs.iter().enumerate() {
+                first_row.entry(image_group[i]).or_insert(row);
+            }
+            group_images = group_images
+                .into_iter()
+                .map(|(group, members)| (first_row[&group], members))
+                .collect();
+            let mut rows: Vec<(usize, usize)> = pairs
+                .iter()
+                .zip(&listed)
+                .map(|((i, _), &pair)| (first_row[&image_group[i]], pair))
+                .collect();
+            rows.sort_by_key(|&(group, _)| group);
+            (group_of, listed) = rows.into_iter().unzip();
+        }
+        self.grouping = Rc::new(Grouping {
+            pairs,
+            indices,
+            grouped,
+            listed,
+            group_of,
+            group_images,
+        });
+        self.grouping.clone()
+    }
+
+    // Whether the pair passes the filters of the results list. Pairs sharing their data are listed
+    // apart.
+    fn is_listed(&self, (i, j): (usize, usize)) -> bool {
+        let filtered_out = self.results_filter.as_ref().is_some_and(|dir| {
+            [i, j].into_iter().all(|idx| {
+                self.images[idx]
+                    .as_ref()
+                    .is_none_or(|image| !Path::new(&image.path).starts_with(dir))
+            })
+        });
+        !filtered_out
+            && !self.below_min_dimension((i, j))
+            && !self.below_min_confidence((i, j))
+            && !self.outside_distance_range((i, j))
+            && self.in_band((i, j))
+            && !matches!(self.identities.get(&(i, j)), Some(Identity::Linked(_)))
+    }
+
+    fn below_min_dimension(&self, (i, j): (usize, usize)) -> bool {
+        let min = self.settings.min_pair_dimension;
+        let small = |idx: usize| {
+            self.images[idx]
+                .as_ref()
+                .is_some_and(|image| image.dimensions.0.min(image.dimensions.1) < min)
+        };
+        if self.settings.min_pair_dimension_both {
+            small(i) && small(j)
+        } else {
+            small(i) || small(j)
+        }
+    }
+
+    // The image of the pair not suggested as the keeper, or the one found last.
+    fn redundant_image(&self, (i, j): (usize, usize)) -> usize {
+        match self.suggested_keeper((i, j)) {
+            Some((keeper, _)) if keeper == i => j,
+            Some(_) => i,
+            None => i.max(j),
+        }
+    }
+
+    fn redundant_images(&self) -> HashSet<usize> {
+        self.similar_images
+            .iter()
+            .map(|&pair| self.redundant_image(pair))
+            .collect()
+    }
+
+    // Marks the image the keep rule does not keep in each pair of the current tab. Returns the
+    // number of pairs it could not decide, e.g. files of the same size.
+    fn mark_by_rule(&mut self) -> usize {
+        let rule = self.settings.keep_rule;
+        let mut undecided = 0;
+        let pairs: Vec<(usize, usize)> = self
+            .similar_images
+            .iter()
+            .copied()
+            .filter(|&pair| self.in_band(pair) && self.bulk_actionable(pair))
+            .collect();
+        for (i, j) in pairs {
+            let (Some(a), Some(b)) = (&self.images[i], &self.images[j]) else {
+                continue;
+            };
+            match rule.keeps_first(a, b) {
+                Some(true) => self.marked.insert(j),
+                Some(false) => self.marked.insert(i),
+                None => {
+                    undecided += 1;
+                    continue;
+                }
+            };
+        }
+        undecided
+    }
+
+    // Trashes all marked images. Each one is kept in the journal against a partner which is not
+    // marked, those without one are skipped. Those needing confirmation wait in `pending_trash`.
+    fn trash_marked(&mut self) {
+        let mut marked: Vec<usize> = self.marked.iter().copied().collect();
+        marked.sort_unstable();
+        let (mut trashed, mut failed, mut pending, mut skipped) = (0, 0, 0, 0);
+        for idx in marked {
+            let kept_idx = self.similar_images.iter().find_map(|&(i, j)| {
+                let partner = match (i == idx, j == idx) {
+                    (true, _) => j,
+                    (_, true) => i,
+                    _ => return None,
+                };
+                (!self.marked.contains(&partner)).then_some(partner)
+            });
+            // Every copy would go otherwise.
+            let Some(kept_idx) = kept_idx else {
+                skipped += 1;
+                continue;
+            };
+            if self.trash_warning(idx).is_some() {
+                self.trash_or_confirm(idx, kept_idx);
+                pending += 1;
+                continue;
+            }
+            match self.trash_image(idx, Some(kept_idx)) {
+                Some(entry) => {
+                    self.push_action(ReviewAction::Trash(entry));
+                    trashed += 1;
+                }
+                None => failed += 1,
+            }
+        }
+        let mut summary = format!("{} trashed", trashed);
+        if failed > 0 {
+            summary += &format!(", {} failed (see the errors)", failed);
+        }
+        if pending > 0 {
+            summary += &format!(", {} waiting for confirmation", pending);
+        }
+        if skipped > 0 {
+            summary += &format!(
+                ", {} skipped: all their copies are marked too, unmark the one to keep",
+                skipped
+            );
+        }
+        info!("Trashed the marked files: {}", summary);
+        self.batch_summary = Some(summary);
+    }
+
+    fn band_of(&self, (i, j): (usize, usize)) -> bands::Band {
+        let (Some(a), Some(b)) = (&self.images[i], &self.images[j]) else {
+            return bands::Band::Loose;
+        };
+        bands::Band::of(
+            a.hash.dist(&b.hash),
+            self.settings.scan.similarity_threshold,
+        )
+    }
+
+    fn in_band(&self, pair: (usize, usize)) -> bool {
+        self.band.is_none_or(|band| self.band_of(pair) == band)
+    }
+
+    // Whether the bulk actions act on the pair: it is listed, i.e. visible in the current tab and
+    // filters and not sharing its data already, and no RAW+JPEG or edited variant policy applies.
+    fn bulk_actionable(&self, pair: (usize, usize)) -> bool {
+        self.is_listed(pair)
+            && self.raw_jpeg_pair(pair).is_none()
+            && self.edited_variant_pair(pair).is_none()
+    }
+
+    // Both images are still there and neither waits for confirmation. Bulk actions skip the pairs
+    // whose other image went earlier in the batch, so that each group keeps a copy.
+    fn pair_intact(&self, (i, j): (usize, usize)) -> bool {
+        [i, j].into_iter().all(|idx| {
+            self.images[idx].is_some()
+                && !self
+                    .pending_trash
+                    .iter()
+                    .any(|&(pending, kept)| pending == idx || kept == idx)
+        })
+    }
+
+    // Trashes the redundant image of every listed pair of the current tab.
+    fn trash_band(&mut self) {
+        let pairs: Vec<(usize, usize)> = self
+            .similar_images
+            .iter()
+            .copied()
+            .filter(|&pair| self.bulk_actionable(pair))
+            .collect();
+        for (i, j) in pairs {
+            if !self.pair_intact((i, j)) {
+                continue;
+            }
+            let redundant = self.redundant_image((i, j));
+            let kept = if redundant == i { j } else { i };
+            self.trash_or_confirm(redundant, kept);
+        }
+    }
+
+    fn pair_by_capture_time(&mut self, ctx: &egui::Context) {
+        let images: Vec<(usize, String)> = self
+            .images
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, image)| Some((idx, image.as_ref()?.path.clone())))
+            .collect();
+        let window = self.settings.capture_window as i64;
+        let generation = self.scan_generation;
+        let sender = self.images_sender.clone();
+        let ctx = ctx.clone();
+        self.grouping_by_capture_time = true;
+        self.capture_pairs = None;
+        std::thread::spawn(move || {
+            let groups = bursts::groups(&images, window);
+            let _ = sender.send(Message::CaptureGroups(generation, groups));
+            ctx.request_repaint();
+        });
+    }
+
+    fn compute_wasted_space(&mut self, ctx: &egui::Context) {
+        let paths: Vec<String> = self
+            .redundant_images()
+            .into_iter()
+            .filter_map(|idx| Some(self.images[idx].as_ref()?.path.clone()))
+            .collect();
+        let sender = self.images_sender.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let mut directories: HashMap<String, u64> = HashMap::new();
+            for path in paths {
+                let size = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+                let dir = Path::new(&path).parent().unwrap_or(Path::new(""));
+                *directories
+                    .entry(dir.to_string_lossy().to_string())
+                    .or_default() += size;
+            }
+            let mut directories: Vec<(String, u64)> = directories.into_iter().collect();
+            directories.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            let _ = sender.send(Message::WastedSpace(directories));
+            ctx.request_repaint();
+        });
+    }
+
+    // Every image still there once the duplicates are resolved.
+    fn export_manifest(&mut self, ctx: &egui::Context, path: PathBuf) {
+        let files: Vec<String> = self
+            .images
+            .iter()
+            .flatten()
+            .map(|image| image.path.clone())
+            .collect();
+        let sender = self.images_sender.clone();
+        let ctx = ctx.clone();
+        rayon::spawn(move || {
+            let result = manifest::write(&path, &files).map_err(|err| err.to_string());
+            let _ = sender.send(Message::ManifestWritten(path, result));
+            ctx.request_repaint();
+        });
+    }
+
+    fn compare_with_cloud(&mut self, ctx: &egui::Context) {
+        let sender = self.images_sender.clone();
+        let ctx = ctx.clone();
+        let hash_alg = self.settings.scan.hash_alg;
+        self.cloud_matches = None;
+        self.cloud_progress = Some(String::new());
+        self.cloud_hidden = false;
+        std::thread::spawn(move || {
+            let progress = |progress| {
+                let _ = sender.send(Message::CloudProgress(progress));
+                ctx.request_repaint();
+            };
+            let hashes = gphotos::fetch(|image| hash_image(image, hash_alg), progress);
+            let _ = sender.send(Message::CloudHashed(hashes));
+            ctx.request_repaint();
+        });
+    }
+
+    fn open_treemap(&mut self, ctx: &egui::Context) {
+        if self.roots.is_empty() {
+            return;
+        }
+        let root = common_ancestor(&self.roots);
+        let redundant = self.redundant_images();
+        let files: Vec<(String, bool)> = self
+            .images
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, image)| {
+                Some((image.as_ref()?.path.clone(), redundant.contains(&idx)))
+            })
+            .collect();
+        self.treemap_open = true;
+        self.treemap = None;
+        self.treemap_zoom.clear();
+        let sender = self.images_sender.clone();
+        let ctx = ctx.clone();
+        std::thread::spawn(move || {
+            let tree = treemap::build(&root, &files);
+            let _ = sender.send(Message::TreemapBuilt(tree));
+            ctx.request_repaint();
+        });
+    }
+
+    // Trashes the copy with less metadata, i.e. the smaller file since their pixels are the same.
+    fn strip_and_dedupe(&mut self, (i, j): (usize, usize)) {
+        if !self.pair_intact((i, j)) {
+            return;
+        }
+        let size = |idx: usize| {
+            self.images[idx]
+                .as_ref()
+                .and_then(|image| std::fs::metadata(&image.path).ok())
+                .map_or(0, |metadata| metadata.len())
+        };
+        if size(i) < size(j) {
+            self.trash_or_confirm(i, j);
+        } else {
+            self.trash_or_confirm(j, i);
+        }
+    }
+
+    // Beyond the threshold of their folders, only messaging app copies are kept.
+    fn within_threshold(&self, i: usize, j: usize) -> bool {
+        let (a, b) = (
+            self.images[i].as_ref().unwrap(),
+            self.images[j].as_ref().unwrap(),
+        );
+        let threshold = self
+            .settings
+            .scan
+            .threshold_for(self.root_of(&a.path), &a.path)
+            .min(
+                self.settings
+                    .scan
+                    .threshold_for(self.root_of(&b.path), &b.path),
+            );
+        a.hash.dist(&b.hash) < threshold || self.messaging_copy((i, j)).is_some()
+    }
+
+    // Orientation is ignored, and so are images of unknown dimensions.
+    fn aspect_ratios_match(&self, i: usize, j: usize) -> bool {
+        let tolerance = self.settings.scan.aspect_ratio_tolerance;
+        let ratio = |idx: usize| {
+            let (width, height) = self.images[idx].as_ref().unwrap().dimensions;
+            let (long, short) = (width.max(height), width.min(height));
+            (short > 0).then(|| long as f64 / short as f64)
+        };
+        let (Some(a), Some(b)) = (ratio(i), ratio(j)) else {
+            return true;
+        };
+        tolerance == 0 || (a.max(b) / a.min(b) - 1.0) * 100.0 <= tolerance as f64
+    }
+
+    // The index of the messaging app copy when the other image of the pair is its original.
+    fn messaging_copy(&self, (i, j): (usize, usize)) -> Option<usize> {
+        if self.settings.scan.messaging_threshold <= self.settings.scan.similarity_threshold {
+            return None;
+        }
+        let (a, b) = (self.images[i].as_ref()?, self.images[j].as_ref()?);
+        if messaging::is_copy(a.dimensions, b.dimensions) {
+            Some(i)
+        } else if messaging::is_copy(b.dimensions, a.dimensions) {
+            Some(j)
+        } else {
+            None
+        }
+    }
+
+    // Frames of the same sequence always look alike, and once two sequences are paired, the pairs
+    // of their other frames add nothing.
+    fn is_new_sequence_pair(&mut self, i: usize, j: usize) -> bool {
+        if self.settings.scan.sequence_step == 0 {
+            return true;
+        }
+        let pattern = |idx: usize| {
+            let image = self.images[idx].as_ref().unwrap();
+            sequence::pattern(&image.path)
+        };
+        let (Some(a), Some(b)) = (pattern(i), pattern(j)) else {
+            return true;
+        };
+        a != b
+            && self
+                .sequence_pairs
+                .insert((a.clone().min(b.clone()), a.max(b)))
+    }
+
+    // The (RAW, JPEG) indices when the pair is a RAW file and the JPEG the camera wrote with it.
+    fn raw_jpeg_pair(&self, (i, j): (usize, usize)) -> Option<(usize, usize)> {
+        let (a, b) = (self.images[i].as_ref()?, self.images[j].as_ref()?);
+        if companions::is_raw_jpeg_pair(&a.path, &b.path) {
+            Some((i, j))
+        } else if companions::is_raw_jpeg_pair(&b.path, &a.path) {
+            Some((j, i))
+        } else {
+            None
+        }
+    }
+
+    fn apply_raw_jpeg_policy(&mut self, pair: (usize, usize), policy: RawJpegPolicy) {
+        let Some((raw, jpeg)) = self.raw_jpeg_pair(pair) else {
+            return;
+        };
+        let (trashed, kept) = match policy {
+            RawJpegPolicy::RawOnly => (jpeg, raw),
+            RawJpegPolicy::JpegOnly => (raw, jpeg),
+            RawJpegPolicy::Both => {
+                self.dismiss_pair(pair);
+                return;
+            }
+        };
+        self.trash_or_confirm(trashed, kept);
+    }
+
+    // The pair leaves the results, both images stay.
+    fn dismiss_pair(&mut self, pair: (usize, usize)) {
+        let (Some(a), Some(b)) = (&self.images[pair.0], &self.images[pair.1]) else {
+            return;
+        };
+        let action = ReviewAction::Dismiss(a.path.clone(), b.path.clone());
+        self.set_dismissed(pair, true);
+        self.push_action(action);
+    }
+
+    // Undoing a dismissal lists the pair again, the sort puts it back in its place.
+    fn set_dismissed(&mut self, pair: (usize, usize), dismissed: bool) {
+        if dismissed {
+            self.similar_images.retain(|&other| other != pair);
+            self.dismissed_pairs.insert(pair);
+        } else {
+            self.dismissed_pairs.remove(&pair);
+            if !self.similar_images.contains(&pair) {
+                self.similar_images.push(pair);
+            }
+        }
+        self.pairs_generation += 1;
+        self.autosave_dirty = true;
+    }
+
+    fn mark_image(&mut self, idx: usize, marked: bool) {
+        let Some(image) = &self.images[idx] else {
+            return;
+        };
+        let action = ReviewAction::Mark(image.path.clone(), marked);
+        self.set_marked(idx, marked);
+        self.push_action(action);
+    }
+
+    fn set_marked(&mut self, idx: usize, marked: bool) {
+        if marked {
+            self.marked.insert(idx);
+        } else {
+            self.marked.remove(&idx);
+        }
+        self.autosave_dirty = true;
+    }
+
+    // The (edited, original) indices when the pair is an iPhone edit and its original.
+    fn edited_variant_pair(&self, (i, j): (usize, usize)) -> Option<(usize, usize)> {
+        if !self.settings.scan.dcim_layout {
+            return None;
+        }
+        let (a, b) = (self.images[i].as_ref()?, self.images[j].as_ref()?);
+        if dcim::is_edited_variant(&a.path, &b.path) {
+            Some((i, j))
+        } else if dcim::is_edited_variant(&b.path, &a.path) {
+            Some((j, i))
+        } else {
+            None
+        }
+    }
+
+    // The image of the pair to keep when one is clearly the original, and why.
+    fn suggested_keeper(&self, (i, j): (usize, usize)) -> Option<(usize, &'static str)> {
+        let (a, b) = (self.images[i].as_ref()?, self.images[j].as_ref()?);
+        let reason = "Decodes cleanly, the other copy may be corrupted";
+        match self.identities.get(&(i, j)) {
+            Some(Identity::Corrupted {
+                a_decodes: true,
+                b_decodes: false,
+            }) => return Some((i, reason)),
+            Some(Identity::Corrupted {
+                a_decodes: false,
+                b_decodes: true,
+            }) => return Some((j, reason)),
+            _ => {}
+        }
+        match volumes::keep_first(&self.settings.keep_volumes, &a.path, &b.path) {
+            Some(true) => return Some((i, "On the volume kept first")),
+            Some(false) => return Some((j, "On the volume kept first")),
+            None => {}
+        }
+        if let Some(copy) = self.messaging_copy((i, j)) {
+            let original = if copy == i { j } else { i };
+            return Some((original, "The original, the other is a messaging app copy"));
+        }
+        if !self.settings.scan.dcim_layout {
+            return None;
+        }
+        let reason = "The original, from the DCIM folder of the camera or phone";
+        match (dcim::is_in_dcim(&a.path), dcim::is_in_dcim(&b.path)) {
+            (true, false) => Some((i, reason)),
+            (false, true) => Some((j, reason)),
+            _ => None,
+        }
+    }
+
+    // Its pairs come back later from the matcher, as `Message::PairFound`.
+    fn add_image(&mut self, image: Image) {
+        let _ = self.matcher.send(MatchCommand::Add {
+            idx: self.images.len(),
+            hash: image.hash.clone(),
+            // Pairs beyond their own threshold are dropped in `handle_message`.
+            threshold: self.settings.scan.max_threshold(),
+        });
+        self.images.push(Some(image));
+        self.autosave_dirty = true;
+    }
+
+    // Restored files come back into the results when they were hashed with the current algorithm
+    // and belong to the scanned directory.
+    fn restore_trashed(&mut self, entries: Vec<journal::JournalEntry>) -> Result<(), String> {
+        journal::restore(&entries)?;
+        for entry in entries {
+            info!("Restored {}", entry.path);
+            self.trashed.retain(|trashed| trashed.path != entry.path);
+            self.add_restored(entry);
+        }
+        Ok(())
+    }
+
+    fn add_restored(&mut self, entry: journal::JournalEntry) {
+        let in_scan = self
+            .roots
+            .iter()
+            .any(|root| Path::new(&entry.path).starts_with(root));
+        if !in_scan || entry.hash_alg != self.settings.scan.hash_alg {
+            return;
+        }
+        let Ok(hash) = img_hash::ImageHash::from_base64(&entry.hash) else {
+            return;
+        };
+        self.add_image(Image {
+            path: entry.path,
+            hash,
+            dimensions: entry.dimensions,
+            frames: 0,
+            texture: None,
+        });
+        self.found_paths = self.found_paths.map(|x| x + 1);
+    }
+
+    // Puts the replaced file back at its path from the trash, after moving the kept file back to
+    // its own path in the move case.
+    fn undo_replace(&mut self, entry: &journal::JournalEntry) -> Result<(), String> {
+        let (Some(backup), Some(kept)) = (&entry.backup, &entry.kept) else {
+            return Err(format!("{} was not kept when it was replaced", entry.path));
+        };
+        let mut trashed = entry.clone();
+        trashed.path = backup.clone();
+        journal::restore(&[trashed])?;
+        if entry.action == journal::JournalAction::Move {
+            quarantine::move_file(Path::new(&entry.path), Path::new(kept))
+                .map_err(|err| format!("Failed to move {} back: {}", entry.path, err))?;
+            for (from, to) in &entry.renamed {
+                if let Err(err) = std::fs::rename(to, from) {
+                    error!("Failed to move {} back: {}", to, err);
+                    self.errors.push((to.clone(), err.to_string()));
+                }
+            }
+            if let Some(Some(image)) = self
+                .image_index(&entry.path)
+                .map(|idx| self.images[idx].as_mut())
+            {
+                image.path = kept.clone();
+            }
+        }
+        std::fs::rename(backup, &entry.path)
+            .map_err(|err| format!("Failed to put {} back: {}", entry.path, err))?;
+        info!("Put {} back", entry.path);
+        self.add_restored(entry.clone());
+        Ok(())
+    }
+
+    // Deletes a file for good when `permanently`, which callers only pass once it was confirmed, or
+    // moves it to the quarantine folder when one is set, to the trash otherwise. Returns where it
+    // was moved in the quarantine case.
+    fn dispose(&self, path: &str, permanently: bool) -> Result<Option<String>, String> {
+        if permanently {
+            info!("Deleting {}", path);
+            return std::fs::remove_file(path)
+                .map(|()| None)
+                .map_err(|err| err.to_string());
+        }
+        if self.settings.quarantine_dir.is_empty() {
+            info!("Moving {} to trash", path);
+            return trash::delete(path)
+                .map(|()| None)
+                .map_err(|err| err.to_string());
+        }
+        let to = quarantine::destination(
+            Path::new(&self.settings.quarantine_dir),
+            self.root_of(path),
+            path,
+        );
+        quarantine::move_file(Path::new(path), &to)
+            .map(|()| Some(to.to_string_lossy().to_string()))
+            .map_err(|err| err.to_string())
+    }
+
+    // Returns the journal entry of the trashed file, `None` when it could not be trashed or was
+    // deleted for good, since there is nothing to undo then.
+    fn trash_image(
+        &mut self,
+        idx: usize,
+        kept_idx: Option<usize>,
+    ) -> Option<journal::JournalEntry> {
+        let img = self.images[idx].as_ref()?;
+        let kept = kept_idx.and_then(|kept_idx| self.images[kept_idx].as_ref());
+        // Confirmed in `trash_or_confirm`, there is nothing to undo.
+        if storage::is_remote(&img.path) {
+            info!("Deleting {}", img.path);
+            if let Err(err) = storage::delete(&img.path) {
+                error!("Failed to delete {}: {}", img.path, err);
+                self.errors.push((img.path.clone(), err));
+                return None;
+            }
+            self.handle_message(Message::RemoveImage(idx));
+            return None;
+        }
+        let size = std::fs::metadata(&img.path).map_or(0, |metadata| metadata.len());
+        let deleted = self.settings.delete_permanently;
+        let moved_to = match self.dispose(&img.path, deleted) {
+            Ok(moved_to) => moved_to,
+            Err(err) => {
+                error!("Failed to move the file to the trash: {} {}", img.path, err);
+                self.errors.push((img.path.clone(), err));
+                return None;
+            }
+        };
+        let mut entry = journal::JournalEntry::new(
+            if deleted {
+                journal::JournalAction::Delete
+            } else {
+                journal::JournalAction::Trash
+            },
+            &img.path,
+            self.settings.scan.hash_alg,
+            img.hash.to_base64(),
+            img.dimensions,
+            kept.map(|kept| kept.path.as_str()),
+        );
+        entry.moved_to = moved_to;
+        if self.settings.trash_companions {
+            let kept_path = kept.map(|kept| Path::new(&kept.path));
+            for companion in companions::find_unshared(Path::new(&img.path), kept_path) {
+                let companion = companion.to_string_lossy().to_string();
+                match self.dispose(&companion, deleted) {
+                    Ok(_) => entry.companions.push(companion),
+                    Err(err) => {
+                        error!("Failed to move {} to the trash: {}", companion, err);
+                        self.errors.push((companion, err));
+                    }
+                }
+            }
+        }
+        journal::append(&entry);
+        self.reclaimed_bytes += size;
+        self.history_dirty = true;
+        if !deleted {
+            self.trashed.insert(0, entry.clone());
+        }
+        let group = idx.min(kept_idx.unwrap_or(idx));
+        let hooks = &self.settings.hooks;
+        hooks::run(hooks, HookEvent::Trash, &img.path, group, &entry.hash);
+        if let Some(kept) = kept {
+            hooks::run(
+                hooks,
+                HookEvent::Keep,
+                &kept.path,
+                group,
+                &kept.hash.to_base64(),
+            );
+        }
+        self.handle_message(Message::RemoveImage(idx));
+        (!deleted).then_some(entry)
+    }
+
+    // Moving a catalog file, or over one, leaves the catalog pointing at the wrong file or none, it
+    // waits for confirmation in `pending_move`.
+    fn replace_or_confirm(&mut self, idx: usize, kept_idx: usize, mode: ReplaceMode) {
+        if mode == ReplaceMode::Move && (self.in_catalog(idx) || self.in_catalog(kept_idx)) {
+            self.pending_move = Some((idx, kept_idx));
+        } else if let Some(entry) = self.replace_image(idx, kept_idx, mode) {
+            self.push_action(ReviewAction::Replace(entry));
+        }
+    }
+
+    // The duplicate leaves the results. After a move, the kept image lives at the duplicate's path.
+    // Returns the journal entry of the replacement, `None` when it failed.
+    fn replace_image(
+        &mut self,
+        idx: usize,
+        kept_idx: usize,
+        mode: ReplaceMode,
+    ) -> Option<journal::JournalEntry> {
+        let (Some(img), Some(kept)) = (&self.images[idx], &self.images[kept_idx]) else {
+            return None;
+        };
+        let (path, kept_path) = (img.path.clone(), kept.path.clone());
+        // After a move, the duplicate's companions describe a file that is gone, and the kept
+        // image's ones follow it to its new name. Looked up before the kept image moves.
+        let (replaced_companions, kept_companions) =
+            if mode == ReplaceMode::Move && self.settings.trash_companions {
+                (
+                    companions::find_unshared(Path::new(&path), Some(Path::new(&kept_path))),
+                    companions::find(Path::new(&kept_path)),
+                )
+            } else {
+                (Vec::new(), Vec::new())
+            };
+        let size = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+        let backup = match replace::backup(Path::new(&path)) {
+            Ok(backup) => backup.to_string_lossy().to_string(),
+            Err(err) => {
+                error!("Failed to keep a copy of {}: {}", path, err);
+                self.errors.push((path, err.to_string()));
+                return None;
+            }
+        };
+        if let Err(err) = replace::replace(
+            Path::new(&path),
+            Path::new(&kept_path),
+            mode,
+            self.settings.preserve_metadata,
+        ) {
+            error!("Failed to replace {}: {}", path, err);
+            self.errors.push((path, err.to_string()));
+            let _ = std::fs::remove_file(&backup);
+            return None;
+        }
+        self.reclaimed_bytes += size;
+        self.history_dirty = true;
+        let mut entry = journal::JournalEntry::new(
+            match mode {
+                ReplaceMode::Hardlink => journal::JournalAction::Hardlink,
+                ReplaceMode::Symlink => journal::JournalAction::Symlink,
+                ReplaceMode::Move => journal::JournalAction::Move,
+                ReplaceMode::Clone => journal::JournalAction::Clone,
+            },
+            &path,
+            self.settings.scan.hash_alg,
+            img.hash.to_base64(),
+            img.dimensions,
+            Some(&kept_path),
+        );
+        // Never deleted for good, replacing was not confirmed as a deletion.
+        match self.dispose(&backup, false) {
+            Ok(moved_to) => {
+                entry.backup = Some(backup);
+                entry.moved_to = moved_to;
+            }
+            Err(err) => {
+                error!("Failed to move {} to the trash: {}", backup, err);
+                self.errors.push((backup.clone(), err));
+                let _ = std::fs::remove_file(&backup);
+            }
+        }
+        for companion in replaced_companions {
+            let companion = companion.to_string_lossy().to_string();
+            match self.dispose(&companion, false) {
+                Ok(_) => entry.companions.push(companion),
+                Err(err) => {
+                    error!("Failed to move {} to the trash: {}", companion, err);
+                    self.errors.push((companion, err));
+                }
+            }
+        }
+        for companion in kept_companions {
+            let to = companions::renamed(&companion, Path::new(&kept_path), Path::new(&path));
+            info!("Moving {} to {}", companion.display(), to.display());
+            match std::fs::rename(&companion, &to) {
+                Ok(()) => entry.renamed.push((
+                    companion.to_string_lossy().to_string(),
+                    to.to_string_lossy().to_string(),
+                )),
+                Err(err) => {
+                    error!("Failed to move {}: {}", companion.display(), err);
+                    self.errors
+                        .push((companion.to_string_lossy().to_string(), err.to_string()));
+                }
+            }
+        }
+        journal::append(&entry);
+        let group = idx.min(kept_idx);
+        let hooks = &self.settings.hooks;
+        hooks::run(hooks, HookEvent::Replace, &path, group, &entry.hash);
+        // After a move, the kept file is at the replaced one's path.
+        let kept_now = if mode == ReplaceMode::Move {
+            &path
+        } else {
+            &kept_path
+        };
+        hooks::run(
+            hooks,
+            HookEvent::Keep,
+            kept_now,
+            group,
+            &kept.hash.to_base64(),
+        );
+        self.handle_message(Message::RemoveImage(idx));
+        if mode == ReplaceMode::Move {
+            if let Some(Some(kept)) = self.images.get_mut(kept_idx) {
+                kept.path = path;
+            }
+        }
+        Some(entry)
+    }
+
+    // Loads the textures of the `pairs` likely to be looked at next, so that they are ready when
+    // selected.
+    fn prefetch_textures(&mut self, ctx: &egui::Context, pairs: &[(usize, usize)], size: u32) {
+        for idx in pairs.iter().flat_map(|&(i, j)| [i, j]) {
+            let Some(image) = &self.images[idx] else {
+                continue;
+            };
+            if image.texture.is_none()
+                && !self.unavailable_textures.contains(&idx)
+                && !self.texture_requests.contains_key(&idx)
+            {
+                self.texture_requests.insert(idx, size);
+                // A placeholder until it is read.
+                let summary = !self.exif_summaries.contains_key(&idx);
+                self.exif_summaries.entry(idx).or_default();
+                request_texture(
+                    self.images_sender.clone(),
+                    ctx.clone(),
+                    idx,
+                    image.path.clone(),
+                    size,
+                    summary,
+                );
+            }
+        }
+    }
+
+    // Drops the textures of the images outside of `keep`, they are loaded again from the thumbnail
+    // cache when needed.
+    fn evict_textures(&mut self, keep: &HashSet<usize>) {
+        let evicted: Vec<usize> = self
+            .textured
+            .iter()
+            .copied()
+            .filter(|idx| !keep.contains(idx))
+            .collect();
+        for idx in evicted {
+            if let Some(Some(image)) = self.images.get_mut(idx) {
+                image.texture = None;
+            }
+            self.textured.remove(&idx);
+            self.texture_requests.remove(&idx);
+        }
+    }
+
+    fn inspect(&mut self, ctx: &egui::Context, idx: usize) {
+        let Some(image) = &self.images[idx] else {
+            return;
+        };
+        self.inspected = Some(idx);
+        self.full_texture = None;
+        self.histogram = None;
+        self.inspector_zoom = 1.0;
+        self.inspector_view = None;
+        // Images are decoded without applying their orientation, start from it.
+        self.inspector_orientation = exif::orientation(&image.path);
+        self.inspector_rotation = self
+            .inspector_orientation
+            .and_then(|orientation| exif::ORIENTATIONS.iter().position(|o| *o == orientation))
+            .unwrap_or(0);
+        // Twice the screen leaves some detail when zooming in, more would only take VRAM.
+        let screen = ctx.input().screen_rect().size() * ctx.pixels_per_point();
+        let max_side = (2.0 * screen.x.max(screen.y)) as u32;
+        let sender = self.images_sender.clone();
+        let ctx = ctx.clone();
+        let path = image.path.clone();
+        rayon::spawn(move || match open_image(&path) {
+            Ok(image) => {
+                let rgba = image.to_rgba8();
+                let texture = rgba_to_texture(&ctx, &path, &rgba, max_side);
+                let histogram = Box::new(histogram::Histogram::of(&rgba));
+                let _ = sender.send(Message::SetFullTexture(idx, texture, histogram));
+                ctx.request_repaint();
+            }
+            Err(err) => error!("Failed to load {}: {}", path, err),
+        });
+    }
+
+    // Returns the journal entry of the change, `None` when it failed. Undoing it writes the
+    // previous orientation back the same way.
+    fn write_orientation(&mut self, idx: usize, orientation: u16) -> Option<journal::JournalEntry> {
+        let image = self.images[idx].as_ref()?;
+        let previous = exif::orientation(&image.path).unwrap_or(1);
+        let mut entry = journal::JournalEntry::new(
+            journal::JournalAction::Orient,
+            &image.path,
+            self.settings.scan.hash_alg,
+            image.hash.to_base64(),
+            image.dimensions,
+            None,
+        );
+        entry.orientation = Some((previous, orientation));
+        if let Err(err) = exif::write_orientation(&entry.path, orientation) {
+            error!("Failed to set the orientation of {}: {}", entry.path, err);
+            self.errors.push((entry.path, err));
+            return None;
+        }
+        info!("Set the orientation of {} to {}", entry.path, orientation);
+        journal::append(&entry);
+        if self.inspected == Some(idx) {
+            self.inspector_orientation = Some(orientation);
+            if let Some(rotation) = exif::ORIENTATIONS.iter().position(|o| *o == orientation) {
+                self.inspector_rotation = rotation;
+            }
+        }
+        Some(entry)
+    }
+
+    fn image_index(&self, path: &str) -> Option<usize> {
+        self.images
+            .iter()
+            .position(|image| image.as_ref().is_some_and(|image| image.path == path))
+    }
+
+    fn push_action(&mut self, action: ReviewAction) {
+        self.undo_stack.push(action);
+        self.redo_stack.clear();
+    }
+
+    fn undo(&mut self) {
+        let Some(action) = self.undo_stack.pop() else {
+            return;
+        };
+        info!("Undoing {:?}", action);
+        let (path, result) = match &action {
+            ReviewAction::Trash(entry) => (
+                entry.path.clone(),
+                self.restore_trashed(vec![entry.clone()]),
+            ),
+            ReviewAction::Replace(entry) => (entry.path.clone(), self.undo_replace(entry)),
+            // Nothing was reclaimed, and `write_orientation` reports its own errors.
+            ReviewAction::Orient(entry) => {
+                let (previous, _) = entry.orientation.unwrap_or((1, 1));
+                let Some(idx) = self.image_index(&entry.path) else {
+                    return;
+                };
+                if self.write_orientation(idx, previous).is_some() {
+                    self.redo_stack.push(action);
+                }
+                return;
+            }
+            ReviewAction::Dismiss(a, b) => {
+                let (Some(i), Some(j)) = (self.image_index(a), self.image_index(b)) else {
+                    return;
+                };
+                self.set_dismissed((i, j), false);
+                self.redo_stack.push(action);
+                return;
+            }
+            ReviewAction::Mark(path, marked) => {
+                let Some(idx) = self.image_index(path) else {
+                    return;
+                };
+                self.set_marked(idx, !marked);
+                self.redo_stack.push(action);
+                return;
+            }
+        };
+        match result {
+            Ok(()) => {
+                let size = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+                self.reclaimed_bytes = self.reclaimed_bytes.saturating_sub(size);
+                self.history_dirty = true;
+                self.redo_stack.push(action);
+            }
+            Err(err) => {
+                error!("Failed to undo: {}", err);
+                self.errors.push((path, err));
+            }
+        }
+    }
+
+    fn redo(&mut self) {
+        let Some(action) = self.redo_stack.pop() else {
+            return;
+        };
+        info!("Redoing {:?}", action);
+        match action {
+            ReviewAction::Trash(entry) => {
+                let Some(idx) = self.image_index(&entry.path) else {
+                    return;
+                };
+                let kept_idx = entry
+                    .kept
+                    .as_deref()
+                    .and_then(|kept| self.image_index(kept));
+                // Confirmed again like any other, e.g. it would now be deleted for good.
+                if self.trash_warning(idx).is_some() {
+                    match kept_idx {
+                        Some(kept_idx) => self.trash_or_confirm(idx, kept_idx),
+                        None => {
+                            info!("Not redoing, {} needs a confirmation", entry.path);
+                            self.redo_stack.push(ReviewAction::Trash(entry));
+                        }
+                    }
+                    return;
+                }
+                if let Some(entry) = self.trash_image(idx, kept_idx) {
+                    self.undo_stack.push(ReviewAction::Trash(entry));
+                }
+            }
+            ReviewAction::Replace(entry) => {
+                let mode = match entry.action {
+                    journal::JournalAction::Hardlink => ReplaceMode::Hardlink,
+                    journal::JournalAction::Symlink => ReplaceMode::Symlink,
+                    journal::JournalAction::Move => ReplaceMode::Move,
+                    _ => ReplaceMode::Clone,
+                };
+                let idx = self.image_index(&entry.path);
+                let kept_idx = entry
+                    .kept
+                    .as_deref()
+                    .and_then(|kept| self.image_index(kept));
+                let (Some(idx), Some(kept_idx)) = (idx, kept_idx) else {
+                    return;
+                };
+                if let Some(entry) = self.replace_image(idx, kept_idx, mode) {
+                    self.undo_stack.push(ReviewAction::Replace(entry));
+                }
+            }
+            ReviewAction::Orient(entry) => {
+                let (_, orientation) = entry.orientation.unwrap_or((1, 1));
+                let Some(idx) = self.image_index(&entry.path) else {
+                    return;
+                };
+                if let Some(entry) = self.write_orientation(idx, orientation) {
+                    self.undo_stack.push(ReviewAction::Orient(entry));
+                }
+            }
+            ReviewAction::Dismiss(a, b) => {
+                let (Some(i), Some(j)) = (self.image_index(&a), self.image_index(&b)) else {
+                    return;
+                };
+                self.set_dismissed((i, j), true);
+                self.undo_stack.push(ReviewAction::Dismiss(a, b));
+            }
+            ReviewAction::Mark(path, marked) => {
+                let Some(idx) = self.image_index(&path) else {
+                    return;
+                };
+                self.set_marked(idx, marked);
+                self.undo_stack.push(ReviewAction::Mark(path, marked));
+            }
+        }
+    }
+
+    fn start_scan(&mut self, ctx: &egui::Context, path: PathBuf) {
+        self.start_scan_of(ctx, vec![path]);
+    }
+
+    // Duplicates are found across all the directories.
+    fn start_scan_of(&mut self, ctx: &egui::Context, roots: Vec<PathBuf>) {
+        self.prep_for_analyze(&roots);
+        self.spawn_analyze(ctx, roots, HashSet::new());
+    }
+
+    // The scanned directory an image is in, for the settings relative to it.
+    fn root_of(&self, path: &str) -> &str {
+        self.roots
+            .iter()
+            .map(String::as_str)
+            .filter(|root| Path::new(path).starts_with(root))
+            .max_by_key(|root| root.len())
+            .unwrap_or_default()
+    }
+
+    // Identifies the scanned directories in the history.
+    fn roots_key(&self) -> String {
+        self.roots.join("\n")
+    }
+
+    // Files in `skip` are counted but not hashed again, which is how an interrupted scan resumes.
+    fn spawn_analyze(&mut self, ctx: &egui::Context, roots: Vec<PathBuf>, skip: HashSet<PathBuf>) {
+        let options = self.settings.scan.clone();
+        let pool = match rayon::ThreadPoolBuilder::new()
+            .num_threads(options.threads)
+            .build()
+        {
+            Ok(pool) => Arc::new(pool),
+            Err(err) => {
+                error!("Failed to create the thread pool: {}", err);
+                self.errors.push((self.roots.join(", "), err.to_string()));
+                return;
+            }
+        };
+
+        let cache_mode = self.settings.hash_cache;
+        let shared_cache =
+            Some(self.settings.shared_cache_url.clone()).filter(|url| !url.is_empty());
+        let ctx = ctx.clone();
+        let sender = self.images_sender.clone();
+        // The previous scan, if still running, would keep adding its images to this one.
+        self.scan_control.cancelled.store(true, Ordering::Relaxed);
+        self.scan_control = Arc::default();
+        let control = self.scan_control.clone();
+        let generation = self.scan_generation;
+        // `rayon::scope` inside `analyze` uses the pool it runs on.
+        pool.spawn(move || {
+            analyze(
+                sender,
+                generation,
+                roots,
+                ctx.clone(),
+                options,
+                skip,
+                cache_mode,
+                shared_cache,
+                &control,
+            );
+            control.finished.store(true, Ordering::Relaxed);
+            ctx.request_repaint();
+        });
+        self.scan_pool = Some(pool);
+    }
+
+    fn cancel_scan(&mut self) {
+        info!("Cancelling the scan of {}", self.roots.join(", "));
+        self.scan_control.cancelled.store(true, Ordering::Relaxed);
+        self.scan_cancelled = true;
+        self.autosave_dirty = true;
+    }
+
+    fn toggle_scan_pause(&mut self) {
+        let paused = !self.scan_control.paused.load(Ordering::Relaxed);
+        info!(
+            "{} the scan of {}",
+            if paused { "Pausing" } else { "Resuming" },
+            self.roots.join(", ")
+        );
+        self.scan_control.paused.store(paused, Ordering::Relaxed);
+    }
+
+    fn export_results(&mut self, path: &Path, format: export::ExportFormat) {
+        let result = std::fs::File::create(path).and_then(|file| {
+            let mut out = std::io::BufWriter::new(file);
+            export::write(format, &mut out, &self.images, &self.similar_images)?;
+            out.flush()
+        });
+        match result {
+            Ok(()) => info!("Exported the results to {}", path.display()),
+            Err(err) => {
+                error!(
+                    "Failed to export the results to {}: {}",
+                    path.display(),
+                    err
+                );
+                self.errors
+                    .push((path.to_string_lossy().to_string(), err.to_string()));
+            }
+        }
+    }
+
+    // Both thumbnails with their paths and metadata, and what is known about the pair above.
+    fn save_comparison(&mut self, (i, j): (usize, usize), path: &Path) {
+        let (Some(a), Some(b)) = (&self.images[i], &self.images[j]) else {
+            return;
+        };
+        let mut header = vec![format!(
+            "Distance {} of {} ({})",
+            a.hash.dist(&b.hash),
+            self.settings.scan.similarity_threshold,
+            self.band_of((i, j)).name()
+        )];
+        let images = [
+            (i, a.path.clone(), a.dimensions),
+            (j, b.path.clone(), b.dimensions),
+        ];
+        if let Some(confidence) = self.confidences.get(&(i, j)) {
+            header.push(format!("{}% confidence", confidence));
+        }
+        match self.identities.get(&(i, j)) {
+            Some(Identity::SamePixels) => {
+                header.push("Identical pixels, different metadata".to_owned())
+            }
+            Some(Identity::SameFile) => header.push("Verified identical".to_owned()),
+            Some(Identity::Corrupted { .. }) => header.push("Possibly corrupted copy".to_owned()),
+            _ => {}
+        }
+        let facts = [self.image_facts(i), self.image_facts(j)];
+        let capture_times = facts.map(|facts| facts.and_then(|facts| facts.capture_time));
+        if let [Some(time_a), Some(time_b)] = capture_times {
+            header.push(format!("Captured {} s apart", (time_a - time_b).abs()));
+        }
+        if let [Some(location_a), Some(location_b)] =
+            facts.map(|facts| facts.and_then(|facts| facts.location))
+        {
+            let apart = map::distance(location_a, location_b);
+            header.push(format!("Taken {} apart", map::format_distance(apart)));
+        }
+        let keeper = self.suggested_keeper((i, j));
+        let side = |(idx, path, (width, height)): &(usize, String, (u32, u32)),
+                    facts: Option<confidence::Facts>| {
+            let image = thumbnail::load(path, 512)
+                .or_else(|_| open_image(path))
+                .map_err(|err| err.to_string())?;
+            let mut lines = vec![path.clone(), format!("{}×{}", width, height)];
+            if let Some(facts) = facts {
+                lines.push(format!("{}", facts.size.bytes()));
+            }
+            if let Some((_, reason)) = keeper.filter(|(keeper, _)| keeper == idx) {
+                lines.push(format!("Suggested keeper: {}", reason));
+            }
+            Ok::<_, String>(snapshot::Side { image, lines })
+        };
+        let result = side(&images[0], facts[0])
+            .and_then(|side_a| Ok([side_a, side(&images[1], facts[1])?]))
+            .and_then(|sides| snapshot::compose(&header, sides))
+            .and_then(|canvas| canvas.save(path).map_err(|err| err.to_string()));
+        match result {
+            Ok(()) => info!("Saved the comparison to {}", path.display()),
+            Err(err) => {
+                error!(
+                    "Failed to save the comparison to {}: {}",
+                    path.display(),
+                    err
+                );
+                self.errors.push((path.to_string_lossy().to_string(), err));
+            }
+        }
+    }
+
+    fn to_session(&self) -> Option<Session> {
+        let (root, extra_roots) = self.roots.split_first()?;
+        let scanned = self.images.len() + self.errors.len();
+        let path = |idx: usize| Some(&self.images.get(idx)?.as_ref()?.path);
+        Some(Session {
+            root: root.clone(),
+            extra_roots: extra_roots.to_vec(),
+            options: self.settings.scan.clone(),
+            images: self
+                .images
+                .iter()
+                .map(|image| {
+                    image.as_ref().map(|image| SessionImage {
+                        path: image.path.clone(),
+                        hash: image.hash.to_base64(),
+                        dimensions: image.dimensions,
+                        frames: image.frames,
+                    })
+                })
+                .collect(),
+            similar_images: self.similar_images.clone(),
+            errors: self.errors.clone(),
+            marked: self
+                .marked
+                .iter()
+                .filter_map(|&idx| Some(path(idx)?.clone()))
+                .collect(),
+            dismissed_pairs: self
+                .dismissed_pairs
+                .iter()
+                .filter_map(|&(i, j)| Some((path(i)?.clone(), path(j)?.clone())))
+                .collect(),
+            analyzed_bytes: self.analyzed_bytes.as_u64(),
+            complete: !self.scan_cancelled
+                && self.found_paths.is_some_and(|total| scanned >= total),
+        })
+    }
+
+    fn autosave(&mut self) {
+        self.autosave_dirty = false;
+        self.last_autosave = Instant::now();
+        let (Some(session), Some(path)) = (self.to_session(), Session::autosave_path()) else {
+            return;
+        };
+        if session.complete && self.history_dirty {
+            self.history_dirty = false;
+            self.record_history(&session);
+        }
+        // One at a time, so that an older session never overwrites a newer one.
+        if let Some(writer) = self.autosave_writer.take() {
+            let _ = writer.join();
+        }
+        self.autosave_writer = Some(std::thread::spawn(move || match session.write(&path) {
+            Ok(()) => debug!("Autosaved the session to {}", path.display()),
+            Err(err) => error!("Failed to autosave the session: {}", err),
+        }));
+    }
+
+    // Once the scan is complete, and again as its duplicates are resolved.
+    fn record_history(&self, session: &Session) {
+        let redundant: Vec<String> = self
+            .redundant_images()
+            .into_iter()
+            .filter_map(|idx| Some(self.images[idx].as_ref()?.path.clone()))
+            .collect();
+        let mut record = history::Record {
+            time: self.scan_started,
+            root: self.roots_key(),
+            total_bytes: session.analyzed_bytes,
+            images: session.images.iter().flatten().count(),
+            duplicates: session.similar_images.len(),
+            reclaimable_bytes: 0,
+            reclaimed_bytes: self.reclaimed_bytes,
+        };
+        let sender = self.images_sender.clone();
+        std::thread::spawn(move || {
+            record.reclaimable_bytes = redundant
+                .iter()
+                .map(|path| std::fs::metadata(path).map_or(0, |metadata| metadata.len()))
+                .sum();
+            match history::save(&record) {
+                Ok(()) => {
+                    let _ = sender.send(Message::HistoryRecorded(record));
+                }
+                Err(err) => error!("Failed to save the statistics history: {}", err),
+            }
+        });
+    }
+
+    fn save_session(&mut self, path: &Path) {
+        let Some(session) = self.to_session() else {
+            return;
+        };
+        match session.write(path) {
+            Ok(()) => info!("Saved the session to {}", path.display()),
+            Err(err) => {
+                error!("Failed to save the session: {}", err);
+                self.errors.push((path.display().to_string(), err));
+            }
+        }
+    }
+
+    fn resume_session(&mut self, ctx: &egui::Context, path: &Path) {
+        match Session::read(path) {
+            Ok(session) => self.recover_session(ctx, session),
+            Err(err) => {
+                error!("Failed to read the session {}: {}", path.display(), err);
+                self.errors.push((path.display().to_string(), err));
+            }
+        }
+    }
+
+    fn compare_sessions(&mut self, old: &Path, new: &Path) {
+        match Session::read(old).and_then(|old| Ok((old, Session::read(new)?))) {
+            Ok((old_session, new_session)) => {
+                let name = |path: &Path| {
+                    path.file_name().map_or(path.display().to_string(), |name| {
+                        name.to_string_lossy().to_string()
+                    })
+                };
+                self.session_diff = Some((
+                    name(old),
+                    name(new),
+                    session::diff(&old_session, &new_session),
+                ));
+            }
+            Err(err) => {
+                error!("Failed to read the sessions: {}", err);
+                self.errors.push(("Compare sessions".to_owned(), err));
+            }
+        }
+    }
+
+    fn recover_session(&mut self, ctx: &egui::Context, session: Session) {
+        info!("Recovering the session of {}", session.root);
+        let roots: Vec<PathBuf> = std::iter::once(&session.root)
+            .chain(&session.extra_roots)
+            .map(PathBuf::from)
+            .collect();
+        self.prep_for_analyze(&roots);
+        self.settings.scan = session.options;
+        self.images = session
+            .images
+            .into_iter()
+            .map(|image| {
+                let image = image?;
+                match img_hash::ImageHash::from_base64(&image.hash) {
+                    Ok(hash) => Some(Image {
+                        path: image.path,
+                        hash,
+                        dimensions: image.dimensions,
+                        frames: image.frames,
+                        texture: None,
+                    }),
+                    Err(err) => {
+                        error!("Invalid hash for {} in the session: {:?}", image.path, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        for (idx, image) in self.images.iter().enumerate() {
+            if let Some(image) = image {
+                let _ = self
+                    .matcher
+                    .send(MatchCommand::Insert(idx, image.hash.clone()));
+            }
+        }
+        let images = &self.images;
+        self.similar_images = session
+            .similar_images
+            .into_iter()
+            .filter(|(i, j)| {
+                matches!(images.get(*i), Some(Some(_))) && matches!(images.get(*j), Some(Some(_)))
+            })
+            .collect();
+        self.pairs_generation += 1;
+        for pair in self.similar_images.clone() {
+            self.queue_identity_check(pair);
+        }
+        let indices: HashMap<&str, usize> = self
+            .images
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, image)| Some((image.as_ref()?.path.as_str(), idx)))
+            .collect();
+        self.marked = session
+            .marked
+            .iter()
+            .filter_map(|path| indices.get(path.as_str()).copied())
+            .collect();
+        self.dismissed_pairs = session
+            .dismissed_pairs
+            .iter()
+            .filter_map(|(a, b)| Some((*indices.get(a.as_str())?, *indices.get(b.as_str())?)))
+            .collect();
+        self.errors = session.errors;
+        self.analyzed_bytes = session.analyzed_bytes.bytes();
+
+        if session.complete {
+            self.found_paths = Some(self.images.len() + self.errors.len());
+        } else {
+            let skip = self
+                .images
+                .iter()
+                .flatten()
+                .map(|image| PathBuf::from(&image.path))
+                .chain(self.errors.iter().map(|(path, _)| PathBuf::from(path)))
+                .collect();
+            self.spawn_analyze(ctx, roots, skip);
+        }
+    }
+}
+
+// The point at `anchor`, as a fraction of the size of the images of the pair view, stays under the
+// pointer.
+fn zoom_pair(zoom: &mut f32, center: &mut egui::Pos2, factor: f32, anchor: Vec2) {
+    let before = pair_uv(*zoom, *center);
+    let point = before.min + anchor * before.size();
+    *zoom = (*zoom * factor).clamp(1.0, PAIR_MAX_ZOOM);
+    let size = 1.0 / *zoom;
+    *center = pair_uv(*zoom, point - anchor * size + Vec2::splat(size / 2.0)).center();
+}
+
+// The part of the images of the pair view shown, in texture coordinates, kept inside them. At
+// 100%, the center does not matter.
+fn pair_uv(zoom: f32, center: egui::Pos2) -> egui::Rect {
+    let half = 0.5 / zoom;
+    let center = egui::pos2(
+        center.x.clamp(half, 1.0 - half),
+        center.y.clamp(half, 1.0 - half),
+    );
+    egui::Rect::from_center_size(center, Vec2::splat(2.0 * half))
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string()
+}
+
+// Images bigger than `max_side`, or than the GPU takes, are downscaled first, e.g. a 40 MP photo in
+// the inspector, which would also take 160 MB of VRAM.
+fn rgba_to_texture(
+    ctx: &egui::Context,
+    name: &str,
+    image: &image::RgbaImage,
+    max_side: u32,
+) -> egui::TextureHandle {
+    let max_side = max_side.min(ctx.input().max_texture_side as u32);
+
+    let resized;
+    let image = if image.width().max(image.height()) > max_side {
+        debug!(
+            "Downscaling {} from {:?} to fit in {} px",
+            name,
+            image.dimensions(),
+            max_side
+        );
+        let scale = max_side as f64 / image.width().max(image.height()) as f64;
+        resized = image::imageops::thumbnail(
+            image,
+            ((image.width() as f64 * scale) as u32).max(1),
+            ((image.height() as f64 * scale) as u32).max(1),
+        );
+        &resized
+    } else {
+        image
+    };
+    let (width, height) = image.dimensions();
+    ctx.load_texture(
+        name,
+        egui::ColorImage::from_rgba_unmultiplied([width as usize, height as usize], image),
+        Default::default(),
+    )
+}
+
+fn request_diff(
+    sender: std::sync::mpsc::Sender<Message>,
+    ctx: egui::Context,
+    pair: (usize, usize),
+    paths: (String, String),
+) {
+    rayon::spawn(move || {
+        let result = thumbnail::load(&paths.0, DIFF_SIZE)
+            .and_then(|a| Ok((a, thumbnail::load(&paths.1, DIFF_SIZE)?)))
+            .map(|(a, b)| {
+                let (image, mean) = diff::difference(&a, &b);
+                let name = format!("diff {} {}", paths.0, paths.1);
+                (rgba_to_texture(&ctx, &name, &image, DIFF_SIZE), mean)
+            })
+            .map_err(|err| err.to_string());
+        let _ = sender.send(Message::DiffComputed(pair, result));
+        ctx.request_repaint();
+    });
+}
+
+// The EXIF summary is read along when `summary` is set, so that the pair view does not wait on it.
+fn request_texture(
+    sender: std::sync::mpsc::Sender<Message>,
+    ctx: egui::Context,
+    idx: usize,
+    path: String,
+    size: u32,
+    summary: bool,
+) {
+    rayon::spawn(move || {
+        if summary {
+            let _ = sender.send(Message::ExifSummary(idx, exif::summary_of(&path)));
+        }
+        match thumbnail::load(&path, size) {
+            Ok(image) => {
+                let texture = rgba_to_texture(&ctx, &path, &image.to_rgba8(), size);
+                let _ = sender.send(Message::SetTexture(idx, size, texture));
+                ctx.request_repaint();
+            }
+            Err(err) => {
+                error!("Failed to load the texture of {}: {}", path, err);
+                let _ = sender.send(Message::TextureUnavailable(idx));
+                ctx.request_repaint();
+            }
+        }
+    });
+}
+
+fn open_image(path: &str) -> image::ImageResult<image::DynamicImage> {
+    let buffer = storage::read(path).map_err(ImageError::IoError)?;
+    formats::load(Path::new(path), &buffer)
+}
+
+fn has_known_extension(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        KNOWN_EXTENSIONS
+            .iter()
+            .chain(&formats::EXTRA_EXTENSIONS)
+            .chain(&formats::RAW_EXTENSIONS)
+            .chain(&formats::VIDEO_EXTENSIONS)
+            .any(|x| extension.eq_ignore_ascii_case(x))
+    })
+}
+
+fn is_known_image(entry: &DirEntry) -> bool {
+    entry.file_type().is_file() && has_known_extension(entry.path())
+}
+
+// Returns the decoded image, the dimensions of the full image, which the decoded image is smaller
+// than when the format supports decoding at a reduced size, and its number of frames. With
+// `color_management`, images with an ICC profile are converted to sRGB.
+fn decode_for_hashing(
+    path: &Path,
+    buffer: &[u8],
+    hash_alg: HashAlgorithm,
+    color_management: bool,
+) -> image::ImageResult<(image::DynamicImage, (u32, u32), u32)> {
+    // pHash only matches libpHash's values when computed on the full image. JPEGs the `image`
+    // crate cannot decode, e.g. arithmetic coded ones, get the fallbacks of `formats::load`.
+    let reduced = if hash_alg != HashAlgorithm::PHash
+        && image::guess_format(buffer).ok() == Some(image::ImageFormat::Jpeg)
+    {
+        reduced_jpeg(buffer).ok()
+    } else {
+        None
+    };
+    let (mut image, dimensions, frames) = match reduced {
+        Some((image, dimensions)) => (image, dimensions, 1),
+        None => {
+            let (image, frames) = formats::load_for_hashing(path, buffer)?;
+            let dimensions = image.dimensions();
+            (image, dimensions, frames)
+        }
+    };
+    if color_management {
+        image = icc::to_srgb(image, buffer);
+    }
+    Ok((hash_input(image, hash_alg), dimensions, frames))
+}
+
+// Decodes at about `REDUCED_DECODE_SIZE`, also returns the full dimensions.
+fn reduced_jpeg(buffer: &[u8]) -> image::ImageResult<(image::DynamicImage, (u32, u32))> {
+    let mut decoder = image::codecs::jpeg::JpegDecoder::new(std::io::Cursor::new(buffer))?;
+    let dimensions = decoder.dimensions();
+    decoder.scale(REDUCED_DECODE_SIZE, REDUCED_DECODE_SIZE)?;
+    Ok((image::DynamicImage::from_decoder(decoder)?, dimensions))
+}
+
+// The mean and gradient hashes only look at the luma. Converting here drops the colour image
+// before hashing, instead of `img_hash` making a luma copy while the colour image is still alive.
+// Blockhash uses the colour channels, and pHash has its own luma formula.
+fn hash_input(image: image::DynamicImage, hash_alg: HashAlgorithm) -> image::DynamicImage {
+    match hash_alg {
+        HashAlgorithm::Mean
+        | HashAlgorithm::Gradient
+        | HashAlgorithm::DoubleGradient
+        | HashAlgorithm::VertGradient => image::DynamicImage::ImageLuma8(image.into_luma8()),
+        HashAlgorithm::Blockhash | HashAlgorithm::PHash => image,
+    }
+}
+
+fn hash_image(image: &image::DynamicImage, hash_alg: HashAlgorithm) -> img_hash::ImageHash {
+    match hash_alg.to_img_hash() {
+        Some(alg) => HasherConfig::new()
+            .hash_size(HASH_SIZE, HASH_SIZE)
+            .hash_alg(alg)
+            .to_hasher()
+            .hash_image(image),
+        // Little endian, so that bit `i` of the pHash value is bit `i` of the `ImageHash`.
+        None => img_hash::ImageHash::from_bytes(&phash::phash(image).to_le_bytes()).unwrap(),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn analyze(
+    sender: std::sync::mpsc::Sender<Message>,
+    generation: u64,
+    roots: Vec<PathBuf>,
+    ctx: egui::Context,
+    options: ScanOptions,
+    skip: HashSet<PathBuf>,
+    cache_mode: HashCacheMode,
+    shared_cache: Option<String>,
+    control: &ScanControl,
+) {
+    let shared_cache = shared_cache.map(netcache::Shared::new);
+    // Remote storage is scanned on its own.
+    if let [path] = roots.as_slice() {
+        match storage::open(&path.to_string_lossy()) {
+            Some(Ok((backend, prefix))) => {
+                analyze_remote(
+                    sender, generation, &*backend, &prefix, ctx, options, skip, control,
+                );
+                return;
+            }
+            Some(Err(err)) => {
+                error!("Failed to open {}: {}", path.display(), err);
+                let _ = sender.send(Message::AddImage(
+                    generation,
+                    0.bytes(),
+                    Err((
+                        path.to_string_lossy().to_string(),
+                        ImageError::IoError(std::io::Error::other(err)),
+                    )),
+                ));
+                let _ = sender.send(Message::WalkDirFinished(generation, 0));
+                return;
+            }
+            None => {}
+        }
+    }
+    let cache = Mutex::new(HashCache::load(cache_mode));
+    let queue = queue::Queue::default();
+    let mut sampler = sequence::Sampler::default();
+    let excluded = exclude::Patterns::new(&options.exclude);
+    // Once each, should a directory be inside another.
+    let mut walked = HashSet::new();
+    // With the directory they were found in, sorted, so that the same frames of a sequence are
+    // sampled from one scan to the next.
+    let entries: Vec<(DirEntry, u64, String)> = roots
+        .iter()
+        .flat_map(|root| {
+            let root_name = root.to_string_lossy().to_string();
+            options
+                .walk(root)
+                .sort_by_file_name()
+                .into_iter()
+                // Excluded directories are not walked at all.
+                .filter_entry(|entry| !excluded.excludes(root, entry.path()))
+                .filter_map(|e| match e {
+                    Ok(entry) => Some(entry),
+                    // Symbolic links back to a parent directory when following them.
+                    Err(err) if err.loop_ancestor().is_some() => {
+                        info!("Not following the loop {}", err);
+                        None
+                    }
+                    Err(_) => None,
+                })
+                .map(move |entry| (entry, root_name.clone()))
+        })
+        .take_while(|_| control.proceed())
+        .filter(|(entry, _)| is_known_image(entry))
+        // Through links, a file can also be reached from several paths.
+        .filter(|(entry, _)| {
+            let path = if options.follow_links {
+                std::fs::canonicalize(entry.path()).unwrap_or_else(|_| entry.path().to_path_buf())
+            } else {
+                entry.path().to_path_buf()
+            };
+            walked.insert(path)
+        })
+        .map(|(entry, root)| {
+            let size = entry.metadata().ok().map(|metadata| metadata.len());
+            (entry, size, root)
+        })
+        // Neither hashed nor counted, files out of the size range are not worth reporting.
+        .filter(|(_, size, _)| size.is_none_or(|size| options.size_in_range(size)))
+        .filter(|(entry, _, _)| sampler.keep(entry.path(), options.sequence_step))
+        .map(|(entry, size, root)| (entry, size.unwrap_or(0), root))
+        .collect();
+    let _ = sender.send(Message::WalkDirFinished(generation, entries.len()));
+    ctx.request_repaint();
+
+    // Skipped files are in the results already, they may have copies among the others.
+    let files: Vec<(&Path, u64)> = entries
+        .iter()
+        .map(|(entry, size, _)| (entry.path(), *size))
+        .collect();
+    let _ = sender.send(Message::IdenticalFiles(
+        generation,
+        exact::groups(&files, control),
+    ));
+    ctx.request_repaint();
+
+    // The scope only returns once every image is hashed, so the cache is complete when saved.
+    rayon::scope(|scope| {
+        for (entry, size, root) in entries {
+            if skip.contains(entry.path()) {
+                continue;
+            }
+            let prioritized = options.is_prioritized(&root, &entry.path().to_string_lossy());
+            queue.push((prioritized, size), entry);
+            let ctx = ctx.clone();
+            let sender = sender.clone();
+            let options = &options;
+            let cache = &cache;
+            let queue = &queue;
+            let shared_cache = shared_cache.as_ref();
+            scope.spawn(move |_| {
+                if !control.proceed() {
+                    return;
+                }
+                if let Some(entry) = queue.pop() {
+                    analyze_image(entry, sender, generation, ctx, options, cache, shared_cache);
+                }
+            });
+        }
+    });
+    cache.into_inner().unwrap().save();
+}
+
+// Objects are listed, then downloaded whole on the scan pool, whose size bounds the number of
+// concurrent downloads. They are not cached, the hash cache is keyed by file system metadata.
+#[allow(clippy::too_many_arguments)]
+fn analyze_remote(
+    sender: std::sync::mpsc::Sender<Message>,
+    generation: u64,
+    backend: &dyn storage::Backend,
+    prefix: &str,
+    ctx: egui::Context,
+    options: ScanOptions,
+    skip: HashSet<PathBuf>,
+    control: &ScanControl,
+) {
+    let mut objects = match backend.list(prefix) {
+        Ok(objects) => objects,
+        Err(err) => {
+            let url = backend.url(prefix);
+            error!("Failed to list {}: {}", url, err);
+            let _ = sender.send(Message::AddImage(
+                generation,
+                0.bytes(),
+                Err((url, ImageError::IoError(std::io::Error::other(err)))),
+            ));
+            let _ = sender.send(Message::WalkDirFinished(generation, 0));
+            return;
+        }
+    };
+    objects.sort_by(|a, b| a.key.cmp(&b.key));
+    let excluded = exclude::Patterns::new(&options.exclude);
+    // Keys have no directories, only `/` separators.
+    let depth = |key: &str| {
+        key.strip_prefix(prefix)
+            .unwrap_or(key)
+            .trim_start_matches('/')
+            .split('/')
+            .count()
+    };
+    objects.retain(|object| {
+        !excluded.excludes(Path::new(prefix), Path::new(&object.key))
+            && options.size_in_range(object.size)
+            && (options.max_depth == 0 || depth(&object.key) <= options.max_depth)
+    });
+    let queue = queue::Queue::default();
+    let mut paths_count = 0usize;
+    let mut sampler = sequence::Sampler::default();
+    rayon::scope(|scope| {
+        objects
+            .into_iter()
+            .filter(|object| has_known_extension(Path::new(&object.key)))
+            .filter(|object| sampler.keep(Path::new(&object.key), options.sequence_step))
+            .take_while(|_| control.proceed())
+            .for_each(|object| {
+                paths_count += 1;
+                if skip.contains(Path::new(&backend.url(&object.key))) {
+                    return;
+                }
+                let prioritized = options.is_prioritized(prefix, &object.key);
+                queue.push((prioritized, object.size), object);
+                let ctx = ctx.clone();
+                let sender = sender.clone();
+                let options = &options;
+                let queue = &queue;
+                scope.spawn(move |_| {
+                    if !control.proceed() {
+                        return;
+                    }
+                    if let Some(object) = queue.pop() {
+                        analyze_object(backend, object, sender, generation, ctx, options);
+                    }
+                });
+            });
+        let _ = sender.send(Message::WalkDirFinished(generation, paths_count));
+    });
+}
+
+fn analyze_object(
+    backend: &dyn storage::Backend,
+    object: storage::Object,
+    sender: std::sync::mpsc::Sender<Message>,
+    generation: u64,
+    ctx: egui::Context,
+    options: &ScanOptions,
+) {
+    let path = backend.url(&object.key);
+    let too_small_error =
+        || ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError));
+
+    info!("Downloading and hashing {}", path);
+    let result = backend
+        .read(&object.key)
+        .map_err(|err| ImageError::IoError(std::io::Error::other(err)))
+        .and_then(|buffer| {
+            decode_for_hashing(
+                Path::new(&path),
+                &buffer,
+                options.hash_alg,
+                options.color_management,
+            )
+        });
+    let image = match result {
+        Ok((_, dimensions, _)) if too_small(dimensions, options) => {
+            debug!("Skipping {}: {:?} is too small", path, dimensions);
+            Err((path, too_small_error()))
+        }
+        Ok((decoded, dimensions, frames)) => Ok(Image {
+            hash: hash_image(&decoded, options.hash_alg),
+            path,
+            dimensions,
+            frames,
+            texture: None,
+        }),
+        Err(err) => {
+            error!("Failed to hash {}: {}", path, err);
+            Err((path, err))
+        }
+    };
+    let _ = sender.send(Message::AddImage(generation, object.size.bytes(), image));
+    ctx.request_repaint();
+}
+
+// The deepest directory containing all of them.
+fn common_ancestor(paths: &[String]) -> PathBuf {
+    let mut ancestor = PathBuf::from(&paths[0]);
+    for path in &paths[1..] {
+        while !Path::new(path).starts_with(&ancestor) {
+            if !ancestor.pop() {
+                break;
+            }
+        }
+    }
+    ancestor
+}
+
+// Screen readers would read the icon of icon-only buttons out loud.
+fn named(response: egui::Response, name: &str) -> egui::Response {
+    response.widget_info(|| WidgetInfo::labeled(WidgetType::Button, name));
+    response
+}
+
+fn too_small((width, height): (u32, u32), options: &ScanOptions) -> bool {
+    width.min(height) < options.min_dimension
+}
+
+fn analyze_image(
+    entry: DirEntry,
+    sender: std::sync::mpsc::Sender<Message>,
+    generation: u64,
+    ctx: egui::Context,
+    options: &ScanOptions,
+    cache: &Mutex<HashCache>,
+    shared_cache: Option<&netcache::Shared>,
+) {
+    let path = entry.path();
+
+    let metadata = entry.metadata().ok();
+    if let Some(metadata) = &metadata {
+        let cached =
+            cache
+                .lock()
+                .unwrap()
+                .get(path, metadata, options.hash_alg, options.color_management);
+        if let Some(cached) = cached.filter(|cached| !too_small(cached.dimensions, options)) {
+            match img_hash::ImageHash::from_base64(&cached.hash) {
+                Ok(hash) => {
+                    debug!("{} found in the hash cache", path.display());
+                    let _ = sender.send(Message::AddImage(
+                        generation,
+                        metadata.len().bytes(),
+                        Ok(Image {
+                            hash,
+                            path: path.to_string_lossy().to_string(),
+                            dimensions: cached.dimensions,
+                            frames: cached.frames,
+                            texture: None,
+                        }),
+                    ));
+                    ctx.request_repaint();
+                    return;
+                }
+                Err(err) => error!("Invalid cached hash for {:?}: {:?}", path, err),
+            }
+        }
+    }
+
+    // Only the header is read, which is much cheaper than decoding the whole image.
+    if options.min_dimension > 0 {
+        if let Ok(dimensions) = image::image_dimensions(path) {
+            if too_small(dimensions, options) {
+                debug!("Skipping {}: {:?} is too small", path.display(), dimensions);
+                let _ = sender.send(Message::AddImage(
+                    generation,
+                    metadata.map_or(0, |metadata| metadata.len()).bytes(),
+                    Err((
+                        path.to_string_lossy().to_string(),
+                        ImageError::Limits(LimitError::from_kind(LimitErrorKind::DimensionError)),
+                    )),
+                ));
+                return;
+            }
+        }
+    }
+
+    info!("Hashing {}", path.display());
+    // ffmpeg reads videos itself, they can be far bigger than images.
+    let video = formats::is_video(path);
+    let read = if video {
+        Ok(Vec::new())
+    } else {
+        std::fs::read(path)
+    };
+    let buffer = match read {
+        Err(err) => {
+            error!("Failed to open {:?}: {}", path, err);
+            let _ = sender.send(Message::AddImage(
+                generation,
+                0.bytes(),
+                Err((path.to_string_lossy().to_string(), ImageError::IoError(err))),
+            ));
+            return;
+        }
+        Ok(buffer) => buffer,
+    };
+    let size = metadata
+        .as_ref()
+        .map_or(buffer.len() as u64, |metadata| metadata.len());
+    // Reading the file is cheap next to decoding it.
+    let shared = shared_cache.filter(|_| !video).map(|shared| {
+        let key = netcache::key(options.hash_alg, options.color_management, &buffer);
+        (shared, key)
+    });
+    let from_shared = shared
+        .as_ref()
+        .and_then(|(shared, key)| shared.get(key))
+        .and_then(|entry| {
+            let hash = img_hash::ImageHash::from_base64(&entry.hash).ok()?;
+            Some((hash, entry.dimensions, entry.frames))
+        });
+    let (hash, dimensions, frames) = match from_shared {
+        Some(found) => {
+            debug!("{} found in the shared hash cache", path.display());
+            found
+        }
+        None => {
+            let (decoded, dimensions, frames) =
+                match decode_for_hashing(path, &buffer, options.hash_alg, options.color_management)
+                {
+                    Err(err) => {
+                        error!("Failed to decode image {:?}: {}", path, err);
+                        let _ = sender.send(Message::AddImage(
+                            generation,
+                            size.bytes(),
+                            Err((path.to_string_lossy().to_string(), err)),
+                        ));
+                        return;
+                    }
+                    Ok(img) => img,
+                };
+            let hash = hash_image(&decoded, options.hash_alg);
+            debug!("{} hashed", path.display());
+            if let Some((shared, key)) = &shared {
+                let entry = netcache::Entry {
+                    hash: hash.to_base64(),
+                    dimensions,
+                    frames,
+                };
+                shared.put(key, &entry);
+            }
+            (hash, dimensions, frames)
+        }
+    };
+
+    if let Some(metadata) = &metadata {
+        cache.lock().unwrap().insert(
+            path,
+            CacheEntry {
+                size: metadata.len(),
+                mtime: cache::mtime_millis(metadata),
+                hash_alg: options.hash_alg,
+                hash: hash.to_base64(),
+                dimensions,
+                frames,
+                color_management: options.color_management,
+                decoder: cache::DECODER_VERSION,
+            },
+        );
+    }
+
+    let _ = sender.send(Message::AddImage(
+        generation,
+        size.bytes(),
+        Ok(Image {
+            hash,
+            path: path.to_string_lossy().to_string(),
+            dimensions,
+            frames,
+            // Most images never match anything, so the texture is only loaded once displayed.
+            texture: None,
+        }),
+    ));
+    ctx.request_repaint();
+}
+
+impl eframe::App for MyApp {
+    fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
+        self.layout.update_window(&frame.info().window_info);
+        if let Some(path) = self.pending_scan.take() {
+            self.start_scan(ctx, path);
+        }
+        self.handle_messages();
+        self.rematch_on_threshold_change(ctx);
+        if self.sorted_generation != Some(self.pairs_generation) {
+            self.score_pairs(ctx);
+            self.sort_results(ctx);
+        }
+
+        let command =
+            palette::show(ctx, &mut self.palette).or_else(|| palette::shortcut_pressed(ctx));
+        let swipe = self.swipe.update(ctx);
+        let mut undo = command == Some(Command::Undo);
+        let mut redo = command == Some(Command::Redo);
+        if let Some(command) = command {
+            self.run_command(ctx, command);
+        }
+
+        if let Ok(release) = self.update_receiver.try_recv() {
+            self.available_update = Some(release);
+        }
+        if let Some(release) = &self.available_update {
+            let mut dismissed = false;
+            egui::TopBottomPanel::top("update_banner").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(format!("Version {} is available.", release.tag_name));
+                    ui.hyperlink_to("Changelog", &release.html_url);
+                    dismissed = ui.button("Dismiss").clicked();
+                });
+            });
+            if dismissed {
+                self.available_update = None;
+            }
+        }
+
+        if let Some(session) = &self.recoverable_session {
+            let mut recover = false;
+            let mut discard = false;
+            egui::Window::new("Recover previous session?")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    ui.label("The previous session did not exit cleanly.");
+                    let roots: Vec<&str> = std::iter::once(&session.root)
+                        .chain(&session.extra_roots)
+                        .map(String::as_str)
+                        .collect();
+                    ui.label(format!(
+                        "{}: {} images, {} similar pairs",
+                        roots.join(", "),
+                        session.images.iter().flatten().count(),
+                        session.similar_images.len()
+                    ));
+                    ui.horizontal(|ui| {
+                        recover = ui.button("Recover").clicked();
+                        discard = ui.button("Discard").clicked();
+                    });
+                });
+            if recover {
+                if let Some(session) = self.recoverable_session.take() {
+                    self.recover_session(ctx, session);
+                }
+            } else if discard {
+                self.recoverable_session = None;
+                if let Some(path) = Session::autosave_path() {
+                    let _ = std::fs::remove_file(path);
+                }
+            }
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if Button::new("Open directory…")
+                .min_size(egui::Vec2 { x: 150.0, y: 50.0 })
+                .ui(ui)
+                .on_hover_text("Ctrl+P or Cmd+K for all commands")
+                .clicked()
+                || command == Some(Command::OpenDirectory)
+            {
+                if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                    self.start_scan(ctx, path);
+                }
+            }
+            if ui
+                .button("Load index…")
+                .on_hover_text("Compare with an index exported on another machine")
+                .clicked()
+                || command == Some(Command::LoadIndex)
+            {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("Index", &["json"])
+                    .pick_file()
+                {
+                    self.load_index(path);
+                }
+            }
+            ui.horizontal(|ui| {
+                let url = ui
+                    .add(
+                        egui::TextEdit::singleline(&mut self.remote_url)
+                            .hint_text("s3://bucket/prefix or ssh://user@host/path"),
+                    )
+                    .on_hover_text(
+                        "S3 credentials, region and endpoint (for MinIO and others) come from the \
+                         AWS_* environment variables. SSH uses your ssh agent, keys and config, \
+                         and needs a POSIX shell, cat, rm and GNU find on the server, servers only \
+                         allowing SFTP are not supported",
+                    );
+                url.widget_info(|| {
+                    let mut info = WidgetInfo::text_edit("", &self.remote_url);
+                    info.label = Some("Remote storage URL".to_owned());
+                    info
+                });
+                let entered = url.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+                if ui
+                    .add_enabled(
+                        storage::is_remote(&self.remote_url),
+                        Button::new("Scan remote storage"),
+                    )
+                    .clicked()
+                    || (entered && storage::is_remote(&self.remote_url))
+                {
+                    self.start_scan(ctx, PathBuf::from(&self.remote_url));
+                }
+            });
+            if ui
+                .add_enabled(
+                    !self.similar_images.is_empty(),
+                    Button::new("Export results…"),
+                )
+                .on_hover_text(
+                    "Save the duplicate groups for dupeGuru, or for rmlint to apply the cleanup",
+                )
+                .clicked()
+                || (command == Some(Command::ExportResults) && !self.similar_images.is_empty())
+            {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("dupeGuru results", &["dupeguru"])
+                    .add_filter("CSV", &["csv"])
+                    .add_filter("rmlint JSON", &["json"])
+                    .add_filter("rmlint shell script", &["sh"])
+                    .set_file_name("results.dupeguru")
+                    .save_file()
+                {
+                    self.export_results(&path, export::ExportFormat::from_path(&path));
+                }
+            }
+            if ui
+                .add_enabled(
+                    !self.similar_images.is_empty(),
+                    Button::new("Export pairs…"),
+                )
+                .on_hover_text(
+                    "Save every similar pair with its distance, file sizes and dimensions, to \
+                     post-process them with other tools",
+                )
+                .clicked()
+                || (command == Some(Command::ExportPairs) && !self.similar_images.is_empty())
+            {
+                if let Some(path) = rfd::FileDialog::new()
+                    .add_filter("JSON", &["json"])
+                    .add_filter("CSV", &["csv"])
+                    .set_file_name("pairs.json")
+                    .save_file()
+                {
+                    self.export_results(&path, export::ExportFormat::pairs_from_path(&path));
+                }
+            }
+            let has_images = self.images.iter().any(Option::is_some);
+            if ui
+                .add_enabled(has_images, Button::new("Export checksums…"))
+                .on_hover_text(
+                    "SHA-256 of the kept files, for backup tools or to verify them later with \
+                     sha256sum -c",
+                )
+                .clicked()
+                || (command == Some(Command::ExportChecksums) && has_images)
+            {
+                if let Some(path) = rfd::FileDialog::new()
+                    .set_file_name("SHA256SUMS")
+                    .save_file()
+                {
+                    self.export_manifest(ctx, path);
+                }
+            }
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!self.roots.is_empty(), Button::new("Save session…"))
+                    .on_hover_text(
+                        "To resume the review later, or compare with a later scan of the same \
+                         directory",
+                    )
+                    .clicked()
+                    || (command == Some(Command::SaveSession) && !self.roots.is_empty())
+                {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Session", &["json"])
+                        .set_file_name("session.json")
+                        .save_file()
+                    {
+                        self.save_session(&path);
+                    }
+                }
+                if ui
+                    .button("Resume session…")
+                    .on_hover_text(
+                        "Bring back the results of a saved session without hashing again, and \
+                         finish its scan if it was interrupted",
+                    )
+                    .clicked()
+                    || command == Some(Command::ResumeSession)
+                {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("Session", &["json"])
+                        .pick_file()
+                    {
+                        self.resume_session(ctx, &path);
+                    }
+                }
+                if ui
+                    .button("Compare sessions…")
+                    .on_hover_text(
+                        "New and resolved duplicates, added and removed files, between two saved \
+                         sessions of the same directory",
+                    )
+                    .clicked()
+                    || command == Some(Command::CompareSessions)
+                {
+                    let pick = |title: &str| {
+                        rfd::FileDialog::new()
+                            .add_filter("Session", &["json"])
+                            .set_title(title)
+                            .pick_file()
+                    };
+                    if let Some(old) = pick("Older session") {
+                        if let Some(new) = pick("Newer session") {
+                            self.compare_sessions(&old, &new);
+                        }
+                    }
+                }
+            });
+            if ui
+                .add_enabled(!self.roots.is_empty(), Button::new("Disk usage…"))
+                .on_hover_text("Where the space goes, and how much of it redundant copies take")
+                .clicked()
+            {
+                self.open_treemap(ctx);
+            }
+            if ui
+                .add_enabled(
+                    self.cloud_progress.is_none() && self.images.iter().any(Option::is_some),
+                    Button::new("Compare with Google Photos…"),
+                )
+                .on_hover_text(
+                    "Which images are already uploaded, among the photos picked in Google Photos. \
+                     Needs an OAuth client in IMG_DEDUP_GOOGLE_CLIENT_ID and \
+                     IMG_DEDUP_GOOGLE_CLIENT_SECRET",
+                )
+                .clicked()
+            {
+                self.compare_with_cloud(ctx);
+            }
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("profile")
+                    .selected_text("Apply…")
+                    .show_ui(ui, |ui| {
+                        for profile in &self.settings.profiles {
+                            if ui
+                                .selectable_label(self.settings.scan == profile.scan, &profile.name)
+                                .clicked()
+                            {
+                                self.settings.scan = profile.scan.clone();
+                            }
+                        }
+                    });
+                let name = ui.text_edit_singleline(&mut self.new_profile_name);
+                let save = ui.add_enabled(
+                    !self.new_profile_name.trim().is_empty(),
+                    Button::new("Save as profile"),
+                );
+                name.labelled_by(save.id);
+                if save.clicked() {
+                    let name = self.new_profile_name.trim().to_owned();
+                    self.settings.save_profile(&name);
+                    if let Err(err) = self.settings.save() {
+                        error!("Failed to save settings: {}", err);
+                        self.errors.push(("settings".to_owned(), err));
+                    }
+                    self.new_profile_name.clear();
+                }
+            });
+            ui.add(
+                Slider::new(&mut self.settings.scan.similarity_threshold, 0..=100)
+                    .text("similarity threshold"),
+            );
+            ui.horizontal(|ui| {
+                let label = ui.label("Leave out pairs whose aspect ratios differ by more than");
+                ui.add(
+                    egui::DragValue::new(&mut self.settings.scan.aspect_ratio_tolerance)
+                        .suffix(" %"),
+                )
+                .labelled_by(label.id);
+                ui.label("(0: keep all)");
+            });
+            ui.horizontal(|ui| {
+                // In KiB and MiB here, the settings are in bytes.
+                let mut min = self.settings.scan.min_file_size / 1024;
+                let mut max = self.settings.scan.max_file_size / (1024 * 1024);
+                let label = ui.label("Only hash files from");
+                if ui
+                    .add(egui::DragValue::new(&mut min).suffix(" KiB"))
+                    .labelled_by(label.id)
+                    .changed()
+                {
+                    self.settings.scan.min_file_size = min * 1024;
+                }
+                let label = ui.label("to");
+                if ui
+                    .add(egui::DragValue::new(&mut max).suffix(" MiB"))
+                    .labelled_by(label.id)
+                    .changed()
+                {
+                    self.settings.scan.max_file_size = max * 1024 * 1024;
+                }
+                ui.label("(0: no limit)");
+            });
+            ui.horizontal(|ui| {
+                let label = ui.label("Walk subdirectories down to depth");
+                ui.add(egui::DragValue::new(&mut self.settings.scan.max_depth))
+                    .labelled_by(label.id)
+                    .on_hover_text("1 for only the files of the scanned directory");
+                ui.label("(0: all)");
+            });
+            ui.checkbox(
+                &mut self.settings.scan.follow_links,
+                "Follow symbolic links to directories and images",
+            );
+            ui.collapsing("Folder thresholds", |ui| {
+                let mut removed = None;
+                for (i, folder) in self.settings.scan.folder_thresholds.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        let prefix = ui
+                            .text_edit_singleline(&mut folder.prefix)
+                            .on_hover_text("Relative to the scanned directory, or absolute");
+                        ui.add(Slider::new(&mut folder.threshold, 0..=100))
+                            .labelled_by(prefix.id);
+                        if named(ui.button("🗑"), "Remove folder threshold").clicked() {
+                            removed = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = removed {
+                    self.settings.scan.folder_thresholds.remove(i);
+                }
+                if ui.button("Add folder").clicked() {
+                    self.settings
+                        .scan
+                        .folder_thresholds
+                        .push(settings::FolderThreshold {
+                            prefix: String::new(),
+                            threshold: self.settings.scan.similarity_threshold,
+                        });
+                }
+            });
+            ui.collapsing("Exclude", |ui| {
+                ui.label(
+                    "Left out of scans, e.g. **/node_modules/** or *.thumb.jpg. Relative to the \
+                     scanned directory, re: for a regular expression.",
+                );
+                let mut removed = None;
+                for (i, pattern) in self.settings.scan.exclude.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(pattern);
+                        if named(ui.button("🗑"), "Remove exclude pattern").clicked() {
+                            removed = Some(i);
+                        }
+                        if let Some(err) = exclude::check(pattern) {
+                            ui.colored_label(ui.visuals().warn_fg_color, "⚠ Invalid")
+                                .on_hover_text(err);
+                        }
+                    });
+                }
+                if let Some(i) = removed {
+                    self.settings.scan.exclude.remove(i);
+                }
+                if ui.button("Add pattern").clicked() {
+                    self.settings.scan.exclude.push(String::new());
+                }
+            });
+            ui.collapsing("Priority folders", |ui| {
+                ui.label(
+                    "Hashed first, then the largest files, so that their duplicates show up early.",
+                );
+                let mut removed = None;
+                for (i, folder) in self.settings.scan.priority_folders.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(folder)
+                            .on_hover_text("Relative to the scanned directory, or absolute");
+                        if named(ui.button("🗑"), "Remove priority folder").clicked() {
+                            removed = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = removed {
+                    self.settings.scan.priority_folders.remove(i);
+                }
+                if ui.button("Add folder").clicked() {
+                    self.settings.scan.priority_folders.push(String::new());
+                }
+            });
+            ui.add(
+                Slider::new(&mut self.settings.scan.messaging_threshold, 0..=100)
+                    .text("threshold for messaging app copies (off when below the other)"),
+            );
+            egui::ComboBox::from_label("hash algorithm")
+                .selected_text(self.settings.scan.hash_alg.name())
+                .show_ui(ui, |ui| {
+                    for alg in HashAlgorithm::ALL {
+                        ui.selectable_value(&mut self.settings.scan.hash_alg, alg, alg.name());
+                    }
+                });
+            ui.checkbox(
+                &mut self.settings.scan.color_management,
+                "Convert images with a color profile to sRGB (slower)",
+            );
+            ui.horizontal(|ui| {
+                let label = ui.label("Hash every");
+                ui.add(egui::DragValue::new(&mut self.settings.scan.sequence_step))
+                    .labelled_by(label.id);
+                ui.label("th frame of EXR sequences (0: treat frames as images)");
+            });
+            ui.checkbox(
+                &mut self.settings.scan.dcim_layout,
+                "Phone/camera dump: suggest keeping DCIM copies, tell IMG_E edits apart",
+            );
+
+            let scanned = self.images.len() + self.errors.len();
+            let similar = self.similar_images.len();
+            if let Some(total) = self.found_paths {
+                ui.label(format!(
+                    "Analyzed {}/{} ({:.2})",
+                    scanned, total, self.analyzed_bytes
+                ));
+                let progress = scanned as f32 / total as f32;
+                ui.add(egui::ProgressBar::new(progress).show_percentage())
+                    .widget_info(|| {
+                        let mut info = WidgetInfo::labeled(
+                            WidgetType::Other,
+                            format!("Analyzed {} of {} images", scanned, total),
+                        );
+                        info.value = Some(progress as f64);
+                        info
+                    });
+                ui.label(format!("Similar: {}/{}", similar, total * (total - 1) / 2));
+            } else {
+                ui.label(format!(
+                    "Analyzed {}/? ({:.2})",
+                    scanned, self.analyzed_bytes
+                ));
+                ui.label(format!("Similar: {}/?", similar));
+            }
+            // Images from an index are counted in `found_paths` too, the scan tells itself when it
+            // is done.
+            let running =
+                self.scan_pool.is_some() && !self.scan_control.finished.load(Ordering::Relaxed);
+            if self.scan_cancelled {
+                ui.label("Scan cancelled, the images hashed so far are kept");
+            } else if running {
+                ui.horizontal(|ui| {
+                    let paused = self.scan_control.paused.load(Ordering::Relaxed);
+                    if ui
+                        .button(if paused {
+                            "▶ Resume scan"
+                        } else {
+                            "⏸ Pause scan"
+                        })
+                        .on_hover_text("Files being hashed are finished first")
+                        .clicked()
+                    {
+                        self.toggle_scan_pause();
+                    }
+                    if ui.button("⏹ Cancel scan").clicked() {
+                        self.cancel_scan();
+                    }
+                    if paused {
+                        ui.label("Paused");
+                    }
+                });
+            }
+
+            ui.collapsing("Settings", |ui| {
+                ui.checkbox(
+                    &mut self.settings.check_for_updates,
+                    "Check for updates on startup",
+                );
+                egui::ComboBox::from_label("hash cache")
+                    .selected_text(self.settings.hash_cache.name())
+                    .show_ui(ui, |ui| {
+                        for mode in HashCacheMode::ALL {
+                            ui.selectable_value(&mut self.settings.hash_cache, mode, mode.name());
+                        }
+                    });
+                ui.horizontal(|ui| {
+                    let label = ui.label("Shared hash cache");
+                    ui.text_edit_singleline(&mut self.settings.shared_cache_url)
+                        .labelled_by(label.id)
+                        .on_hover_text(
+                            "URL of a cache shared with other machines, e.g. served by \
+                             img-dedup cache-server on a NAS",
+                        );
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Maintain hash cache").clicked() {
+                        self.cache_report = Some("Running…".to_owned());
+                        let sender = self.images_sender.clone();
+                        let ctx = ctx.clone();
+                        std::thread::spawn(move || {
+                            let report = cache::maintain(1000).map(|report| report.to_string());
+                            let _ = sender.send(Message::CacheMaintained(report));
+                            ctx.request_repaint();
+                        });
+                    }
+                    if let Some(report) = &self.cache_report {
+                        ui.label(report);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let mut mib = self.settings.thumbnail_cache_size / (1024 * 1024);
+                    let label = ui.label("Thumbnail cache size:");
+                    if ui
+                        .add(egui::DragValue::new(&mut mib).suffix(" MiB"))
+                        .labelled_by(label.id)
+                        .changed()
+                    {
+                        self.settings.thumbnail_cache_size = mib * 1024 * 1024;
+                    }
+                    if ui.button("Clear thumbnail cache").clicked() {
+                        let freed = thumbnail::cache_size();
+                        self.thumbnail_cache_report = Some(match thumbnail::clear_cache() {
+                            Ok(()) => format!("Freed {}", freed.bytes()),
+                            Err(err) => {
+                                error!("Failed to clear the thumbnail cache: {}", err);
+                                err
+                            }
+                        });
+                    }
+                    if let Some(report) = &self.thumbnail_cache_report {
+                        ui.label(report);
+                    }
+                });
+                ui.checkbox(
+                    &mut self.settings.preserve_metadata,
+                    "Keep timestamps and permissions when replacing files",
+                );
+                ui.checkbox(
+                    &mut self.settings.trash_companions,
+                    "Trash or move Live Photo videos and sidecars (XMP, AAE, JSON) too",
+                );
+                ui.checkbox(
+                    &mut self.settings.delete_permanently,
+                    "Delete duplicates permanently instead of moving them to the trash",
+                )
+                .on_hover_text(
+                    "Faster for huge caches and does not fill the trash, but cannot be undone. \
+                     Every deletion asks for confirmation",
+                );
+                egui::ComboBox::from_label("for pairs verified identical")
+                    .selected_text(self.settings.verified_action.name())
+                    .show_ui(ui, |ui| {
+                        for action in VerifiedAction::ALL {
+                            ui.selectable_value(
+                                &mut self.settings.verified_action,
+                                action,
+                                action.name(),
+                            );
+                        }
+                    });
+                ui.collapsing("Volumes", |ui| {
+                    ui.label("Copies on the volumes listed first are suggested as keepers");
+                    let mut removed = None;
+                    for (i, volume) in self.settings.keep_volumes.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            ui.add(egui::TextEdit::singleline(volume).hint_text("Mount point"))
+                                .widget_info(|| {
+                                    let mut info = WidgetInfo::text_edit("", volume.as_str());
+                                    info.label = Some(format!("Volume {}", i + 1));
+                                    info
+                                });
+                            if named(ui.button("🗑"), "Remove volume").clicked() {
+                                removed = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = removed {
+                        self.settings.keep_volumes.remove(i);
+                    }
+                    if ui.button("Add volume").clicked() {
+                        self.settings.keep_volumes.push(String::new());
+                    }
+                    ui.horizontal(|ui| {
+                        let label = ui.label("Archive volume:");
+                        ui.text_edit_singleline(&mut self.settings.archive_volume)
+                            .labelled_by(label.id)
+                            .on_hover_text(
+                                "Trashing the last copy of an image on it asks for confirmation",
+                            );
+                    });
+                });
+                ui.horizontal(|ui| {
+                    let label = ui.label("Quarantine folder:");
+                    ui.text_edit_singleline(&mut self.settings.quarantine_dir)
+                        .labelled_by(label.id)
+                        .on_hover_text(
+                            "Duplicates are moved there instead of the trash, under their path \
+                             in the scanned directory, e.g. for network shares without a trash. \
+                             Empty for the trash",
+                        );
+                    if ui.button("Browse…").clicked() {
+                        if let Some(dir) = rfd::FileDialog::new().pick_folder() {
+                            self.settings.quarantine_dir = dir.to_string_lossy().to_string();
+                        }
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let label = ui.label("Lightroom catalog:");
+                    let path = ui
+                        .text_edit_singleline(&mut self.settings.lightroom_catalog)
+                        .labelled_by(label.id);
+                    let mut reload = path.lost_focus() && path.changed();
+                    if ui.button("Browse…").clicked() {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("Lightroom catalog", &["lrcat"])
+                            .pick_file()
+                        {
+                            self.settings.lightroom_catalog = path.to_string_lossy().to_string();
+                            reload = true;
+                        }
+                    }
+                    reload |= ui.button("Reload").clicked();
+                    if reload {
+                        self.load_catalog(ctx);
+                    }
+                    if !self.catalog_files.is_empty() {
+                        ui.label(format!("{} referenced files", self.catalog_files.len()));
+                    }
+                    if ui
+                        .add_enabled(
+                            !self.catalog_files.is_empty(),
+                            Button::new("Save removed catalog files…"),
+                        )
+                        .on_hover_text(
+                            "List the trashed files the catalog still references, to remove them \
+                             from it",
+                        )
+                        .clicked()
+                    {
+                        if let Some(path) = rfd::FileDialog::new()
+                            .add_filter("Text", &["txt"])
+                            .set_file_name("removed.txt")
+                            .save_file()
+                        {
+                            self.save_removed_catalog_files(&path);
+                        }
+                    }
+                });
+                ui.label("Hooks");
+                egui::Grid::new("hooks").num_columns(2).show(ui, |ui| {
+                    let label = ui.label("On keep:");
+                    ui.text_edit_singleline(&mut self.settings.hooks.on_keep)
+                        .labelled_by(label.id);
+                    ui.end_row();
+                    let label = ui.label("On trash:");
+                    ui.text_edit_singleline(&mut self.settings.hooks.on_trash)
+                        .labelled_by(label.id);
+                    ui.end_row();
+                    let label = ui.label("On replace:");
+                    ui.text_edit_singleline(&mut self.settings.hooks.on_replace)
+                        .labelled_by(label.id);
+                    ui.end_row();
+                });
+                if ui.button("Save").clicked() {
+                    if let Err(err) = self.settings.save() {
+                        error!("Failed to save settings: {}", err);
+                        self.errors.push(("settings".to_owned(), err));
+                    }
+                }
+            });
+
+            if !self.trashed.is_empty() {
+                let mut to_restore = Vec::new();
+                ui.collapsing(format!("Trash ({})", self.trashed.len()), |ui| {
+                    if ui.button("Restore all").clicked() {
+                        to_restore = self.trashed.clone();
+                    }
+                    egui::ScrollArea::vertical()
+                        .id_source("trash")
+                        .max_height(200.0)
+                        .show(ui, |ui| {
+                            for entry in &self.trashed {
+                                ui.horizontal(|ui| {
+                                    if ui.button("Restore").clicked() {
+                                        to_restore.push(entry.clone());
+                                    }
+                                    ui.monospace(&entry.path);
+                                    if let Some(kept) = &entry.kept {
+                                        ui.label(format!("(duplicate of {})", kept));
+                                    }
+                                });
+                            }
+                        });
+                });
+                if !to_restore.is_empty() {
+                    if let Err(err) = self.restore_trashed(to_restore) {
+                        error!("Failed to restore from the trash: {}", err);
+                        self.errors.push(("trash".to_owned(), err));
+                    }
+                }
+            }
+
+            if !self.errors.is_empty() {
+                let matching = self
+                    .errors
+                    .iter()
+                    .filter(|(path, _)| search::matches(&self.search, path))
+                    .count();
+                let title = if matching > 0 {
+                    format!("Errors ({}, {} matching)", self.errors.len(), matching)
+                } else {
+                    format!("Errors ({})", self.errors.len())
+                };
+                ui.collapsing(title, |ui| {
+                    for (path, err) in &self.errors {
+                        ui.horizontal(|ui| {
+                            let text = egui::RichText::new(format!("{} {}", path, err));
+                            ui.label(if search::matches(&self.search, path) {
+                                text.background_color(SEARCH_HIGHLIGHT)
+                            } else {
+                                text
+                            });
+                            if named(ui.button("📋"), "Copy error").clicked() {
+                                self.clipboard
+                                    .set_contents(format!("{} {}", path, err))
+                                    .unwrap();
+                            }
+                        });
+                    }
+                });
+            }
+
+            if !self.roots.is_empty() {
+                let mut new_roots = None;
+                for (i, root) in self.roots.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(if i == 0 { "Picked directory:" } else { "and:" });
+                        ui.monospace(root);
+                        if self.roots.len() > 1
+                            && named(ui.small_button("✖"), "Remove directory")
+                                .on_hover_text("Scan again without it")
+                                .clicked()
+                        {
+                            let mut roots = self.roots.clone();
+                            roots.remove(i);
+                            new_roots = Some(roots);
+                        }
+                    });
+                }
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            !self.roots.iter().any(|root| storage::is_remote(root)),
+                            Button::new("+ Add folder…"),
+                        )
+                        .on_hover_text("Scan again with it, to find duplicates across directories")
+                        .clicked()
+                    {
+                        if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                            let mut roots = self.roots.clone();
+                            roots.push(path.to_string_lossy().to_string());
+                            new_roots = Some(roots);
+                        }
+                    }
+                    if ui
+                        .add_enabled(!self.undo_stack.is_empty(), Button::new("⟲ Undo"))
+                        .on_hover_text("Ctrl+Z")
+                        .clicked()
+                    {
+                        undo = true;
+                    }
+                    if ui
+                        .add_enabled(!self.redo_stack.is_empty(), Button::new("⟳ Redo"))
+                        .on_hover_text("Ctrl+Shift+Z")
+                        .clicked()
+                    {
+                        redo = true;
+                    }
+                });
+                if let Some(roots) = new_roots {
+                    self.start_scan_of(ctx, roots.into_iter().map(PathBuf::from).collect());
+                }
+
+                // Applied after the loop since it changes `similar_images`.
+                let mut to_trash = None;
+                let mut to_replace = None;
+                let mut to_inspect = None;
+                let mut raw_jpeg_policies = Vec::new();
+                let mut to_dismiss = Vec::new();
+                let mut trash_others = false;
+                let mut to_mark = Vec::new();
+                let mut trash_marked = false;
+                let mut to_diff = None;
+                let mut to_dedupe = Vec::new();
+                let mut to_snapshot = None;
+                let mut verified_action = None;
+                let raw_jpeg_pairs: Vec<(usize, usize)> = self
+                    .similar_images
+                    .iter()
+                    .copied()
+                    .filter(|&pair| self.raw_jpeg_pair(pair).is_some())
+                    .collect();
+                let variant_pairs: Vec<(usize, usize)> = self
+                    .similar_images
+                    .iter()
+                    .copied()
+                    .filter(|&pair| self.edited_variant_pair(pair).is_some())
+                    .collect();
+                let keepers: Vec<((usize, usize), usize)> = self
+                    .similar_images
+                    .iter()
+                    .copied()
+                    .filter(|&pair| self.bulk_actionable(pair))
+                    .filter_map(|pair| Some((pair, self.suggested_keeper(pair)?.0)))
+                    .collect();
+                let identical: Vec<(usize, usize)> = self
+                    .similar_images
+                    .iter()
+                    .copied()
+                    .filter(|&pair| {
+                        matches!(
+                            self.identities.get(&pair),
+                            Some(Identity::SamePixels | Identity::SameFile)
+                        ) && self.bulk_actionable(pair)
+                    })
+                    .collect();
+                if !self.identical_files.is_empty() {
+                    let reclaimable: u64 = self
+                        .identical_files
+                        .iter()
+                        .map(|(size, paths)| size * (paths.len() as u64 - 1))
+                        .sum();
+                    ui.collapsing(
+                        format!(
+                            "100% identical: {} groups of files, {} reclaimable",
+                            self.identical_files.len(),
+                            reclaimable.bytes()
+                        ),
+                        |ui| {
+                            ui.label(
+                                "Byte for byte, whatever the similarity threshold. Those which \
+                                 decode are also in the pairs below.",
+                            );
+                            egui::ScrollArea::vertical()
+                                .id_source("identical_files")
+                                .max_height(200.0)
+                                .show(ui, |ui| {
+                                    for (size, paths) in &self.identical_files {
+                                        ui.label(format!("{} × {}", paths.len(), size.bytes()));
+                                        for path in paths {
+                                            ui.monospace(format!("  {}", path));
+                                        }
+                                    }
+                                });
+                        },
+                    );
+                }
+                if !identical.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} pairs with identical pixels:", identical.len()));
+                        if ui
+                            .button("Strip and dedupe all")
+                            .on_hover_text("Trash the copy with less metadata of each pair")
+                            .clicked()
+                            || command == Some(Command::StripAndDedupeAll)
+                        {
+                            to_dedupe.extend(identical.iter().copied());
+                        }
+                    });
+                }
+                let verified = identical
+                    .iter()
+                    .filter(|pair| self.identities.get(pair) == Some(&Identity::SameFile))
+                    .count();
+                if verified > 0 {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} pairs verified identical:", verified));
+                        if ui.button("🔗 Hardlink all").clicked()
+                            || command == Some(Command::HardlinkAll)
+                        {
+                            verified_action = Some(VerifiedAction::Hardlink);
+                        }
+                        if ui
+                            .button("⧉ Clone all")
+                            .on_hover_text("Share the data of each pair on btrfs, XFS or APFS")
+                            .clicked()
+                            || command == Some(Command::CloneAll)
+                        {
+                            verified_action = Some(VerifiedAction::Clone);
+                        }
+                        if ui.button("🗑 Trash all duplicates").clicked()
+                            || command == Some(Command::TrashAllDuplicates)
+                        {
+                            verified_action = Some(VerifiedAction::Trash);
+                        }
+                    });
+                }
+                if !keepers.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} pairs with a suggested keeper:", keepers.len()));
+                        trash_others = ui.button("🗑 Trash the other copies").clicked()
+                            || command == Some(Command::TrashOtherCopies);
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Keep the");
+                    egui::ComboBox::from_id_source("keep_rule")
+                        .selected_text(self.settings.keep_rule.name())
+                        .show_ui(ui, |ui| {
+                            for rule in rules::KeepRule::ALL {
+                                ui.selectable_value(
+                                    &mut self.settings.keep_rule,
+                                    rule,
+                                    rule.name(),
+                                );
+                            }
+                        });
+                    if ui
+                        .button("Mark the others")
+                        .on_hover_text(
+                            "Mark the other image of each pair of this tab for deletion, pairs \
+                             the rule cannot decide are left alone",
+                        )
+                        .clicked()
+                        || command == Some(Command::MarkByRule)
+                    {
+                        let undecided = self.mark_by_rule();
+                        if undecided > 0 {
+                            info!("The keep rule left {} pairs undecided", undecided);
+                        }
+                    }
+                    if !self.marked.is_empty() {
+                        if ui
+                            .button(format!("🗑 Trash {} marked files", self.marked.len()))
+                            .clicked()
+                            || command == Some(Command::TrashMarked)
+                        {
+                            trash_marked = true;
+                        }
+                        if ui.button("Clear marks").clicked() {
+                            self.marked.clear();
+                        }
+                    }
+                    if let Some(summary) = &self.batch_summary {
+                        ui.label(summary);
+                    }
+                });
+                if !variant_pairs.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} edited variants:", variant_pairs.len()));
+                        if ui.button("Keep both").clicked() {
+                            to_dismiss.extend(variant_pairs.iter().copied());
+                        }
+                    });
+                }
+                if !raw_jpeg_pairs.is_empty() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("{} RAW+JPEG pairs:", raw_jpeg_pairs.len()));
+                        for policy in RawJpegPolicy::ALL {
+                            if ui.button(policy.label()).clicked() {
+                                raw_jpeg_policies
+                                    .extend(raw_jpeg_pairs.iter().map(|&pair| (pair, policy)));
+                            }
+                        }
+                    });
+                }
+                let linked: Vec<(&str, &str, links::Link)> = self
+                    .similar_images
+                    .iter()
+                    .filter_map(|pair| match self.identities.get(pair) {
+                        Some(Identity::Linked(link)) => Some((
+                            self.images[pair.0].as_ref()?.path.as_str(),
+                            self.images[pair.1].as_ref()?.path.as_str(),
+                            *link,
+                        )),
+                        _ => None,
+                    })
+                    .collect();
+                if !linked.is_empty() {
+                    ui.collapsing(
+                        format!("Already sharing their data ({})", linked.len()),
+                        |ui| {
+                            ui.label(
+                                "Other tools report these as duplicates, but removing one frees \
+                                 no space.",
+                            );
+                            egui::ScrollArea::vertical()
+                                .id_source("linked")
+                                .max_height(200.0)
+                                .show(ui, |ui| {
+                                    for (a, b, link) in &linked {
+                                        ui.monospace(format!("{} ⇔ {} ({})", a, b, link.name()));
+                                    }
+                                });
+                        },
+                    );
+                }
+                let mut compute_wasted_space = false;
+                let mut pair_by_capture_time = false;
+                let mut filter = None;
+                ui.collapsing("Wasted space by directory", |ui| {
+                    compute_wasted_space = ui
+                        .button(if self.wasted_space.is_some() {
+                            "Refresh"
+                        } else {
+                            "Compute"
+                        })
+                        .clicked();
+                    let Some(directories) = &self.wasted_space else {
+                        return;
+                    };
+                    let max = directories.first().map_or(1, |(_, size)| *size).max(1);
+                    for (dir, size) in directories.iter().take(WASTED_SPACE_ROWS) {
+                        ui.horizontal(|ui| {
+                            let (rect, bar) = ui
+                                .allocate_exact_size(Vec2::new(200.0, 14.0), egui::Sense::click());
+                            bar.widget_info(|| {
+                                WidgetInfo::labeled(
+                                    WidgetType::Button,
+                                    format!("{} wasted in {}", size.bytes(), dir),
+                                )
+                            });
+                            let width = rect.width() * (*size as f32 / max as f32);
+                            ui.painter().rect_filled(
+                                egui::Rect::from_min_size(
+                                    rect.min,
+                                    Vec2::new(width, rect.height()),
+                                ),
+                                2.0,
+                                Color32::from_rgb(220, 40, 40),
+                            );
+                            ui.label(size.bytes().to_string());
+                            let link = ui
+                                .link(dir)
+                                .on_hover_text("Only list the pairs with an image in there");
+                            if bar.clicked() || link.clicked() {
+                                filter = Some(dir.clone());
+                            }
+                        });
+                    }
+                });
+                ui.collapsing("Shot at the same time", |ui| {
+                    ui.label(
+                        "Bursts and RAW+JPEG pairs, which may not look alike enough to be \
+                         found by their hashes.",
+                    );
+                    ui.horizontal(|ui| {
+                        ui.label("Within");
+                        ui.add(
+                            egui::DragValue::new(&mut self.settings.capture_window)
+                                .clamp_range(0..=3600)
+                                .suffix(" s"),
+                        )
+                        .on_hover_text("Of each other, by the same camera");
+                        pair_by_capture_time = ui
+                            .add_enabled(
+                                !self.grouping_by_capture_time,
+                                egui::Button::new("Pair by capture time"),
+                            )
+                            .clicked();
+                        if self.grouping_by_capture_time {
+                            ui.spinner();
+                        } else if let Some(added) = self.capture_pairs {
+                            ui.label(format!("{} new pairs", added));
+                        }
+                    });
+                });
+                ui.collapsing("Statistics", |ui| {
+                    let mut records: Vec<&history::Record> = self
+                        .history
+                        .iter()
+                        .filter(|record| record.root == self.roots_key())
+                        .collect();
+                    records.sort_by_key(|record| record.time);
+                    let Some(latest) = records.last() else {
+                        ui.label("Recorded once the scan is complete.");
+                        return;
+                    };
+                    ui.label(format!(
+                        "{} scans of this directory. Latest: {} in {} images, {} duplicate \
+                         pairs, {} reclaimable, {} reclaimed",
+                        records.len(),
+                        latest.total_bytes.bytes(),
+                        latest.images,
+                        latest.duplicates,
+                        latest.reclaimable_bytes.bytes(),
+                        latest.reclaimed_bytes.bytes()
+                    ));
+                    history::show(ui, &records);
+                });
+                if let Some(dir) = &self.results_filter {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("Only the pairs with an image in {}", dir));
+                        if ui.button("✖ Show all").clicked() {
+                            filter = Some(String::new());
+                        }
+                    });
+                }
+
+                let search_matches: Vec<usize> = self
+                    .similar_images
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, pair)| {
+                        !matches!(self.identities.get(pair), Some(Identity::Linked(_)))
+                            && !self.below_min_dimension(**pair)
+                            && self.in_band(**pair)
+                            && [pair.0, pair.1].into_iter().any(|idx| {
+                                self.images[idx]
+                                    .as_ref()
+                                    .is_some_and(|image| search::matches(&self.search, &image.path))
+                            })
+                    })
+                    .map(|(pair, _)| pair)
+                    .collect();
+                ui.horizontal(|ui| {
+                    let label = ui.label("🔍");
+                    label.widget_info(|| WidgetInfo::labeled(WidgetType::Label, "Search"));
+                    if ui
+                        .text_edit_singleline(&mut self.search)
+                        .labelled_by(label.id)
+                        .on_hover_text("Words match parts of a file or directory name, e.g. vac 23")
+                        .changed()
+                    {
+                        self.search_match = 0;
+                        self.scroll_to_match = true;
+                    }
+                    if !self.search.trim().is_empty() {
+                        ui.label(format!("{} matching pairs", search_matches.len()));
+                    }
+                    let count = search_matches.len();
+                    if named(
+                        ui.add_enabled(count > 0, Button::new("◀")),
+                        "Previous match",
+                    )
+                    .clicked()
+                    {
+                        self.search_match = (self.search_match + count - 1) % count;
+                        self.scroll_to_match = true;
+                    }
+                    if named(ui.add_enabled(count > 0, Button::new("▶")), "Next match").clicked()
+                    {
+                        self.search_match = (self.search_match + 1) % count;
+                        self.scroll_to_match = true;
+                    }
+                });
+                let mut trash_band = false;
+                ui.horizontal(|ui| {
+                    if ui
+                        .selectable_label(
+                            self.band.is_none(),
+                            format!("All ({})", self.similar_images.len()),
+                        )
+                        .clicked()
+                    {
+                        self.band = None;
+                    }
+                    for band in bands::Band::ALL {
+                        let count = self
+                            .similar_images
+                            .iter()
+                            .filter(|&&pair| self.band_of(pair) == band)
+                            .count();
+                        if ui
+                            .selectable_label(
+                                self.band == Some(band),
+                                format!("{} ({})", band.name(), count),
+                            )
+                            .clicked()
+                        {
+                            self.band = Some(band);
+                        }
+                    }
+                    if self.band.is_some() {
+                        trash_band = ui
+                            .button("🗑 Trash the duplicates of this tab")
+                            .on_hover_text(
+                                "Keeps the suggested keeper of each pair, or the image found first",
+                            )
+                            .clicked()
+                            || command == Some(Command::TrashBand);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let sort = self.sort;
+                    egui::ComboBox::from_label("sort by")
+                        .selected_text(self.sort.name())
+                        .show_ui(ui, |ui| {
+                            for key in sort::SortKey::ALL {
+                                ui.selectable_value(&mut self.sort, key, key.name());
+                            }
+                        });
+                    let direction = if self.sort_descending {
+                        "⬇ descending"
+                    } else {
+                        "⬆ ascending"
+                    };
+                    if ui.button(direction).clicked() {
+                        self.sort_descending = !self.sort_descending;
+                        self.sorted_generation = None;
+                    }
+                    if self.sort != sort {
+                        self.sort_keys.clear();
+                        self.sort_keys_pending.clear();
+                        self.sorted_generation = None;
+                    }
+                    ui.checkbox(&mut self.settings.group_pairs, "Group similar images")
+                        .on_hover_text(
+                            "One row for images similar to each other, e.g. A, B and C rather \
+                             than A ↔ B, A ↔ C and B ↔ C",
+                        );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Hide pairs where");
+                    egui::ComboBox::from_id_source("min_pair_dimension_both")
+                        .selected_text(if self.settings.min_pair_dimension_both {
+                            "both images are"
+                        } else {
+                            "an image is"
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(
+                                &mut self.settings.min_pair_dimension_both,
+                                false,
+                                "an image is",
+                            );
+                            ui.selectable_value(
+                                &mut self.settings.min_pair_dimension_both,
+                                true,
+                                "both images are",
+                            );
+                        });
+                    ui.label("smaller than");
+                    ui.add(
+                        egui::DragValue::new(&mut self.settings.min_pair_dimension).suffix(" px"),
+                    );
+                    let hidden = self
+                        .similar_images
+                        .iter()
+                        .filter(|&&pair| self.below_min_dimension(pair))
+                        .count();
+                    if hidden > 0 {
+                        ui.label(format!("({} hidden)", hidden));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Hide pairs below");
+                    ui.add(
+                        egui::Slider::new(&mut self.settings.min_confidence, 0..=100).suffix("%"),
+                    );
+                    ui.label("confidence");
+                    let hidden = self
+                        .similar_images
+                        .iter()
+                        .filter(|&&pair| self.below_min_confidence(pair))
+                        .count();
+                    if hidden > 0 {
+                        ui.label(format!("({} hidden)", hidden));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let mut enabled = self.distance_range.is_some();
+                    ui.checkbox(&mut enabled, "Only show distances from");
+                    let (mut min, mut max) = self
+                        .distance_range
+                        .unwrap_or((0, self.settings.scan.similarity_threshold));
+                    ui.add_enabled(enabled, egui::DragValue::new(&mut min).clamp_range(0..=max));
+                    ui.label("to");
+                    ui.add_enabled(
+                        enabled,
+                        egui::DragValue::new(&mut max).clamp_range(min..=100),
+                    );
+                    self.distance_range = Some((min, max)).filter(|_| enabled);
+                    let hidden = self
+                        .similar_images
+                        .iter()
+                        .filter(|&&pair| self.outside_distance_range(pair))
+                        .count();
+                    if hidden > 0 {
+                        ui.label(format!("({} hidden)", hidden));
+                    }
+                });
+                let target = search_matches.get(self.search_match).copied();
+                // The match may be hidden by the directory filter.
+                if self.scroll_to_match && target.is_some() && self.results_filter.is_some() {
+                    filter = Some(String::new());
+                }
+                let grouping = self.grouping();
+                let Grouping {
+                    listed,
+                    group_of,
+                    group_images,
+                    ..
+                } = &*grouping;
+
+                if self.scroll_to_match {
+                    if let Some(target) = target {
+                        self.selected_pair = Some(self.similar_images[target]);
+                    }
+                }
+                // The pair at the same row once the selected one is gone, e.g. trashed.
+                let selected_row = listed
+                    .iter()
+                    .position(|&pair| Some(self.similar_images[pair]) == self.selected_pair)
+                    .unwrap_or(self.selected_row.min(listed.len().saturating_sub(1)));
+                let (up, down) = if ctx.wants_keyboard_input() {
+                    (false, false)
+                } else {
+                    let mut input = ctx.input_mut();
+                    (
+                        input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowUp),
+                        input.consume_key(egui::Modifiers::NONE, egui::Key::ArrowDown),
+                    )
+                };
+                let mut new_row = if up {
+                    Some(selected_row.saturating_sub(1))
+                } else if down {
+                    Some((selected_row + 1).min(listed.len().saturating_sub(1)))
+                } else {
+                    None
+                };
+                let selected = listed.get(new_row.unwrap_or(selected_row)).copied();
+                let selected_group = selected
+                    .and_then(|pair| listed.iter().position(|&p| p == pair))
+                    .map(|row| group_of[row]);
+                // The rows shown, as rows of `listed` and whether they are the header of their
+                // group. One row per group, unfolded to its pairs when selected.
+                let mut shown: Vec<(usize, bool)> = Vec::with_capacity(listed.len());
+                for (row, &pair) in listed.iter().enumerate() {
+                    let group = group_of[row];
+                    let grouped = group_images
+                        .get(&group)
+                        .is_some_and(|members| members.len() > 2);
+                    if grouped && (row == 0 || group_of[row - 1] != group) {
+                        shown.push((row, true));
+                    }
+                    let (i, j) = self.similar_images[pair];
+                    if (!grouped || selected_group == Some(group))
+                        && self.images[i].is_some()
+                        && self.images[j].is_some()
+                    {
+                        shown.push((row, false));
+                    }
+                }
+                let jumped_row = listed
+                    .iter()
+                    .position(|&pair| {
+                        (self.scroll_to_match && target == Some(pair))
+                            || ((up || down) && Some(pair) == selected)
+                    })
+                    .and_then(|row| shown.iter().position(|&shown| shown == (row, false)));
+
+                let list = egui::SidePanel::left("results_list")
+                    .resizable(true)
+                    .min_width(150.0)
+                    .default_width(self.layout.results_split)
+                    .show_inside(ui, |ui| {
+                        // Only the rows in view are laid out, there may be hundreds of thousands.
+                        let row_height = ui.spacing().interact_size.y;
+                        let mut scroll_area =
+                            egui::ScrollArea::vertical().id_source("results_list");
+                        if let Some(jumped_row) = jumped_row {
+                            let offset = jumped_row as f32
+                                * (row_height + ui.spacing().item_spacing.y)
+                                - ui.available_height() / 2.0;
+                            scroll_area = scroll_area.vertical_scroll_offset(offset.max(0.0));
+                        }
+                        scroll_area.show_rows(ui, row_height, shown.len(), |ui, range| {
+                            for &(row, header) in &shown[range] {
+                                let pair = listed[row];
+                                let group = group_of[row];
+                                let members =
+                                    group_images.get(&group).filter(|members| members.len() > 2);
+                                if header {
+                                    if let Some(members) = members {
+                                        let paths: Vec<&str> = members
+                                            .iter()
+                                            .filter_map(|&idx| {
+                                                Some(self.images[idx].as_ref()?.path.as_str())
+                                            })
+                                            .collect();
+                                        let matching = paths
+                                            .iter()
+                                            .any(|path| search::matches(&self.search, path));
+                                        let mut names: Vec<String> =
+                                            paths.iter().map(|path| file_name(path)).collect();
+                                        let count = names.len();
+                                        names.truncate(3);
+                                        let mut text =
+                                            format!("▣ {} images: {}", count, names.join(", "));
+                                        if count > names.len() {
+                                            text += ", …";
+                                        }
+                                        let text = egui::RichText::new(text);
+                                        let label = ui.selectable_label(
+                                            selected_group == Some(group),
+                                            if matching {
+                                                text.background_color(SEARCH_HIGHLIGHT)
+                                            } else {
+                                                text
+                                            },
+                                        );
+                                        if label.clicked() {
+                                            new_row = Some(row);
+                                        }
+                                    }
+                                    continue;
+                                }
+                                let (i, j) = self.similar_images[pair];
+                                let (Some(a), Some(b)) = (&self.images[i], &self.images[j]) else {
+                                    continue;
+                                };
+                                let mut text = format!(
+                                    "{} ↔ {}  {}% Δ{}",
+                                    file_name(&a.path),
+                                    file_name(&b.path),
+                                    export::similarity(a, b),
+                                    a.hash.dist(&b.hash)
+                                );
+                                if let Some(confidence) = self.confidences.get(&(i, j)) {
+                                    text += &format!("  🎯{}%", confidence);
+                                }
+                                let text = egui::RichText::new(text);
+                                let matching = [&a.path, &b.path]
+                                    .into_iter()
+                                    .any(|path| search::matches(&self.search, path));
+                                let label = ui.horizontal(|ui| {
+                                    if members.is_some() {
+                                        ui.add_space(16.0);
+                                    }
+                                    ui.selectable_label(
+                                        Some(pair) == selected,
+                                        if matching {
+                                            text.background_color(SEARCH_HIGHLIGHT)
+                                        } else {
+                                            text
+                                        },
+                                    )
+                                    .on_hover_text(format!("{}\n{}", a.path, b.path))
+                                });
+                                if label.inner.clicked() {
+                                    new_row = Some(row);
+                                }
+                            }
+                        });
+                    });
+                self.layout.results_split = list.response.rect.width();
+                self.scroll_to_match = false;
+                if let Some(row) = new_row {
+                    self.selected_row = row;
+                    let pair = listed.get(row).map(|&pair| self.similar_images[pair]);
+                    if pair != self.selected_pair {
+                        self.pair_zoom = 1.0;
+                    }
+                    self.selected_pair = pair;
+                } else {
+                    self.selected_row = selected_row;
+                }
+
+                let mut prefetch_size = None;
+                egui::CentralPanel::default().show_inside(ui, |ui| {
+                    let mut pair_hovered = false;
+                    egui::ScrollArea::vertical()
+                        .id_source("comparison")
+                        .enable_scrolling(!self.pair_hovered)
+                        .show(ui, |ui| {
+                            // Images take up to half the width each.
+                            prefetch_size = Some(thumbnail::size_for(
+                                (ui.available_width() / 2.0 - 10.0) * ctx.pixels_per_point(),
+                            ));
+                            for (i, j) in selected.iter().map(|&pair| &self.similar_images[pair]) {
+                                let a = self.images[*i].as_ref().unwrap();
+                                let b = self.images[*j].as_ref().unwrap();
+                                let is_raw_jpeg = raw_jpeg_pairs.contains(&(*i, *j));
+                                ui.horizontal(|ui| {
+                                    ui.label(format!(
+                                        "≈ {}% similar, {} bits apart",
+                                        export::similarity(a, b),
+                                        a.hash.dist(&b.hash)
+                                    ))
+                                    .on_hover_text(
+                                        "Of the perceptual hashes, 100% when they are identical",
+                                    );
+                                    if let Some(confidence) = self.confidences.get(&(*i, *j)) {
+                                        ui.label(format!("🎯 {}% confidence", confidence))
+                                .on_hover_text(
+                                    "Combines the hash distance, file sizes, dimensions, capture \
+                                 times and file names",
+                                );
+                                    }
+                                    if ui
+                                        .button("📷 Save comparison…")
+                                        .on_hover_text(
+                                            "Both images with their paths and metadata in a PNG, \
+                                             to keep a record or to share",
+                                        )
+                                        .clicked()
+                                    {
+                                        to_snapshot = Some((*i, *j));
+                                    }
+                                    let mut show_diff = self.diff_pair == Some((*i, *j));
+                                    if ui
+                                        .checkbox(&mut show_diff, "Show diff")
+                                        .on_hover_text(
+                                            "Difference of the two images, resized to the same \
+                                             size: black where they match",
+                                        )
+                                        .changed()
+                                    {
+                                        to_diff = Some(((*i, *j), show_diff));
+                                    }
+                                    if self.pair_zoom > 1.0 {
+                                        ui.label(format!("🔍 {:.0}%", self.pair_zoom * 100.0));
+                                        if ui.button("Fit").clicked() {
+                                            self.pair_zoom = 1.0;
+                                        }
+                                    }
+                                });
+                                let locations = [*i, *j].map(|idx| {
+                                    self.image_facts.get(&idx).and_then(|facts| facts.location)
+                                });
+                                if let [Some(location_a), Some(location_b)] = locations {
+                                    let apart =
+                                        map::format_distance(map::distance(location_a, location_b));
+                                    ui.horizontal(|ui| {
+                                        map::show(ui, location_a, location_b).widget_info(|| {
+                                            WidgetInfo::labeled(
+                                                WidgetType::Label,
+                                                format!("Map, taken {} apart", apart),
+                                            )
+                                        });
+                                        ui.vertical(|ui| {
+                                            ui.label(format!("📍 Taken {} apart", apart));
+                                            ui.hyperlink_to(
+                                                "Open in OpenStreetMap",
+                                                map::url(location_a, location_b),
+                                            );
+                                        });
+                                    });
+                                }
+                                if variant_pairs.contains(&(*i, *j)) {
+                                    ui.horizontal(|ui| {
+                                        ui.label("✏ Edited variant of the same shot").on_hover_text(
+                                    "IMG_E files are the edits iOS saves next to the original",
+                                );
+                                        if ui.button("Keep both").clicked() {
+                                            to_dismiss.push((*i, *j));
+                                        }
+                                    });
+                                }
+                                if let Some(Identity::Corrupted {
+                                    a_decodes,
+                                    b_decodes,
+                                }) = self.identities.get(&(*i, *j))
+                                {
+                                    let name = |path: &str| {
+                                        Path::new(path)
+                                            .file_name()
+                                            .unwrap_or_default()
+                                            .to_string_lossy()
+                                            .to_string()
+                                    };
+                                    let text = match (a_decodes, b_decodes) {
+                                        (true, false) => format!(
+                                            "⚠ Possibly corrupted copy: only {} decodes cleanly",
+                                            name(&a.path)
+                                        ),
+                                        (false, true) => format!(
+                                            "⚠ Possibly corrupted copy: only {} decodes cleanly",
+                                            name(&b.path)
+                                        ),
+                                        _ => format!(
+                                            "⚠ Possibly corrupted copy: {} and {} differ in a few \
+                                             bytes, compare them closely",
+                                            name(&a.path),
+                                            name(&b.path)
+                                        ),
+                                    };
+                                    ui.colored_label(ui.visuals().warn_fg_color, text)
+                                        .on_hover_text(
+                                            "These should be the same file but are not, one may \
+                                             have been damaged by the disk or an interrupted copy",
+                                        );
+                                }
+                                let identity = match self.identities.get(&(*i, *j)) {
+                                    Some(Identity::SamePixels) => {
+                                        Some("🟰 identical pixels, different metadata")
+                                    }
+                                    Some(Identity::SameFile) => Some("✔ verified identical"),
+                                    _ => None,
+                                };
+                                if let Some(identity) = identity {
+                                    ui.horizontal(|ui| {
+                                        ui.label(identity);
+                                        if ui
+                                    .button("Strip and dedupe")
+                                    .on_hover_text(
+                                        "Trash the copy with less metadata, no pixel is lost",
+                                    )
+                                    .clicked()
+                                {
+                                    to_dedupe.push((*i, *j));
+                                }
+                                    });
+                                }
+                                // Only identical files can be cloned without losing anything.
+                                let verified =
+                                    self.identities.get(&(*i, *j)) == Some(&Identity::SameFile);
+                                let keeper = self.suggested_keeper((*i, *j));
+                                let messaging_copy = self.messaging_copy((*i, *j));
+                                if is_raw_jpeg {
+                                    ui.horizontal(|ui| {
+                                        ui.label("📷 RAW+JPEG from the same shot:");
+                                        for policy in RawJpegPolicy::ALL {
+                                            if ui.button(policy.label()).clicked() {
+                                                raw_jpeg_policies.push(((*i, *j), policy));
+                                            }
+                                        }
+                                    });
+                                }
+
+                                // The image the pair is sorted by comes first.
+                                let (key_i, key_j) = (self.sort_keys.get(i), self.sort_keys.get(j));
+                                let swap = self.sort.orders_images()
+                                    && if self.sort_descending {
+                                        key_j > key_i
+                                    } else {
+                                        key_j < key_i
+                                    };
+                                let members = if swap {
+                                    [(j, b, i), (i, a, j)]
+                                } else {
+                                    [(i, a, j), (j, b, i)]
+                                };
+                                ui.horizontal(|ui| {
+                                    let max_width = ui.available_width() / 2.0 - 10.0;
+
+                                    for (idx, img, other_idx) in members {
+                                        ui.vertical(|ui| {
+                                            ui.horizontal(|ui| {
+                                                // TODO: inline in struct?
+                                                let text = egui::RichText::new(format!(
+                                                    "{} ({}x{})",
+                                                    img.path, img.dimensions.0, img.dimensions.1
+                                                ));
+                                                ui.label(
+                                                    if search::matches(&self.search, &img.path) {
+                                                        text.background_color(SEARCH_HIGHLIGHT)
+                                                    } else {
+                                                        text
+                                                    },
+                                                );
+                                                if let Some(pattern) = sequence::pattern(&img.path)
+                                                    .filter(|_| {
+                                                        self.settings.scan.sequence_step > 0
+                                                    })
+                                                {
+                                                    ui.label("🎬 sequence").on_hover_text(format!(
+                                                "Sampled frame of {}, other similar frames are \
+                                                 not listed",
+                                                pattern
+                                            ));
+                                                }
+                                                if formats::is_video(Path::new(&img.path)) {
+                                                    ui.label(
+                                                        egui::RichText::new("🎥 video").strong(),
+                                                    )
+                                                    .on_hover_text(format!(
+                                                        "Compared on the average of {} keyframes",
+                                                        img.frames
+                                                    ));
+                                                } else if img.frames > 1 {
+                                                    if formats::is_icon(Path::new(&img.path)) {
+                                                        ui.label(format!("🗐 {} sizes", img.frames))
+                                                            .on_hover_text(
+                                                                "Compared on the largest size",
+                                                            );
+                                                    } else {
+                                                        ui.label(
+                                                            egui::RichText::new("🎞 animated")
+                                                                .strong(),
+                                                        )
+                                                        .on_hover_text(format!(
+                                                            "Compared on the average of {} frames",
+                                                            img.frames
+                                                        ));
+                                                    }
+                                                }
+                                                if let Some((_, reason)) =
+                                                    keeper.filter(|(keeper, _)| keeper == idx)
+                                                {
+                                                    ui.label("⭐ suggested keeper")
+                                                        .on_hover_text(reason);
+                                                }
+                                                let mut marked = self.marked.contains(idx);
+                                                if ui
+                                                    .checkbox(&mut marked, "🗑 marked")
+                                                    .on_hover_text("Marked for deletion")
+                                                    .changed()
+                                                {
+                                                    to_mark.push((*idx, marked));
+                                                }
+                                                if messaging_copy == Some(*idx) {
+                                                    ui.label("💬 messaging copy").on_hover_text(
+                                                "Resized and recompressed by a messaging app, \
+                                                 e.g. WhatsApp or Telegram",
+                                            );
+                                                }
+                                                if self
+                                                    .catalog_files
+                                                    .contains(&lightroom::canonical(&img.path))
+                                                {
+                                                    ui.label("📒 in catalog").on_hover_text(
+                                                "Referenced by the Lightroom catalog, trashing it \
+                                                 asks for confirmation",
+                                            );
+                                                }
+                                                if named(ui.button("📋"), "Copy path").clicked() {
+                                                    self.clipboard
+                                                        .set_contents(img.path.clone())
+                                                        .unwrap();
+                                                }
+                                            });
+                                            let summary = self
+                                                .exif_summaries
+                                                .get(idx)
+                                                .cloned()
+                                                .unwrap_or_default();
+                                            let mut details = Vec::new();
+                                            if let Some(camera) = &summary.camera {
+                                                details.push(format!("📷 {}", camera));
+                                            }
+                                            if let Some(time) = summary.capture_time {
+                                                details.push(format!(
+                                                    "🕓 {}",
+                                                    exif::format_time(time)
+                                                ));
+                                            }
+                                            if let Some(iso) = summary.iso {
+                                                details.push(format!("ISO {}", iso));
+                                            }
+                                            if let Some((latitude, longitude)) = summary.location {
+                                                details.push(format!(
+                                                    "📍 {:.5}, {:.5}",
+                                                    latitude, longitude
+                                                ));
+                                            }
+                                            if !details.is_empty() {
+                                                ui.small(details.join("  ·  "));
+                                            }
+
+                                            let (width, height) =
+                                                (img.dimensions.0 as f32, img.dimensions.1 as f32);
+                                            let w = f32::clamp(width, 0.0, max_width);
+
+                                            let h = f32::clamp(w * height / width, 0.0, height);
+
+                                            // Snapped to physical pixels, or the thumbnail is resampled
+                                            // once more and looks blurry.
+                                            let pixels_per_point = ui.ctx().pixels_per_point();
+                                            let display_img_size =
+                                                (Vec2::new(w, h) * pixels_per_point).round()
+                                                    / pixels_per_point;
+                                            // Thumbnails follow the scale of the monitor the window is on,
+                                            // and the zoom.
+                                            let wanted_size = thumbnail::size_for(
+                                                (display_img_size.max_elem()
+                                                    * pixels_per_point
+                                                    * self.pair_zoom)
+                                                    .min(img.dimensions.0.max(img.dimensions.1)
+                                                        as f32),
+                                            );
+                                            let rect = match &img.texture {
+                                                Some(texture) => {
+                                                    let (rect, image) = ui.allocate_exact_size(
+                                                        display_img_size,
+                                                        egui::Sense::click_and_drag(),
+                                                    );
+                                                    let rect = egui::Rect::from_min_size(
+                                                        ui.painter().round_pos_to_pixels(rect.min),
+                                                        rect.size(),
+                                                    );
+                                                    let uv =
+                                                        pair_uv(self.pair_zoom, self.pair_center);
+                                                    egui::Image::new(texture, display_img_size)
+                                                        .uv(uv)
+                                                        .paint_at(ui, rect);
+                                                    if image.hovered() {
+                                                        pair_hovered = true;
+                                                        let (scroll, pointer) = {
+                                                            let input = ui.input();
+                                                            (
+                                                                input.scroll_delta.y,
+                                                                input.pointer.hover_pos(),
+                                                            )
+                                                        };
+                                                        if let Some(pointer) =
+                                                            pointer.filter(|_| scroll != 0.0)
+                                                        {
+                                                            zoom_pair(
+                                                                &mut self.pair_zoom,
+                                                                &mut self.pair_center,
+                                                                (scroll * 0.005).exp(),
+                                                                (pointer - rect.min) / rect.size(),
+                                                            );
+                                                        }
+                                                    }
+                                                    if image.dragged() {
+                                                        self.pair_center -= image.drag_delta()
+                                                            / rect.size()
+                                                            / self.pair_zoom;
+                                                        self.pair_center = pair_uv(
+                                                            self.pair_zoom,
+                                                            self.pair_center,
+                                                        )
+                                                        .center();
+                                                    }
+                                                    let image = image.on_hover_text(
+                                                "Click to inspect in full resolution, scroll to \
+                                                     zoom both images, drag to pan them. On a \
+                                                     touch screen, swipe left to trash it, right \
+                                                     to keep both",
+                                            );
+                                                    match swipe.filter(|(start, _)| {
+                                                        image.rect.contains(*start)
+                                                    }) {
+                                                        Some((_, gestures::Direction::Left)) => {
+                                                            to_trash = Some((*idx, *other_idx));
+                                                        }
+                                                        Some((_, gestures::Direction::Right)) => {
+                                                            to_dismiss.push((*i, *j));
+                                                        }
+                                                        None => {}
+                                                    }
+                                                    image.widget_info(|| {
+                                                        WidgetInfo::labeled(
+                                                            WidgetType::ImageButton,
+                                                            format!(
+                                                        "{}, {} by {} pixels, inspect in full \
+                                                         resolution",
+                                                        img.path,
+                                                        img.dimensions.0,
+                                                        img.dimensions.1
+                                                    ),
+                                                        )
+                                                    });
+                                                    if image.clicked() {
+                                                        to_inspect = Some(*idx);
+                                                    }
+                                                    rect
+                                                }
+                                                None if self.unavailable_textures.contains(idx) => {
+                                                    ui.allocate_ui(display_img_size, |ui| {
+                                                        ui.label("Preview unavailable")
+                                                    })
+                                                    .response
+                                                    .rect
+                                                }
+                                                None => {
+                                                    ui.allocate_ui(display_img_size, |ui| {
+                                                        ui.spinner().widget_info(|| {
+                                                            WidgetInfo::labeled(
+                                                                WidgetType::Label,
+                                                                "Loading preview",
+                                                            )
+                                                        })
+                                                    })
+                                                    .response
+                                                    .rect
+                                                }
+                                            };
+                                            // Off-screen ones are left to the prefetching. The smaller
+                                            // texture stays up until the bigger one is loaded.
+                                            if ui.is_rect_visible(rect)
+                                                && !self.unavailable_textures.contains(idx)
+                                                && self.texture_requests.get(idx).is_none_or(
+                                                    |&requested| requested < wanted_size,
+                                                )
+                                            {
+                                                self.texture_requests.insert(*idx, wanted_size);
+                                                let summary =
+                                                    !self.exif_summaries.contains_key(idx);
+                                                self.exif_summaries.entry(*idx).or_default();
+                                                request_texture(
+                                                    self.images_sender.clone(),
+                                                    ui.ctx().clone(),
+                                                    *idx,
+                                                    img.path.clone(),
+                                                    wanted_size,
+                                                    summary,
+                                                );
+                                            }
+                                            // The RAW+JPEG policies replace the generic actions.
+                                            if is_raw_jpeg {
+                                                return;
+                                            }
+                                            let trash_button = egui::Button::new("🗑 Move to trash")
+                                                .fill(Color32::RED)
+                                                .ui(ui);
+                                            // Each image of the pair has one.
+                                            trash_button.widget_info(|| {
+                                                WidgetInfo::labeled(
+                                                    WidgetType::Button,
+                                                    format!("Move {} to trash", img.path),
+                                                )
+                                            });
+                                            // Only looked up when hovered, it lists the directory.
+                                            let trash_button = if self.settings.trash_companions {
+                                                trash_button.on_hover_ui(|ui| {
+                                                    let kept = self.images[*other_idx]
+                                                        .as_ref()
+                                                        .map(|kept| Path::new(&kept.path));
+                                                    let companions = companions::find_unshared(
+                                                        Path::new(&img.path),
+                                                        kept,
+                                                    );
+                                                    if companions.is_empty() {
+                                                        ui.label("No companion files");
+                                                    }
+                                                    for companion in companions {
+                                                        ui.label(format!(
+                                                            "Also trashes {}",
+                                                            companion.display()
+                                                        ));
+                                                    }
+                                                })
+                                            } else {
+                                                trash_button
+                                            };
+                                            if trash_button.clicked() {
+                                                to_trash = Some((*idx, *other_idx));
+                                            }
+                                            ui.horizontal(|ui| {
+                                                let modes = [
+                                                    ReplaceMode::Hardlink,
+                                                    ReplaceMode::Symlink,
+                                                    ReplaceMode::Move,
+                                                    ReplaceMode::Clone,
+                                                ];
+                                                for mode in modes.into_iter().filter(|&mode| {
+                                                    mode != ReplaceMode::Clone || verified
+                                                }) {
+                                                    if ui.button(mode.label()).clicked() {
+                                                        to_replace = Some((*idx, *other_idx, mode));
+                                                    }
+                                                }
+                                            });
+                                        });
+                                    }
+                                });
+                                if self.diff_pair == Some((*i, *j)) {
+                                    match &self.diff {
+                                        Some((texture, mean)) => {
+                                            ui.label(format!(
+                                                "Mean difference: {:.1}%",
+                                                mean * 100.0
+                                            ));
+                                            let width = ui.available_width() / 2.0 - 10.0;
+                                            let size = texture.size_vec2();
+                                            let size = size * (width / size.x).min(1.0);
+                                            let uv = pair_uv(self.pair_zoom, self.pair_center);
+                                            ui.add(egui::Image::new(texture, size).uv(uv))
+                                                .widget_info(|| {
+                                                    WidgetInfo::labeled(
+                                                        WidgetType::Label,
+                                                        "Difference of the two images",
+                                                    )
+                                                });
+                                        }
+                                        None => {
+                                            ui.spinner().widget_info(|| {
+                                                WidgetInfo::labeled(
+                                                    WidgetType::Label,
+                                                    "Computing the difference",
+                                                )
+                                            });
+                                        }
+                                    }
+                                }
+                            }
+                        });
+                    self.pair_hovered = pair_hovered;
+                });
+                // The next pairs are likely to be looked at next, and the previous one when going
+                // back.
+                if let (Some(row), Some(size)) = (
+                    selected.and_then(|pair| listed.iter().position(|&p| p == pair)),
+                    prefetch_size,
+                ) {
+                    let next: Vec<(usize, usize)> = listed
+                        .iter()
+                        .skip(row + 1)
+                        .take(PREFETCH_PAIRS)
+                        .map(|&pair| self.similar_images[pair])
+                        .collect();
+                    self.prefetch_textures(ctx, &next, size);
+                    let keep: HashSet<usize> = listed
+                        [row.saturating_sub(1)..(row + 1 + PREFETCH_PAIRS).min(listed.len())]
+                        .iter()
+                        .flat_map(|&pair| {
+                            let (i, j) = self.similar_images[pair];
+                            [i, j]
+                        })
+                        .collect();
+                    self.evict_textures(&keep);
+                }
+                if let Some(idx) = to_inspect {
+                    self.inspect(ctx, idx);
+                }
+                if command == Some(Command::SaveComparison) {
+                    to_snapshot = selected.map(|pair| self.similar_images[pair]);
+                }
+                if let Some(pair) = to_snapshot {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("PNG", &["png"])
+                        .set_file_name("comparison.png")
+                        .save_file()
+                    {
+                        self.save_comparison(pair, &path);
+                    }
+                }
+                if let Some((idx, kept_idx, mode)) = to_replace {
+                    self.replace_or_confirm(idx, kept_idx, mode);
+                }
+                if let Some((idx, kept_idx)) = to_trash {
+                    self.trash_or_confirm(idx, kept_idx);
+                }
+                for (pair, policy) in raw_jpeg_policies {
+                    self.apply_raw_jpeg_policy(pair, policy);
+                }
+                for pair in to_dismiss {
+                    self.dismiss_pair(pair);
+                }
+                for (idx, marked) in to_mark {
+                    self.mark_image(idx, marked);
+                }
+                if trash_marked {
+                    self.trash_marked();
+                }
+                match to_diff {
+                    Some(((i, j), true)) => {
+                        if let (Some(a), Some(b)) = (&self.images[i], &self.images[j]) {
+                            request_diff(
+                                self.images_sender.clone(),
+                                ctx.clone(),
+                                (i, j),
+                                (a.path.clone(), b.path.clone()),
+                            );
+                            self.diff_pair = Some((i, j));
+                            self.diff = None;
+                        }
+                    }
+                    Some((_, false)) => {
+                        self.diff_pair = None;
+                        self.diff = None;
+                    }
+                    None => {}
+                }
+                for pair in to_dedupe {
+                    self.strip_and_dedupe(pair);
+                }
+                if trash_band {
+                    self.trash_band();
+                }
+                if compute_wasted_space {
+                    self.compute_wasted_space(ctx);
+                }
+                if pair_by_capture_time {
+                    self.pair_by_capture_time(ctx);
+                }
+                if let Some(dir) = filter {
+                    self.results_filter = Some(dir).filter(|dir| !dir.is_empty());
+                }
+                if let Some(action) = verified_action {
+                    for pair in identical {
+                        if self.identities.get(&pair) == Some(&Identity::SameFile) {
+                            self.apply_verified_action(pair, action);
+                        }
+                    }
+                }
+                if trash_others {
+                    for ((i, j), keeper) in keepers {
+                        if self.pair_intact((i, j)) {
+                            self.trash_or_confirm(if keeper == i { j } else { i }, keeper);
+                        }
+                    }
+                }
+            }
+        });
+
+        if !self.pending_trash.is_empty() {
+            let mut confirmed = false;
+            let mut cancelled = false;
+            let (title, confirm) = if self.settings.delete_permanently {
+                ("Delete these files permanently?", "🗑 Delete permanently")
+            } else {
+                ("Trash these files?", "🗑 Move to trash anyway")
+            };
+            egui::Window::new(title)
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    // Deleting permanently confirms every file, there may be thousands.
+                    egui::ScrollArea::vertical()
+                        .max_height(400.0)
+                        .show(ui, |ui| {
+                            for &(idx, _) in &self.pending_trash {
+                                if let (Some(image), Some(warning)) =
+                                    (&self.images[idx], self.trash_warning(idx))
+                                {
+                                    ui.monospace(&image.path);
+                                    ui.label(format!("⚠ {}", warning));
+                                }
+                            }
+                        });
+                    ui.horizontal(|ui| {
+                        confirmed = ui.button(confirm).clicked();
+                        cancelled = ui.button("Cancel").clicked();
+                    });
+                });
+            if confirmed {
+                for (idx, kept_idx) in std::mem::take(&mut self.pending_trash) {
+                    if let Some(entry) = self.trash_image(idx, Some(kept_idx)) {
+                        self.push_action(ReviewAction::Trash(entry));
+                    }
+                }
+            } else if cancelled {
+                self.pending_trash.clear();
+            }
+        }
+
+        if let Some((idx, kept_idx)) = self.pending_move {
+            let mut confirmed = false;
+            let mut cancelled = false;
+            egui::Window::new("Move this file?")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    if let (Some(image), Some(kept)) = (&self.images[idx], &self.images[kept_idx]) {
+                        ui.monospace(format!("{} → {}", kept.path, image.path));
+                    }
+                    ui.label(
+                        "⚠ The Lightroom catalog will show the moved file as missing, or its \
+                         edits on the replaced one",
+                    );
+                    ui.horizontal(|ui| {
+                        confirmed = ui.button("Move anyway").clicked();
+                        cancelled = ui.button("Cancel").clicked();
+                    });
+                });
+            if confirmed {
+                self.pending_move = None;
+                if let Some(entry) = self.replace_image(idx, kept_idx, ReplaceMode::Move) {
+                    self.push_action(ReviewAction::Replace(entry));
+                }
+            } else if cancelled {
+                self.pending_move = None;
+            }
+        }
+
+        if !self.cloud_hidden && (self.cloud_progress.is_some() || self.cloud_matches.is_some()) {
+            let mut open = true;
+            egui::Window::new("Google Photos")
+                .open(&mut open)
+                .default_size(Vec2::new(600.0, 400.0))
+                .show(ctx, |ui| {
+                    if let Some(progress) = &self.cloud_progress {
+                        ui.horizontal(|ui| {
+                            ui.spinner().widget_info(|| {
+                                WidgetInfo::labeled(WidgetType::Label, progress.as_str())
+                            });
+                            ui.label(progress.as_str());
+                        });
+                    }
+                    let Some(matches) = &self.cloud_matches else {
+                        return;
+                    };
+                    let path = |idx: usize| self.images[idx].as_ref().map(|image| &image.path);
+                    let (uploaded, missing): (Vec<_>, Vec<_>) =
+                        matches.iter().partition(|(_, found)| found.is_some());
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.collapsing(format!("Not in Google Photos ({})", missing.len()), |ui| {
+                            for (idx, _) in &missing {
+                                if let Some(path) = path(*idx) {
+                                    ui.monospace(path);
+                                }
+                            }
+                        });
+                        ui.collapsing(format!("Already uploaded ({})", uploaded.len()), |ui| {
+                            for (idx, found) in &uploaded {
+                                if let (Some(path), Some(found)) = (path(*idx), found) {
+                                    ui.monospace(format!("{} ☁ {}", path, found));
+                                }
+                            }
+                        });
+                    });
+                });
+            if !open {
+                self.cloud_matches = None;
+                self.cloud_hidden = self.cloud_progress.is_some();
+            }
+        }
+
+        if let Some((old, new, diff)) = &self.session_diff {
+            let mut open = true;
+            egui::Window::new("Session changes")
+                .open(&mut open)
+                .default_size(Vec2::new(600.0, 400.0))
+                .show(ctx, |ui| {
+                    ui.label(format!("From {} to {}", old, new));
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        ui.collapsing(
+                            format!("New duplicates ({})", diff.new_duplicates.len()),
+                            |ui| {
+                                for (a, b) in &diff.new_duplicates {
+                                    ui.monospace(format!("{} ↔ {}", a, b));
+                                }
+                            },
+                        );
+                        ui.collapsing(
+                            format!("Resolved duplicates ({})", diff.resolved_duplicates.len()),
+                            |ui| {
+                                for (a, b) in &diff.resolved_duplicates {
+                                    ui.monospace(format!("{} ↔ {}", a, b));
+                                }
+                            },
+                        );
+                        ui.collapsing(format!("Added files ({})", diff.added_files.len()), |ui| {
+                            for path in &diff.added_files {
+                                ui.monospace(path);
+                            }
+                        });
+                        ui.collapsing(
+                            format!("Removed files ({})", diff.removed_files.len()),
+                            |ui| {
+                                for path in &diff.removed_files {
+                                    ui.monospace(path);
+                                }
+                            },
+                        );
+                    });
+                });
+            if !open {
+                self.session_diff = None;
+            }
+        }
+
+        if self.treemap_open {
+            let mut open = true;
+            let mut zoom = None;
+            let mut content = None;
+            let window = egui::Window::new("Disk usage")
+                .open(&mut open)
+                .default_size(Vec2::new(900.0, 600.0));
+            let shown = layout::place(window, self.layout.disk_usage).show(ctx, |ui| {
+                content = Some(ui.max_rect());
+                let Some(tree) = &self.treemap else {
+                    ui.spinner();
+                    return;
+                };
+                ui.horizontal(|ui| {
+                    if named(ui.button("."), "Scanned directory").clicked() {
+                        zoom = Some(Vec::new());
+                    }
+                    for (depth, dir) in self.treemap_zoom.iter().enumerate() {
+                        ui.label("/");
+                        if ui.button(dir).clicked() {
+                            zoom = Some(self.treemap_zoom[..=depth].to_vec());
+                        }
+                    }
+                    ui.label("Red: redundant copies. Click a directory to zoom in.");
+                });
+                if let Some(node) = tree.get(&self.treemap_zoom) {
+                    if let Some(path) = treemap::show(ui, node) {
+                        zoom = Some([self.treemap_zoom.clone(), path].concat());
+                    }
+                }
+            });
+            if let (Some(shown), Some(content)) = (shown, content) {
+                self.layout.disk_usage =
+                    Some(layout::Rect::of_window(shown.response.rect, content));
+            }
+            if let Some(zoom) = zoom {
+                self.treemap_zoom = zoom;
+            }
+            if !open {
+                self.treemap_open = false;
+                self.treemap = None;
+            }
+        }
+
+        if let Some(idx) = self.inspected {
+            let mut open = true;
+            let mut to_orient = None;
+            let title = self.images[idx]
+                .as_ref()
+                .map_or(String::new(), |image| image.path.clone());
+            let mut content = None;
+            let window = egui::Window::new(title)
+                .id(egui::Id::new("inspector"))
+                .open(&mut open)
+                .default_size(Vec2::new(800.0, 600.0));
+            let shown = layout::place(window, self.layout.inspector).show(ctx, |ui| {
+                content = Some(ui.max_rect());
+                match &self.full_texture {
+                    Some(texture) => {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("{:.0}%", self.inspector_zoom * 100.0));
+                            if ui.button("1:1").clicked() {
+                                self.inspector_zoom = 1.0;
+                            }
+                            if named(ui.button("⟲"), "Rotate left").clicked() {
+                                self.inspector_rotation = (self.inspector_rotation + 3) % 4;
+                            }
+                            if named(ui.button("⟳"), "Rotate right").clicked() {
+                                self.inspector_rotation = (self.inspector_rotation + 1) % 4;
+                            }
+                            let wanted = exif::ORIENTATIONS[self.inspector_rotation];
+                            // Mirrored orientations are left as they are.
+                            let fixable = self.inspector_orientation.is_some_and(|orientation| {
+                                orientation != wanted && exif::ORIENTATIONS.contains(&orientation)
+                            });
+                            if fixable
+                                && ui
+                                    .button("Write orientation")
+                                    .on_hover_text(
+                                        "Save this rotation in the EXIF orientation of the file, \
+                                         the pixels are not touched",
+                                    )
+                                    .clicked()
+                            {
+                                to_orient = Some((idx, wanted));
+                            }
+                            ui.label("Pinch or Ctrl+scroll to zoom, drag with two fingers to pan");
+                        });
+                        ui.horizontal(|ui| {
+                            ui.checkbox(&mut self.show_histogram, "Histogram")
+                                .on_hover_text(
+                                    "Red, green, blue and luminance, to tell which copy was \
+                                 edited or clipped",
+                                );
+                            if let (true, Some(histogram)) = (self.show_histogram, &self.histogram)
+                            {
+                                let (black, white) = histogram.clipped();
+                                ui.label(format!(
+                                    "Mean luminance {:.0}, {:.1}% pure black, {:.1}% pure white",
+                                    histogram.mean_luma(),
+                                    black * 100.0,
+                                    white * 100.0
+                                ));
+                            }
+                        });
+                        let (zoom, pan, pointer) = {
+                            let input = ui.input();
+                            (
+                                input.zoom_delta(),
+                                input
+                                    .multi_touch()
+                                    .map_or(Vec2::ZERO, |touch| touch.translation_delta),
+                                input.pointer.hover_pos(),
+                            )
+                        };
+                        let mut area = egui::ScrollArea::both();
+                        if let Some((offset, viewport)) = self.inspector_view {
+                            let pointer = pointer.filter(|pos| viewport.contains(*pos));
+                            if let Some(pointer) =
+                                pointer.filter(|_| zoom != 1.0 || pan != Vec2::ZERO)
+                            {
+                                let before = self.inspector_zoom;
+                                self.inspector_zoom = (self.inspector_zoom * zoom)
+                                    .clamp(INSPECTOR_MIN_ZOOM, INSPECTOR_MAX_ZOOM);
+                                // The point of the image under the pointer stays there.
+                                let anchor = pointer - viewport.min;
+                                area = area.scroll_offset(
+                                    (offset + anchor) * (self.inspector_zoom / before)
+                                        - anchor
+                                        - pan,
+                                );
+                            }
+                        }
+                        // At 100%, a pixel of the image is a pixel of the screen.
+                        let size =
+                            texture.size_vec2() * self.inspector_zoom / ctx.pixels_per_point();
+                        let turned = if self.inspector_rotation % 2 == 1 {
+                            Vec2::new(size.y, size.x)
+                        } else {
+                            size
+                        };
+                        let rotation = self.inspector_rotation;
+                        let output = area.show(ui, |ui| {
+                            let (rect, response) =
+                                ui.allocate_exact_size(turned, egui::Sense::hover());
+                            egui::Image::new(texture, size)
+                                .rotate(
+                                    rotation as f32 * std::f32::consts::FRAC_PI_2,
+                                    Vec2::splat(0.5),
+                                )
+                                .paint_at(ui, egui::Rect::from_center_size(rect.center(), size));
+                            response.widget_info(|| {
+                                WidgetInfo::labeled(
+                                    WidgetType::Label,
+                                    format!(
+                                        "Full resolution, {} by {} pixels",
+                                        texture.size()[0],
+                                        texture.size()[1]
+                                    ),
+                                )
+                            });
+                        });
+                        self.inspector_view = Some((output.state.offset, output.inner_rect));
+                        if let (true, Some(histogram)) = (self.show_histogram, &self.histogram) {
+                            let rect = egui::Rect::from_min_size(
+                                output.inner_rect.min + Vec2::splat(8.0),
+                                histogram::SIZE,
+                            );
+                            histogram::paint(ui.painter(), rect, histogram);
+                        }
+                    }
+                    None => {
+                        ui.spinner().widget_info(|| {
+                            WidgetInfo::labeled(WidgetType::Label, "Loading full resolution")
+                        });
+                    }
+                }
+            });
+            if let (Some(shown), Some(content)) = (shown, content) {
+                self.layout.inspector = Some(layout::Rect::of_window(shown.response.rect, content));
+            }
+            if let Some((idx, orientation)) = to_orient {
+                if let Some(entry) = self.write_orientation(idx, orientation) {
+                    self.push_action(ReviewAction::Orient(entry));
+                }
+            }
+            if !open || self.images[idx].is_none() {
+                self.inspected = None;
+                self.full_texture = None;
+                self.histogram = None;
+            }
+        }
+
+        if undo {
+            self.undo();
+        } else if redo {
+            self.redo();
+        }
+
+        if self.autosave_dirty {
+            let elapsed = self.last_autosave.elapsed();
+            if elapsed >= AUTOSAVE_INTERVAL {
+                self.autosave();
+            } else {
+                ctx.request_repaint_after(AUTOSAVE_INTERVAL - elapsed);
+            }
+        }
+    }
+
+    // A clean exit: there is nothing to recover on the next launch.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        if let Err(err) = self.layout.save() {
+            error!("Failed to save the window layout: {}", err);
+        }
+        // It would write the autosave back otherwise.
+        if let Some(writer) = self.autosave_writer.take() {
+            let _ = writer.join();
+        }
+        if let Some(path) = Session::autosave_path() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let cli = Cli::parse();
+    paths::init(cli.portable);
+    let mut settings = Settings::load();
+    if let Some(threshold) = cli.threshold {
+        settings.scan.similarity_threshold = threshold;
+    }
+    settings.overrides_applied();
+
+    match &cli.command {
+        Some(cli::Command::Completions { shell }) => {
+            cli::print_completions(*shell);
+            return;
+        }
+        Some(cli::Command::Man) => {
+            if let Err(err) = cli::print_man() {
+                error!("Failed to render the man page: {}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(cli::Command::Hash {
+            alg,
+            color_management,
+            files,
+        }) => {
+            let mut failed = false;
+            for file in files {
+                match std::fs::read(file)
+                    .map_err(ImageError::IoError)
+                    .and_then(|buffer| decode_for_hashing(file, &buffer, *alg, *color_management))
+                {
+                    Ok((image, _, _)) => {
+                        let hash = hash_image(&image, *alg);
+                        let hash = match alg {
+                            HashAlgorithm::PHash => format!(
+                                "{:016x}",
+                                u64::from_le_bytes(hash.as_bytes().try_into().unwrap())
+                            ),
+                            _ => hash.to_base64(),
+                        };
+                        println!("{}  {}", hash, file.display());
+                    }
+                    Err(err) => {
+                        error!("Failed to decode image {:?}: {}", file, err);
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(cli::Command::Diff { old, new }) => {
+            match Session::read(old).and_then(|old| Ok((old, Session::read(new)?))) {
+                Ok((old, new)) => print!("{}", session::diff(&old, &new)),
+                Err(err) => {
+                    error!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(cli::Command::CacheServer { dir, listen }) => {
+            if let Err(err) = netcache::serve(dir, listen) {
+                error!("Failed to serve the shared hash cache: {}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(cli::Command::Cache { sample }) => {
+            match cache::maintain(*sample) {
+                Ok(report) => println!("{}", report),
+                Err(err) => {
+                    error!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(cli::Command::Index { action }) => {
+            let result = match action {
+                cli::IndexAction::Build { path } => {
+                    let count = index::build(path, &settings.scan);
+                    println!("Indexed {} images", count);
+                    Ok(())
+                }
+                cli::IndexAction::Export { file } => index::export(file)
+                    .map(|count| println!("Exported {} entries to {}", count, file.display())),
+                cli::IndexAction::Import { file } => index::import(file)
+                    .map(|count| println!("Imported {} entries from {}", count, file.display())),
+            };
+            if let Err(err) = result {
+                error!("{}", err);
+                std::process::exit(1);
+            }
+            return;
+        }
+        None => {}
+    }
 
-    let options = eframe::NativeOptions {
+    let mut options = eframe::NativeOptions {
         drag_and_drop_support: false,
-        maximized: true,
         ..Default::default()
     };
+    let layout = layout::Layout::load();
+    layout.apply(&mut options);
     eframe::run_native(
         "Image dedup",
         options,
-        Box::new(|_cc| Box::new(MyApp::new())),
+        Box::new(|cc| Box::new(MyApp::new(cli, settings, layout, &cc.egui_ctx))),
     )
 }
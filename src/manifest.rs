@@ -0,0 +1,62 @@
+// Checksums of the kept files, in the format of `sha256sum`, so that backup and verification tools
+// can check the cleaned library with `sha256sum -c`.
+use crate::storage;
+use rayon::prelude::*;
+use ring::digest::{Context, SHA256};
+use std::io::{Read, Write};
+use std::path::Path;
+
+fn sha256(path: &str) -> std::io::Result<String> {
+    let mut context = Context::new(&SHA256);
+    if storage::is_remote(path) {
+        context.update(&storage::read(path)?);
+    } else {
+        let mut file = std::fs::File::open(path)?;
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let len = file.read(&mut buffer)?;
+            if len == 0 {
+                break;
+            }
+            context.update(&buffer[..len]);
+        }
+    }
+    Ok(context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+// Like GNU coreutils, names with a backslash or a newline are escaped and the line starts with a
+// backslash.
+fn line(digest: &str, path: &str) -> String {
+    if path.contains(['\\', '\n']) {
+        let escaped = path.replace('\\', "\\\\").replace('\n', "\\n");
+        format!("\\{}  {}\n", digest, escaped)
+    } else {
+        format!("{}  {}\n", digest, path)
+    }
+}
+
+// Hashes the files in parallel and writes them in the given order. Returns the number of files
+// written and the ones which could not be read, with the error.
+pub fn write(path: &Path, files: &[String]) -> std::io::Result<(usize, Vec<(String, String)>)> {
+    let digests: Vec<(&String, std::io::Result<String>)> =
+        files.par_iter().map(|file| (file, sha256(file))).collect();
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    let mut written = 0;
+    let mut failed = Vec::new();
+    for (file, digest) in digests {
+        match digest {
+            Ok(digest) => {
+                out.write_all(line(&digest, file).as_bytes())?;
+                written += 1;
+            }
+            Err(err) => failed.push((file.clone(), err.to_string())),
+        }
+    }
+    out.flush()?;
+    Ok((written, failed))
+}
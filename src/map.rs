@@ -0,0 +1,91 @@
+// Where the two images of a pair were taken, when both are geotagged. There are no map tiles, the
+// app works offline: both pins are drawn to scale with a scale bar, which is enough to reveal that
+// "duplicates" are different shots from the same trip. A link opens the spot on OpenStreetMap.
+use eframe::egui;
+use egui::{Align2, Color32, FontId, Pos2, Sense, Stroke};
+
+const EARTH_RADIUS: f64 = 6_371_000.0;
+pub const SIZE: egui::Vec2 = egui::vec2(240.0, 140.0);
+// In degrees, closer pins are drawn as if this far apart, so that GPS noise does not look like
+// kilometres.
+const MIN_SPAN: f64 = 0.001;
+const PIN_RADIUS: f32 = 5.0;
+
+// Great circle distance in metres, with the haversine formula.
+pub fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat_a, lat_b) = (a.0.to_radians(), b.0.to_radians());
+    let d_lat = lat_b - lat_a;
+    let d_lon = (b.1 - a.1).to_radians();
+    let h = (d_lat / 2.0).sin().powi(2) + lat_a.cos() * lat_b.cos() * (d_lon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+pub fn format_distance(metres: f64) -> String {
+    if metres < 1000.0 {
+        format!("{:.0} m", metres)
+    } else {
+        format!("{:.1} km", metres / 1000.0)
+    }
+}
+
+pub fn url(a: (f64, f64), b: (f64, f64)) -> String {
+    format!(
+        "https://www.openstreetmap.org/directions?route={:.6},{:.6};{:.6},{:.6}",
+        a.0, a.1, b.0, b.1
+    )
+}
+
+// Both pins, labelled A and B, on an equirectangular projection centred between them.
+pub fn show(ui: &mut egui::Ui, a: (f64, f64), b: (f64, f64)) -> egui::Response {
+    let (rect, response) = ui.allocate_exact_size(SIZE, Sense::hover());
+    let painter = ui.painter_at(rect);
+    let visuals = ui.visuals();
+    painter.rect_filled(rect, 2.0, visuals.extreme_bg_color);
+    painter.rect_stroke(rect, 2.0, visuals.widgets.noninteractive.bg_stroke);
+
+    let middle = ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+    let shrink = middle.0.to_radians().cos();
+    // Degrees of latitude per point, with room around the pins.
+    let span = ((a.0 - b.0).abs())
+        .max((a.1 - b.1).abs() * shrink)
+        .max(MIN_SPAN)
+        * 1.6;
+    let scale = span / rect.width().min(rect.height()) as f64;
+    let project = |(lat, lon): (f64, f64)| {
+        rect.center()
+            + egui::vec2(
+                ((lon - middle.1) * shrink / scale) as f32,
+                ((middle.0 - lat) / scale) as f32,
+            )
+    };
+
+    // A quarter of the width, in metres.
+    let bar = rect.width() / 4.0;
+    let bar_metres = distance(middle, (middle.0 + bar as f64 * scale, middle.1));
+    let start = Pos2::new(rect.left() + 8.0, rect.bottom() - 8.0);
+    let stroke = Stroke::new(1.0, visuals.text_color());
+    painter.line_segment([start, start + egui::vec2(bar, 0.0)], stroke);
+    painter.text(
+        start + egui::vec2(bar + 4.0, 0.0),
+        Align2::LEFT_BOTTOM,
+        format_distance(bar_metres),
+        FontId::proportional(11.0),
+        visuals.text_color(),
+    );
+
+    for (location, label, color) in [
+        (a, "A", Color32::from_rgb(230, 80, 60)),
+        (b, "B", Color32::from_rgb(60, 140, 230)),
+    ] {
+        let pos = project(location);
+        painter.circle(pos, PIN_RADIUS, color, Stroke::new(1.0, Color32::WHITE));
+        painter.text(
+            pos + egui::vec2(PIN_RADIUS + 2.0, -PIN_RADIUS),
+            Align2::LEFT_BOTTOM,
+            label,
+            FontId::proportional(12.0),
+            visuals.text_color(),
+        );
+    }
+    response
+}
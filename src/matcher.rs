@@ -0,0 +1,217 @@
+use crate::Message;
+use eframe::egui;
+use img_hash::ImageHash;
+use std::collections::HashSet;
+use std::sync::mpsc::{Receiver, Sender};
+
+// Beyond this many differing bits per byte, most buckets are candidates and the BK-tree prunes
+// better.
+const MAX_BYTE_DISTANCE: u32 = 2;
+
+// BK-tree over the Hamming distance: the children of a node are keyed by their distance to it, so
+// by the triangle inequality only those keyed within `max_distance` of the distance from the query
+// to the node can hold matches. Used for thresholds too high for the byte buckets.
+struct Node {
+    idx: usize,
+    hash: ImageHash,
+    // Distance to this node and index in `BkTree::nodes`.
+    children: Vec<(u32, usize)>,
+}
+
+#[derive(Default)]
+struct BkTree {
+    nodes: Vec<Node>,
+}
+
+impl BkTree {
+    fn insert(&mut self, idx: usize, hash: ImageHash) {
+        let new = self.nodes.len();
+        let mut node = 0;
+        while node < self.nodes.len() {
+            let distance = self.nodes[node].hash.dist(&hash);
+            match self.nodes[node]
+                .children
+                .iter()
+                .find(|(d, _)| *d == distance)
+            {
+                Some(&(_, child)) => node = child,
+                None => {
+                    self.nodes[node].children.push((distance, new));
+                    break;
+                }
+            }
+        }
+        self.nodes.push(Node {
+            idx,
+            hash,
+            children: Vec::new(),
+        });
+    }
+
+    // Images inserted with a hash within `max_distance` of `hash`, removed or not.
+    fn candidates(&self, hash: &ImageHash, max_distance: u32) -> Vec<usize> {
+        let mut candidates = Vec::new();
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(node) = stack.pop() {
+            let node = &self.nodes[node];
+            let distance = node.hash.dist(hash);
+            if distance <= max_distance {
+                candidates.push(node.idx);
+            }
+            let range = distance.saturating_sub(max_distance)..=distance + max_distance;
+            stack.extend(
+                node.children
+                    .iter()
+                    .filter(|(d, _)| range.contains(d))
+                    .map(|&(_, child)| child),
+            );
+        }
+        candidates
+    }
+}
+
+// Multi-index hashing: two hashes within distance `d` of each other, split into `n` bytes, have at
+// least one byte within `d / n` bits of each other (pigeonhole principle). Indexing the images by
+// the value of each of their bytes gives the few candidates worth an exact distance check.
+#[derive(Default)]
+struct Index {
+    hashes: Vec<Option<ImageHash>>,
+    // Byte position -> byte value -> images.
+    buckets: Vec<Vec<Vec<usize>>>,
+    tree: BkTree,
+}
+
+impl Index {
+    fn insert(&mut self, idx: usize, hash: ImageHash) {
+        for (position, &byte) in hash.as_bytes().iter().enumerate() {
+            if self.buckets.len() <= position {
+                self.buckets.push(vec![Vec::new(); 256]);
+            }
+            self.buckets[position][byte as usize].push(idx);
+        }
+        if self.hashes.len() <= idx {
+            self.hashes.resize(idx + 1, None);
+        }
+        self.hashes[idx] = Some(hash.clone());
+        self.tree.insert(idx, hash);
+    }
+
+    // Bucket entries and tree nodes of removed images are left behind and skipped when matching.
+    fn remove(&mut self, idx: usize) {
+        if let Some(hash) = self.hashes.get_mut(idx) {
+            *hash = None;
+        }
+    }
+
+    // Images strictly closer than `threshold` to `hash`.
+    fn matches(&self, hash: &ImageHash, threshold: u32) -> Vec<usize> {
+        let Some(max_distance) = threshold.checked_sub(1) else {
+            return Vec::new();
+        };
+        let bytes = hash.as_bytes();
+        let byte_distance = max_distance / bytes.len().max(1) as u32;
+        let is_match = |i: usize| {
+            self.hashes[i]
+                .as_ref()
+                .is_some_and(|other| other.dist(hash) <= max_distance)
+        };
+        let mut candidates = HashSet::new();
+        if byte_distance > MAX_BYTE_DISTANCE {
+            candidates.extend(self.tree.candidates(hash, max_distance));
+        } else {
+            for (position, &byte) in bytes.iter().enumerate() {
+                let Some(buckets) = self.buckets.get(position) else {
+                    break;
+                };
+                for value in 0..=255u8 {
+                    if (value ^ byte).count_ones() <= byte_distance {
+                        candidates.extend(&buckets[value as usize]);
+                    }
+                }
+            }
+        }
+        let mut matches: Vec<usize> = candidates.into_iter().filter(|&i| is_match(i)).collect();
+        matches.sort_unstable();
+        matches
+    }
+}
+
+pub enum MatchCommand {
+    // Forgets all hashes, for a new scan. Pairs are tagged with the generation they were found in,
+    // so that the UI can drop the ones of a previous scan still in flight.
+    Reset(u64),
+    // An image whose pairs are already known, e.g. from a recovered session: only matched against
+    // the images added after it.
+    Insert(usize, ImageHash),
+    // Matched against all known images, each match is sent back as `Message::PairFound`.
+    Add {
+        idx: usize,
+        hash: ImageHash,
+        threshold: u32,
+    },
+    Remove(usize),
+    // All the pairs of the known images, sent back at once as `Message::Rematched`, e.g. when the
+    // threshold changed after the scan.
+    Rematch(u32),
+}
+
+// Runs on its own thread and owns a copy of the hashes, indexed like `MyApp::images`, so that the
+// distance computations never block the UI.
+pub fn spawn(sender: Sender<Message>, ctx: egui::Context) -> Sender<MatchCommand> {
+    let (command_sender, command_receiver) = std::sync::mpsc::channel();
+    std::thread::Builder::new()
+        .name("matcher".to_owned())
+        .spawn(move || run(command_receiver, sender, ctx))
+        .unwrap();
+    command_sender
+}
+
+fn run(commands: Receiver<MatchCommand>, sender: Sender<Message>, ctx: egui::Context) {
+    let mut generation = 0;
+    let mut index = Index::default();
+    for command in commands {
+        match command {
+            MatchCommand::Reset(new_generation) => {
+                generation = new_generation;
+                index = Index::default();
+            }
+            MatchCommand::Insert(idx, hash) => index.insert(idx, hash),
+            MatchCommand::Add {
+                idx,
+                hash,
+                threshold,
+            } => {
+                let matches = index.matches(&hash, threshold);
+                for &i in matches.iter().filter(|&&i| i != idx) {
+                    let _ = sender.send(Message::PairFound(generation, idx, i));
+                }
+                index.insert(idx, hash);
+                if !matches.is_empty() {
+                    ctx.request_repaint();
+                }
+            }
+            MatchCommand::Remove(idx) => index.remove(idx),
+            MatchCommand::Rematch(threshold) => {
+                // Each pair as `Add` finds it: the image added last first.
+                let pairs: Vec<(usize, usize)> = index
+                    .hashes
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(idx, hash)| Some((idx, hash.as_ref()?)))
+                    .flat_map(|(idx, hash)| {
+                        index
+                            .matches(hash, threshold)
+                            .into_iter()
+                            .filter(move |&i| i < idx)
+                            .map(move |i| (idx, i))
+                    })
+                    .collect();
+                let _ = sender.send(Message::Rematched(generation, pairs));
+                ctx.request_repaint();
+            }
+        }
+    }
+}
@@ -0,0 +1,20 @@
+// WhatsApp, Telegram and the like resize photos and re-save them at a low JPEG quality, which moves
+// their hash further from the original than a plain re-save would. Such pairs get a looser
+// threshold, see `ScanOptions::messaging_threshold`.
+
+// The largest photos they send are 2560 pixels on the longest side (Telegram), 1600 for WhatsApp.
+const MAX_SIDE: u32 = 2560;
+
+// The copy has at most this fraction of the pixels of the original.
+const MAX_AREA_RATIO: f64 = 0.5;
+
+// Resizing keeps the aspect ratio, a crop does not.
+const ASPECT_RATIO_TOLERANCE: f64 = 0.02;
+
+pub fn is_copy(copy: (u32, u32), original: (u32, u32)) -> bool {
+    let area = |(width, height): (u32, u32)| width as f64 * height as f64;
+    let aspect_ratio = |(width, height): (u32, u32)| width as f64 / height.max(1) as f64;
+    copy.0.max(copy.1) <= MAX_SIDE
+        && area(copy) <= area(original) * MAX_AREA_RATIO
+        && (aspect_ratio(copy) / aspect_ratio(original) - 1.0).abs() <= ASPECT_RATIO_TOLERANCE
+}
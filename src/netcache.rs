@@ -0,0 +1,196 @@
+// A hash cache shared by the machines of a household or team, e.g. on the NAS holding the photos,
+// so that an image hashed on one of them is never hashed again on another. Paths differ from one
+// machine to the next, so it is keyed by the SHA-256 of the file content: `GET` and `PUT` of
+// `<url>/<algorithm>/<digest>` with the JSON of an `Entry`, and 404 when unknown. Any HTTP server
+// storing what is PUT will do, `img-dedup cache-server` is one.
+use crate::settings::HashAlgorithm;
+use log::{debug, error, info};
+use ring::digest::{digest, SHA256};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// On a local network, a slow answer is worse than hashing again.
+const TIMEOUT: Duration = Duration::from_secs(5);
+// Entries are a few hundred bytes.
+const MAX_BODY_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    // Base64, as produced by `ImageHash::to_base64`.
+    pub hash: String,
+    pub dimensions: (u32, u32),
+    #[serde(default)]
+    pub frames: u32,
+}
+
+// Color management changes the hash of images with an ICC profile, they are cached apart.
+pub fn key(hash_alg: HashAlgorithm, color_management: bool, content: &[u8]) -> String {
+    let digest: String = digest(&SHA256, content)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if color_management {
+        format!("{}/{}", hash_alg.name(), digest)
+    } else {
+        format!("{}-unmanaged/{}", hash_alg.name(), digest)
+    }
+}
+
+// The shared cache as used by one scan. Once the server cannot be reached, the rest of the scan
+// goes without it rather than waiting for each file, the next scan tries again.
+pub struct Shared {
+    url: String,
+    unreachable: AtomicBool,
+}
+
+impl Shared {
+    pub fn new(url: String) -> Self {
+        Shared {
+            url,
+            unreachable: AtomicBool::new(false),
+        }
+    }
+
+    fn unreachable(&self, url: &str, err: ureq::Error) {
+        if !self.unreachable.swap(true, Ordering::Relaxed) {
+            error!("Not using the shared hash cache {}: {}", url, err);
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Entry> {
+        if self.unreachable.load(Ordering::Relaxed) {
+            return None;
+        }
+        let url = format!("{}/{}", self.url.trim_end_matches('/'), key);
+        match ureq::get(&url).timeout(TIMEOUT).call() {
+            Ok(response) => response
+                .into_json()
+                .map_err(|err| debug!("Invalid entry {}: {}", url, err))
+                .ok(),
+            Err(ureq::Error::Status(404, _)) => None,
+            Err(ureq::Error::Status(status, _)) => {
+                debug!("Failed to get {}: HTTP {}", url, status);
+                None
+            }
+            Err(err) => {
+                self.unreachable(&url, err);
+                None
+            }
+        }
+    }
+
+    pub fn put(&self, key: &str, entry: &Entry) {
+        if self.unreachable.load(Ordering::Relaxed) {
+            return;
+        }
+        let url = format!("{}/{}", self.url.trim_end_matches('/'), key);
+        match ureq::put(&url).timeout(TIMEOUT).send_json(entry) {
+            Ok(_) => {}
+            Err(ureq::Error::Status(status, _)) => {
+                debug!("Failed to put {}: HTTP {}", url, status)
+            }
+            Err(err) => self.unreachable(&url, err),
+        }
+    }
+}
+
+// `<algorithm>/<digest>`, anything else could escape the directory.
+fn file_for(dir: &Path, key: &str) -> Option<PathBuf> {
+    let (alg, digest) = key.split_once('/')?;
+    let valid_alg = !alg.is_empty()
+        && alg
+            .bytes()
+            .all(|byte| byte.is_ascii_lowercase() || byte == b'_' || byte == b'-');
+    let valid_digest = digest.len() == 64 && digest.bytes().all(|byte| byte.is_ascii_hexdigit());
+    (valid_alg && valid_digest).then(|| dir.join(alg).join(digest))
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &[u8]) -> std::io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+fn handle(dir: &Path, mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        reader.read_line(&mut header)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut parts = request.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    let Some(file) = file_for(dir, target.trim_start_matches('/')) else {
+        return respond(&mut stream, "400 Bad Request", b"");
+    };
+    match method {
+        "GET" => match std::fs::read(&file) {
+            Ok(body) => respond(&mut stream, "200 OK", &body),
+            Err(_) => respond(&mut stream, "404 Not Found", b""),
+        },
+        "PUT" if content_length > MAX_BODY_SIZE => {
+            respond(&mut stream, "413 Payload Too Large", b"")
+        }
+        "PUT" => {
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body)?;
+            if serde_json::from_slice::<Entry>(&body).is_err() {
+                return respond(&mut stream, "400 Bad Request", b"");
+            }
+            if let Some(parent) = file.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            // Written aside then renamed, concurrent readers never see half an entry.
+            let tmp = file.with_extension(format!("{:?}.tmp", std::thread::current().id()));
+            std::fs::write(&tmp, &body)?;
+            std::fs::rename(&tmp, &file)?;
+            respond(&mut stream, "204 No Content", b"")
+        }
+        _ => respond(&mut stream, "405 Method Not Allowed", b""),
+    }
+}
+
+// One file per entry under `dir`, one thread per connection. Meant for a trusted local network:
+// there is no authentication.
+pub fn serve(dir: &Path, address: &str) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let listener = TcpListener::bind(address)?;
+    info!(
+        "Serving the shared hash cache {} on http://{}",
+        dir.display(),
+        listener.local_addr()?
+    );
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else {
+            continue;
+        };
+        let dir = dir.to_path_buf();
+        std::thread::spawn(move || {
+            if let Err(err) = handle(&dir, stream) {
+                debug!("Failed to serve a request: {}", err);
+            }
+        });
+    }
+    Ok(())
+}
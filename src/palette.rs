@@ -0,0 +1,265 @@
+// Command palette: every action by name, found with the same fuzzy matching as the results search,
+// so that the growing number of features stays discoverable without hunting through the window.
+use crate::bands::Band;
+use crate::sort::SortKey;
+use eframe::egui;
+use egui::{Key, KeyboardShortcut, Modifiers};
+
+pub const OPEN_PALETTE: [KeyboardShortcut; 2] = [
+    KeyboardShortcut::new(Modifiers::COMMAND, Key::P),
+    KeyboardShortcut::new(Modifiers::COMMAND, Key::K),
+];
+
+// Steps of the threshold commands.
+pub const THRESHOLD_STEP: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    OpenDirectory,
+    LoadIndex,
+    ExportResults,
+    ExportPairs,
+    ExportChecksums,
+    SaveComparison,
+    SaveSession,
+    ResumeSession,
+    CompareSessions,
+    DiskUsage,
+    WastedSpace,
+    PairByCaptureTime,
+    Undo,
+    Redo,
+    RaiseThreshold,
+    LowerThreshold,
+    ShowAll,
+    ShowBand(Band),
+    TrashBand,
+    SortBy(SortKey),
+    ReverseSort,
+    ClearFilter,
+    StripAndDedupeAll,
+    HardlinkAll,
+    CloneAll,
+    TrashAllDuplicates,
+    TrashOtherCopies,
+    MarkByRule,
+    TrashMarked,
+    SaveSettings,
+}
+
+impl Command {
+    pub fn all() -> Vec<Command> {
+        let mut commands = vec![
+            Command::OpenDirectory,
+            Command::LoadIndex,
+            Command::ExportResults,
+            Command::ExportPairs,
+            Command::ExportChecksums,
+            Command::SaveComparison,
+            Command::SaveSession,
+            Command::ResumeSession,
+            Command::CompareSessions,
+            Command::DiskUsage,
+            Command::WastedSpace,
+            Command::PairByCaptureTime,
+            Command::Undo,
+            Command::Redo,
+            Command::RaiseThreshold,
+            Command::LowerThreshold,
+            Command::ShowAll,
+        ];
+        commands.extend(Band::ALL.map(Command::ShowBand));
+        commands.push(Command::TrashBand);
+        commands.extend(SortKey::ALL.map(Command::SortBy));
+        commands.extend([
+            Command::ReverseSort,
+            Command::ClearFilter,
+            Command::StripAndDedupeAll,
+            Command::HardlinkAll,
+            Command::CloneAll,
+            Command::TrashAllDuplicates,
+            Command::TrashOtherCopies,
+            Command::MarkByRule,
+            Command::TrashMarked,
+            Command::SaveSettings,
+        ]);
+        commands
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Command::OpenDirectory => "Open directory…".to_owned(),
+            Command::LoadIndex => "Load index…".to_owned(),
+            Command::ExportResults => "Export results…".to_owned(),
+            Command::ExportPairs => "Export similar pairs as JSON or CSV…".to_owned(),
+            Command::ExportChecksums => "Export checksums of the kept files…".to_owned(),
+            Command::SaveComparison => "Save comparison of the selected pair…".to_owned(),
+            Command::SaveSession => "Save session…".to_owned(),
+            Command::ResumeSession => "Resume a saved session…".to_owned(),
+            Command::CompareSessions => "Compare two saved sessions…".to_owned(),
+            Command::DiskUsage => "Disk usage…".to_owned(),
+            Command::WastedSpace => "Compute wasted space by directory".to_owned(),
+            Command::PairByCaptureTime => "Pair images shot at the same time".to_owned(),
+            Command::Undo => "Undo".to_owned(),
+            Command::Redo => "Redo".to_owned(),
+            Command::RaiseThreshold => {
+                format!("Raise similarity threshold (+{})", THRESHOLD_STEP)
+            }
+            Command::LowerThreshold => {
+                format!("Lower similarity threshold (-{})", THRESHOLD_STEP)
+            }
+            Command::ShowAll => "Show all pairs".to_owned(),
+            Command::ShowBand(band) => format!("Show {} pairs", band.name()),
+            Command::TrashBand => "Trash the duplicates of this tab".to_owned(),
+            Command::SortBy(key) => format!("Sort by {}", key.name()),
+            Command::ReverseSort => "Reverse sort order".to_owned(),
+            Command::ClearFilter => "Show pairs of all directories".to_owned(),
+            Command::StripAndDedupeAll => "Strip and dedupe all identical pixels".to_owned(),
+            Command::HardlinkAll => "Hardlink all verified identical".to_owned(),
+            Command::CloneAll => "Clone all verified identical".to_owned(),
+            Command::TrashAllDuplicates => "Trash all verified duplicates".to_owned(),
+            Command::TrashOtherCopies => "Trash the copies of suggested keepers".to_owned(),
+            Command::MarkByRule => "Mark duplicates for deletion with the keep rule".to_owned(),
+            Command::TrashMarked => "Trash the marked files".to_owned(),
+            Command::SaveSettings => "Save settings".to_owned(),
+        }
+    }
+
+    pub fn shortcut(self) -> Option<KeyboardShortcut> {
+        match self {
+            Command::OpenDirectory => Some(KeyboardShortcut::new(Modifiers::COMMAND, Key::O)),
+            Command::ExportResults => Some(KeyboardShortcut::new(Modifiers::COMMAND, Key::E)),
+            Command::Undo => Some(KeyboardShortcut::new(Modifiers::COMMAND, Key::Z)),
+            Command::Redo => Some(KeyboardShortcut::new(
+                Modifiers::COMMAND | Modifiers::SHIFT,
+                Key::Z,
+            )),
+            Command::SaveSettings => Some(KeyboardShortcut::new(Modifiers::COMMAND, Key::S)),
+            _ => None,
+        }
+    }
+}
+
+// Same rule as the results search: every word of the query, in order but not necessarily
+// adjacent, ignoring case.
+fn matches(query: &str, name: &str) -> bool {
+    let name = name.to_lowercase();
+    query.split_whitespace().all(|word| {
+        let mut name = name.chars();
+        word.to_lowercase().chars().all(|c| name.any(|n| n == c))
+    })
+}
+
+#[derive(Default)]
+pub struct Palette {
+    open: bool,
+    query: String,
+    // Index in the matching commands.
+    selected: usize,
+}
+
+// The command whose shortcut was pressed, text fields keep theirs, e.g. undo.
+pub fn shortcut_pressed(ctx: &egui::Context) -> Option<Command> {
+    if ctx.wants_keyboard_input() {
+        return None;
+    }
+    let mut input = ctx.input_mut();
+    Command::all().into_iter().find(|command| {
+        command
+            .shortcut()
+            .is_some_and(|shortcut| input.consume_shortcut(&shortcut))
+    })
+}
+
+// Opens on Ctrl+P or Cmd+K, returns the command picked.
+pub fn show(ctx: &egui::Context, palette: &mut Palette) -> Option<Command> {
+    let toggled = {
+        let mut input = ctx.input_mut();
+        OPEN_PALETTE
+            .iter()
+            .any(|shortcut| input.consume_shortcut(shortcut))
+    };
+    if toggled {
+        palette.open = !palette.open;
+        palette.query.clear();
+        palette.selected = 0;
+    }
+    if !palette.open {
+        return None;
+    }
+
+    let commands: Vec<Command> = Command::all()
+        .into_iter()
+        .filter(|command| matches(&palette.query, &command.name()))
+        .collect();
+    let (up, down, enter, escape) = {
+        let mut input = ctx.input_mut();
+        (
+            input.consume_key(Modifiers::NONE, Key::ArrowUp),
+            input.consume_key(Modifiers::NONE, Key::ArrowDown),
+            input.key_pressed(Key::Enter),
+            input.consume_key(Modifiers::NONE, Key::Escape),
+        )
+    };
+    if up {
+        palette.selected = palette.selected.saturating_sub(1);
+    }
+    if down {
+        palette.selected += 1;
+    }
+    palette.selected = palette.selected.min(commands.len().saturating_sub(1));
+
+    let mut picked = None;
+    egui::Window::new("Command palette")
+        .title_bar(false)
+        .collapsible(false)
+        .resizable(false)
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 40.0))
+        .fixed_size(egui::vec2(420.0, 0.0))
+        .show(ctx, |ui| {
+            let query = ui.add(
+                egui::TextEdit::singleline(&mut palette.query)
+                    .hint_text("Type a command")
+                    .desired_width(f32::INFINITY),
+            );
+            query.widget_info(|| {
+                let mut info = egui::WidgetInfo::text_edit("", &palette.query);
+                info.label = Some("Command palette".to_owned());
+                info
+            });
+            query.request_focus();
+            if query.changed() {
+                palette.selected = 0;
+            }
+            egui::ScrollArea::vertical()
+                .max_height(300.0)
+                .show(ui, |ui| {
+                    for (i, command) in commands.iter().enumerate() {
+                        let selected = i == palette.selected;
+                        let row = ui.horizontal(|ui| {
+                            let label = ui.selectable_label(selected, command.name());
+                            if let Some(shortcut) = command.shortcut() {
+                                ui.weak(ctx.format_shortcut(&shortcut));
+                            }
+                            label
+                        });
+                        if selected && (up || down) {
+                            row.response.scroll_to_me(None);
+                        }
+                        if row.inner.clicked() {
+                            picked = Some(*command);
+                        }
+                    }
+                    if commands.is_empty() {
+                        ui.weak("No matching command");
+                    }
+                });
+        });
+    if enter {
+        picked = picked.or_else(|| commands.get(palette.selected).copied());
+    }
+    if picked.is_some() || escape {
+        palette.open = false;
+    }
+    picked
+}
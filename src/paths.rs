@@ -0,0 +1,49 @@
+use log::{error, info};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+const PORTABLE_FLAG_FILE_NAME: &str = "portable.flag";
+
+// Set once at startup. `Some(dir)` means portable mode: all state lives in `dir`, next to the
+// executable, instead of the platform directories.
+static PORTABLE_DIR: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+fn executable_dir() -> Option<PathBuf> {
+    match std::env::current_exe() {
+        Ok(exe) => exe.parent().map(|dir| dir.to_path_buf()),
+        Err(err) => {
+            error!("Failed to locate the executable: {}", err);
+            None
+        }
+    }
+}
+
+pub fn init(portable: bool) {
+    let dir =
+        executable_dir().filter(|dir| portable || dir.join(PORTABLE_FLAG_FILE_NAME).is_file());
+    if let Some(dir) = &dir {
+        info!("Portable mode, storing state in {}", dir.display());
+    }
+    let _ = PORTABLE_DIR.set(dir);
+}
+
+fn portable_dir() -> Option<PathBuf> {
+    PORTABLE_DIR.get().cloned().flatten()
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    portable_dir().or_else(|| dirs::config_dir().map(|dir| dir.join("img-dedup")))
+}
+
+// `IMG_DEDUP_CACHE_DIR` wins over portable mode, e.g. to keep the caches on a faster disk.
+pub fn cache_dir() -> Option<PathBuf> {
+    std::env::var_os("IMG_DEDUP_CACHE_DIR")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(portable_dir)
+        .or_else(|| dirs::cache_dir().map(|dir| dir.join("img-dedup")))
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    portable_dir().or_else(|| dirs::data_dir().map(|dir| dir.join("img-dedup")))
+}
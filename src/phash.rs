@@ -0,0 +1,95 @@
+// Reimplementation of libpHash's `ph_dct_imagehash`, bit for bit, so that hashes can be compared
+// with databases produced by pHash based tools:
+// Y channel -> 7x7 box filter -> nearest neighbour resize to 32x32 -> DCT -> the 8x8 low
+// frequencies (skipping the first row and column) compared against their median.
+use image::{DynamicImage, GenericImageView};
+
+const SIZE: usize = 32;
+const KERNEL_RADIUS: i64 = 3;
+
+// Luma as computed by CImg's `RGBtoYCbCr` on 8 bit images.
+fn luma_channel(image: &DynamicImage) -> (Vec<u8>, usize, usize) {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let pixels = match image {
+        DynamicImage::ImageLuma8(_)
+        | DynamicImage::ImageLumaA8(_)
+        | DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_) => image.to_luma8().into_raw(),
+        // Most decoded images are already RGB, no need for a copy.
+        DynamicImage::ImageRgb8(rgb) => rgb.pixels().map(ycbcr_luma).collect(),
+        _ => image.to_rgb8().pixels().map(ycbcr_luma).collect(),
+    };
+    (pixels, width, height)
+}
+
+fn ycbcr_luma(pixel: &image::Rgb<u8>) -> u8 {
+    let [r, g, b] = pixel.0.map(f32::from);
+    let y = (66.0 * r + 129.0 * g + 25.0 * b + 128.0) / 256.0 + 16.0;
+    y.clamp(0.0, 255.0) as u8
+}
+
+// The unnormalized 7x7 box filter with clamped borders, only evaluated where the nearest
+// neighbour resize samples it.
+fn filtered_samples(pixels: &[u8], width: usize, height: usize) -> [[f64; SIZE]; SIZE] {
+    let sample = |x: usize, len: usize| ((x as f64) * (len as f64) / SIZE as f64) as i64;
+    let mut samples = [[0.0; SIZE]; SIZE];
+    for (row, samples_row) in samples.iter_mut().enumerate() {
+        let sy = sample(row, height);
+        for (col, value) in samples_row.iter_mut().enumerate() {
+            let sx = sample(col, width);
+            let mut sum = 0.0;
+            for dy in -KERNEL_RADIUS..=KERNEL_RADIUS {
+                let y = (sy + dy).clamp(0, height as i64 - 1) as usize;
+                for dx in -KERNEL_RADIUS..=KERNEL_RADIUS {
+                    let x = (sx + dx).clamp(0, width as i64 - 1) as usize;
+                    sum += f64::from(pixels[y * width + x]);
+                }
+            }
+            *value = sum;
+        }
+    }
+    samples
+}
+
+fn dct_matrix() -> [[f64; SIZE]; SIZE] {
+    let n = SIZE as f64;
+    let mut matrix = [[1.0 / n.sqrt(); SIZE]; SIZE];
+    let c1 = (2.0 / n).sqrt();
+    for (y, row) in matrix.iter_mut().enumerate().skip(1) {
+        for (x, value) in row.iter_mut().enumerate() {
+            *value = c1 * (std::f64::consts::PI / 2.0 / n * y as f64 * (2 * x + 1) as f64).cos();
+        }
+    }
+    matrix
+}
+
+pub fn phash(image: &DynamicImage) -> u64 {
+    let (pixels, width, height) = luma_channel(image);
+    if width == 0 || height == 0 {
+        return 0;
+    }
+    let img = filtered_samples(&pixels, width, height);
+    let c = dct_matrix();
+
+    // Coefficients (1..=8, 1..=8) of C * img * C^T, in row major order.
+    let mut coefficients = [0.0; 64];
+    for (i, coefficient) in coefficients.iter_mut().enumerate() {
+        let (r, col) = (1 + i / 8, 1 + i % 8);
+        let mut sum = 0.0;
+        for (k, img_row) in img.iter().enumerate() {
+            let left: f64 = (0..SIZE).map(|j| img_row[j] * c[col][j]).sum();
+            sum += c[r][k] * left;
+        }
+        *coefficient = sum;
+    }
+
+    let mut sorted = coefficients;
+    sorted.sort_by(f64::total_cmp);
+    let median = (sorted[31] + sorted[32]) / 2.0;
+
+    coefficients
+        .iter()
+        .enumerate()
+        .filter(|(_, &value)| value > median)
+        .fold(0u64, |hash, (i, _)| hash | (1 << i))
+}
@@ -0,0 +1,45 @@
+// Disposal for filesystems without a trash, e.g. network shares, where moving to the trash fails:
+// duplicates are moved to a folder chosen by the user instead, under their path relative to the
+// scanned directory, so that files with the same name in different folders do not collide and
+// can be put back by hand too.
+use log::info;
+use std::path::{Component, Path, PathBuf};
+
+// Files outside `root` keep their whole path, minus the drive or leading `/`.
+pub fn destination(dir: &Path, root: &str, path: &str) -> PathBuf {
+    let path = Path::new(path);
+    let relative: PathBuf = match path.strip_prefix(root) {
+        Ok(relative) if !root.is_empty() => relative.to_path_buf(),
+        _ => path
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect(),
+    };
+    dir.join(relative)
+}
+
+// Renamed when on the same filesystem, copied then removed otherwise. Never overwrites.
+pub fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if to.exists() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::AlreadyExists,
+            format!("{} already exists", to.display()),
+        ));
+    }
+    if let Some(dir) = to.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    info!("Moving {} to {}", from.display(), to.display());
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    std::fs::copy(from, to)?;
+    std::fs::remove_file(from).inspect_err(|_| {
+        let _ = std::fs::remove_file(to);
+    })
+}
+
+// Companions were moved next to their image, under the same name.
+pub fn companion_destination(moved_to: &str, companion: &str) -> PathBuf {
+    Path::new(moved_to).with_file_name(Path::new(companion).file_name().unwrap_or_default())
+}
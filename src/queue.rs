@@ -0,0 +1,62 @@
+// The hashing queue: the most valuable files first rather than in walk order, so that the
+// duplicates freeing the most space show up early and can be acted on before the scan finishes.
+// Files under the priority folders come first, then the largest. Each task spawned on the scan
+// pool pops the best file queued so far, not the one it was spawned for.
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::sync::Mutex;
+
+// Higher is hashed first.
+pub type Priority = (bool, u64);
+
+struct Queued<T> {
+    priority: Priority,
+    // Ties are broken in walk order.
+    order: Reverse<usize>,
+    item: T,
+}
+
+impl<T> PartialEq for Queued<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for Queued<T> {}
+
+impl<T> PartialOrd for Queued<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for Queued<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.priority, self.order).cmp(&(other.priority, other.order))
+    }
+}
+
+// With the number of items pushed so far.
+pub struct Queue<T>(Mutex<(BinaryHeap<Queued<T>>, usize)>);
+
+impl<T> Default for Queue<T> {
+    fn default() -> Self {
+        Queue(Mutex::new((BinaryHeap::new(), 0)))
+    }
+}
+
+impl<T> Queue<T> {
+    pub fn push(&self, priority: Priority, item: T) {
+        let (heap, pushed) = &mut *self.0.lock().unwrap();
+        heap.push(Queued {
+            priority,
+            order: Reverse(*pushed),
+            item,
+        });
+        *pushed += 1;
+    }
+
+    pub fn pop(&self) -> Option<T> {
+        self.0.lock().unwrap().0.pop().map(|queued| queued.item)
+    }
+}
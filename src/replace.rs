@@ -0,0 +1,173 @@
+use crate::quarantine::move_file;
+use log::{debug, info};
+use std::fs::{FileTimes, Metadata};
+use std::io;
+use std::path::{Path, PathBuf};
+
+// Ways of getting rid of a duplicate while keeping its path valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaceMode {
+    // The duplicate's path becomes another name of the kept file.
+    Hardlink,
+    // The duplicate's path points to the kept file.
+    Symlink,
+    // The kept file is moved to the duplicate's path, replacing it.
+    Move,
+    // The duplicate's path gets a clone of the kept file: both stay independent files, but their
+    // data is stored once. Needs a file system with reflinks, e.g. btrfs, XFS or APFS.
+    Clone,
+}
+
+impl ReplaceMode {
+    pub fn label(self) -> &'static str {
+        match self {
+            ReplaceMode::Hardlink => "🔗 Hardlink to other",
+            ReplaceMode::Symlink => "↪ Symlink to other",
+            ReplaceMode::Move => "⮕ Move other here",
+            ReplaceMode::Clone => "⧉ Clone other here",
+        }
+    }
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".img-dedup-tmp")
+}
+
+// A second name of the duplicate, which the caller trashes once it is replaced so that the
+// replacement can be undone. Copied where hardlinks are not supported.
+pub fn backup(duplicate: &Path) -> io::Result<PathBuf> {
+    let backup = with_suffix(duplicate, ".img-dedup-replaced");
+    std::fs::hard_link(duplicate, &backup)
+        .or_else(|_| std::fs::copy(duplicate, &backup).map(|_| ()))?;
+    Ok(backup)
+}
+
+#[cfg(unix)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+fn symlink(target: &Path, link: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(target, link)
+}
+
+#[cfg(target_os = "linux")]
+fn clone_file(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    // `_IOW(0x94, 9, int)` from `linux/fs.h`.
+    const FICLONE: u32 = 0x4004_9409;
+    let source = std::fs::File::open(from)?;
+    let target = std::fs::File::create(to)?;
+    if unsafe { libc::ioctl(target.as_raw_fd(), FICLONE as _, source.as_raw_fd()) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn clone_file(from: &Path, to: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    extern "C" {
+        fn clonefile(src: *const libc::c_char, dst: *const libc::c_char, flags: u32)
+            -> libc::c_int;
+    }
+    let from = CString::new(from.as_os_str().as_bytes())?;
+    let to = CString::new(to.as_os_str().as_bytes())?;
+    if unsafe { clonefile(from.as_ptr(), to.as_ptr(), 0) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn clone_file(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "Cloning files is not supported on this platform",
+    ))
+}
+
+#[cfg(unix)]
+fn copy_ownership(metadata: &Metadata, path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    std::os::unix::fs::chown(path, Some(metadata.uid()), Some(metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn copy_ownership(_metadata: &Metadata, _path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+// Best effort: ownership usually requires elevated privileges, so failing to change it is only
+// logged.
+fn copy_metadata(metadata: &Metadata, path: &Path) -> io::Result<()> {
+    let mut times = FileTimes::new();
+    if let Ok(modified) = metadata.modified() {
+        times = times.set_modified(modified);
+    }
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+    std::fs::File::options()
+        .write(true)
+        .open(path)?
+        .set_times(times)?;
+    std::fs::set_permissions(path, metadata.permissions())?;
+    if let Err(err) = copy_ownership(metadata, path) {
+        debug!("Could not keep the owner of {}: {}", path.display(), err);
+    }
+    Ok(())
+}
+
+// The replacement is prepared next to the duplicate and renamed over it, so that the duplicate's
+// path is never missing. With `preserve_metadata`, the file ending up at the duplicate's path gets
+// the duplicate's timestamps, permissions and owner, so that backup tools do not see it as
+// changed. Hardlinks share the kept file's metadata and symlinks have none of their own worth
+// keeping, so only moves and clones are affected.
+pub fn replace(
+    duplicate: &Path,
+    kept: &Path,
+    mode: ReplaceMode,
+    preserve_metadata: bool,
+) -> io::Result<()> {
+    let metadata = std::fs::metadata(duplicate)?;
+    let tmp = tmp_path(duplicate);
+    let result = match mode {
+        ReplaceMode::Hardlink => std::fs::hard_link(kept, &tmp),
+        ReplaceMode::Symlink => symlink(&std::fs::canonicalize(kept)?, &tmp),
+        ReplaceMode::Move => move_file(kept, &tmp),
+        ReplaceMode::Clone => clone_file(kept, &tmp),
+    }
+    .and_then(|()| {
+        if preserve_metadata && matches!(mode, ReplaceMode::Move | ReplaceMode::Clone) {
+            copy_metadata(&metadata, &tmp)
+        } else {
+            Ok(())
+        }
+    })
+    .and_then(|()| std::fs::rename(&tmp, duplicate));
+
+    if let Err(err) = result {
+        // A moved file is put back where it was.
+        if mode == ReplaceMode::Move && tmp.exists() {
+            let _ = move_file(&tmp, kept);
+        }
+        let _ = std::fs::remove_file(&tmp);
+        return Err(err);
+    }
+    info!(
+        "Replaced {} ({:?} of {})",
+        duplicate.display(),
+        mode,
+        kept.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,57 @@
+// Rules picking the image to keep in each pair, the other one being marked for deletion, so that
+// thousands of pairs can be cleaned up without deciding on each one. Within a group of similar
+// images, those the rule cannot tell apart from the best one all stay unmarked, so that ties leave
+// more than one copy rather than none.
+use crate::sort::{image_key, SortKey};
+use crate::Image;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeepRule {
+    Largest,
+    // Modification time.
+    Newest,
+    Oldest,
+    HighestResolution,
+}
+
+impl KeepRule {
+    pub const ALL: [KeepRule; 4] = [
+        KeepRule::Largest,
+        KeepRule::Newest,
+        KeepRule::Oldest,
+        KeepRule::HighestResolution,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            KeepRule::Largest => "largest file",
+            KeepRule::Newest => "newest",
+            KeepRule::Oldest => "oldest",
+            KeepRule::HighestResolution => "highest resolution",
+        }
+    }
+
+    // Whether `a` is kept rather than `b`, `None` when the rule cannot tell them apart.
+    pub fn keeps_first(self, a: &Image, b: &Image) -> Option<bool> {
+        let sort_key = match self {
+            KeepRule::Largest => SortKey::Size,
+            KeepRule::Newest | KeepRule::Oldest => SortKey::Date,
+            KeepRule::HighestResolution => SortKey::Resolution,
+        };
+        let key = |image: &Image| image_key(sort_key, &image.path, image.dimensions);
+        let ordering = key(a).cmp(&key(b));
+        let ordering = if self == KeepRule::Oldest {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        match ordering {
+            Ordering::Greater => Some(true),
+            Ordering::Less => Some(false),
+            Ordering::Equal => None,
+        }
+    }
+}
@@ -0,0 +1,255 @@
+// S3 and compatible object storage, e.g. MinIO. Buckets are addressed in path style, which every
+// implementation supports, and requests are signed with AWS Signature Version 4. The credentials
+// come from the usual environment variables, so that they are never written to the settings:
+// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`, `AWS_SESSION_TOKEN` (optional), `AWS_REGION` and
+// `AWS_ENDPOINT_URL` for other providers than AWS.
+use crate::storage::{Backend, Object};
+use ring::{digest, hmac};
+use std::io::Read;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use xml::reader::{EventReader, XmlEvent};
+
+const DEFAULT_REGION: &str = "us-east-1";
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+pub struct Bucket {
+    // E.g. `https://s3.eu-west-3.amazonaws.com` or `http://localhost:9000`.
+    endpoint: String,
+    region: String,
+    name: String,
+    access_key: String,
+    secret_key: String,
+    session_token: Option<String>,
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn sha256(data: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+// Percent-encodes everything but the unreserved characters, and `/` in paths.
+fn encode(text: &str, path: bool) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            b'/' if path => "/".to_owned(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// `YYYYMMDDTHHMMSSZ`, in UTC.
+fn amz_date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let (year, month, day) = crate::exif::civil_from_days(seconds.div_euclid(86400));
+    let seconds = seconds.rem_euclid(86400);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+impl Bucket {
+    // `location` is the part of the URL after `s3://`: the bucket, then the key or prefix.
+    pub fn from_env(location: &str) -> Result<(Self, String), String> {
+        let (name, key) = location.split_once('/').unwrap_or((location, ""));
+        if name.is_empty() {
+            return Err("No bucket in the URL".to_owned());
+        }
+        let var = |name: &str| std::env::var(name).ok().filter(|value| !value.is_empty());
+        let region = var("AWS_REGION")
+            .or_else(|| var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|| DEFAULT_REGION.to_owned());
+        let endpoint = var("AWS_ENDPOINT_URL")
+            .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+        let bucket = Bucket {
+            endpoint: endpoint.trim_end_matches('/').to_owned(),
+            region,
+            name: name.to_owned(),
+            access_key: var("AWS_ACCESS_KEY_ID").ok_or("AWS_ACCESS_KEY_ID is not set")?,
+            secret_key: var("AWS_SECRET_ACCESS_KEY").ok_or("AWS_SECRET_ACCESS_KEY is not set")?,
+            session_token: var("AWS_SESSION_TOKEN"),
+        };
+        Ok((bucket, key.to_owned()))
+    }
+
+    // Signed with Signature Version 4, the payload is left unsigned as allowed by S3.
+    fn request(&self, method: &str, key: &str, query: &[(&str, &str)]) -> ureq::Request {
+        let host = self
+            .endpoint
+            .split_once("://")
+            .map_or(self.endpoint.as_str(), |(_, host)| host);
+        let path = encode(&format!("/{}/{}", self.name, key), true);
+        let mut query: Vec<(String, String)> = query
+            .iter()
+            .map(|(name, value)| (encode(name, false), encode(value, false)))
+            .collect();
+        query.sort();
+        let query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("&");
+
+        let date = amz_date(SystemTime::now());
+        let mut headers = vec![
+            ("host", host.to_owned()),
+            ("x-amz-content-sha256", "UNSIGNED-PAYLOAD".to_owned()),
+            ("x-amz-date", date.clone()),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token.clone()));
+        }
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\nUNSIGNED-PAYLOAD",
+            method, path, query, canonical_headers, signed_headers
+        );
+        let scope = format!("{}/{}/s3/aws4_request", &date[..8], self.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            date,
+            scope,
+            sha256(canonical_request.as_bytes())
+        );
+        let key = [&date[..8], &self.region, "s3", "aws4_request"]
+            .iter()
+            .fold(
+                format!("AWS4{}", self.secret_key).into_bytes(),
+                |key, part| hmac_sha256(&key, part),
+            );
+        let signature = hex(&hmac_sha256(&key, &string_to_sign));
+
+        let url = if query.is_empty() {
+            format!("{}{}", self.endpoint, path)
+        } else {
+            format!("{}{}?{}", self.endpoint, path, query)
+        };
+        let mut request = ureq::request(method, &url).timeout(TIMEOUT);
+        // Host is set by ureq from the URL.
+        for (name, value) in &headers[1..] {
+            request = request.set(name, value);
+        }
+        request.set(
+            "Authorization",
+            &format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                self.access_key, scope, signed_headers, signature
+            ),
+        )
+    }
+}
+
+fn describe(err: ureq::Error) -> String {
+    match err {
+        ureq::Error::Status(status, response) => {
+            // The XML error of S3 names the problem, e.g. `NoSuchBucket`.
+            let body = response.into_string().unwrap_or_default();
+            format!("HTTP {}: {}", status, body.trim())
+        }
+        err => err.to_string(),
+    }
+}
+
+// One page of ListObjectsV2: the objects and the token of the next page, if any.
+fn parse_listing(xml: &str) -> Result<(Vec<Object>, Option<String>), String> {
+    let mut objects = Vec::new();
+    let mut next = None;
+    let (mut key, mut size) = (None, None);
+    let mut element = String::new();
+    for event in EventReader::from_str(xml) {
+        match event.map_err(|err| err.to_string())? {
+            XmlEvent::StartElement { name, .. } => element = name.local_name,
+            XmlEvent::Characters(text) => match element.as_str() {
+                "Key" => key = Some(text),
+                "Size" => size = text.parse().ok(),
+                "NextContinuationToken" => next = Some(text),
+                _ => {}
+            },
+            XmlEvent::EndElement { name } => {
+                if name.local_name == "Contents" {
+                    if let (Some(key), Some(size)) = (key.take(), size.take()) {
+                        objects.push(Object { key, size });
+                    }
+                }
+                element.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok((objects, next))
+}
+
+impl Backend for Bucket {
+    fn list(&self, prefix: &str) -> Result<Vec<Object>, String> {
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2"), ("prefix", prefix)];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.as_str()));
+            }
+            let xml = self
+                .request("GET", "", &query)
+                .call()
+                .map_err(describe)?
+                .into_string()
+                .map_err(|err| err.to_string())?;
+            let (page, next) = parse_listing(&xml)?;
+            objects.extend(page);
+            match next {
+                Some(next) => token = Some(next),
+                None => return Ok(objects),
+            }
+        }
+    }
+
+    fn read(&self, key: &str) -> Result<Vec<u8>, String> {
+        let mut buffer = Vec::new();
+        self.request("GET", key, &[])
+            .call()
+            .map_err(describe)?
+            .into_reader()
+            .read_to_end(&mut buffer)
+            .map_err(|err| err.to_string())?;
+        Ok(buffer)
+    }
+
+    fn delete(&self, key: &str) -> Result<(), String> {
+        self.request("DELETE", key, &[])
+            .call()
+            .map(|_| ())
+            .map_err(describe)
+    }
+
+    fn url(&self, key: &str) -> String {
+        format!("s3://{}/{}", self.name, key)
+    }
+}
@@ -0,0 +1,25 @@
+// Fuzzy search over paths: every word of the query has to match a single component of the path,
+// with its letters in order but not necessarily adjacent and ignoring case, e.g. `vac 23 beach`
+// matches `Vacation 2023/beach_01.jpg`. Matching within a component keeps short queries from
+// matching every long path.
+use std::path::Path;
+
+fn is_subsequence(needle: &str, haystack: &str) -> bool {
+    let mut haystack = haystack.chars();
+    needle.chars().all(|c| haystack.any(|h| h == c))
+}
+
+pub fn matches(query: &str, path: &str) -> bool {
+    let components: Vec<String> = Path::new(path)
+        .iter()
+        .map(|component| component.to_string_lossy().to_lowercase())
+        .collect();
+    let mut words = query.split_whitespace().peekable();
+    words.peek().is_some()
+        && words.all(|word| {
+            let word = word.to_lowercase();
+            components
+                .iter()
+                .any(|component| is_subsequence(&word, component))
+        })
+}
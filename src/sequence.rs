@@ -0,0 +1,51 @@
+// Numbered frames of VFX renders, e.g. `plate.1001.exr`, `plate.1002.exr`... Hashing thousands of
+// nearly identical frames is slow and buries the results under pairs of consecutive frames, so
+// only every Nth frame is hashed and each pair of sequences is reported once.
+use std::collections::HashMap;
+use std::path::Path;
+
+// Numbered JPEGs and PNGs are usually photos, not frames.
+const EXTENSIONS: [&str; 1] = ["exr"];
+
+// Fewer digits are more likely a version or a take than a frame number.
+const MIN_DIGITS: usize = 3;
+
+// The path with the frame number replaced by `#`s, e.g. `shot/plate.####.exr`, `None` for files
+// that are not frames.
+pub fn pattern(path: &str) -> Option<String> {
+    let path = Path::new(path);
+    let extension = path.extension()?.to_str()?;
+    if !EXTENSIONS.iter().any(|x| x.eq_ignore_ascii_case(extension)) {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    let prefix = stem.trim_end_matches(|c: char| c.is_ascii_digit());
+    let digits = stem.len() - prefix.len();
+    if digits < MIN_DIGITS || !(prefix.is_empty() || prefix.ends_with(['.', '_', '-'])) {
+        return None;
+    }
+    let name = format!("{}{}.{}", prefix, "#".repeat(digits), extension);
+    Some(path.with_file_name(name).to_string_lossy().to_string())
+}
+
+// Picks every `step`th frame of each sequence met during a directory walk, starting with the first
+// one. Files which are not frames are always kept.
+#[derive(Default)]
+pub struct Sampler {
+    seen: HashMap<String, u32>,
+}
+
+impl Sampler {
+    pub fn keep(&mut self, path: &Path, step: u32) -> bool {
+        if step <= 1 {
+            return true;
+        }
+        let Some(pattern) = path.to_str().and_then(pattern) else {
+            return true;
+        };
+        let seen = self.seen.entry(pattern).or_default();
+        let keep = seen.is_multiple_of(step);
+        *seen += 1;
+        keep
+    }
+}
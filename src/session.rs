@@ -0,0 +1,143 @@
+use crate::paths;
+use crate::settings::ScanOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+const AUTOSAVE_FILE_NAME: &str = "autosave.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionImage {
+    pub path: String,
+    // Base64, as produced by `ImageHash::to_base64`.
+    pub hash: String,
+    pub dimensions: (u32, u32),
+    // Animation frames or icon sizes, 0 when unknown.
+    #[serde(default)]
+    pub frames: u32,
+}
+
+// Everything needed to bring the results view back without re-hashing. Textures are not saved,
+// they are reloaded from the files when displayed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    pub root: String,
+    // The other directories scanned along with `root`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extra_roots: Vec<String>,
+    pub options: ScanOptions,
+    // Same indices as `MyApp::images`, `None` for images trashed during the review.
+    pub images: Vec<Option<SessionImage>>,
+    pub similar_images: Vec<(usize, usize)>,
+    pub errors: Vec<(String, String)>,
+    // By path, the review is not lost when the session is resumed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub marked: Vec<String>,
+    // Pairs the user kept both of, never listed again when matching.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dismissed_pairs: Vec<(String, String)>,
+    pub analyzed_bytes: u64,
+    // When false, the directory walk or the hashing was interrupted.
+    pub complete: bool,
+}
+
+impl Session {
+    pub fn autosave_path() -> Option<PathBuf> {
+        paths::data_dir().map(|dir| dir.join(AUTOSAVE_FILE_NAME))
+    }
+
+    // Written to a temporary file first and then renamed, so that a crash while writing never
+    // leaves a truncated session behind.
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        let content = serde_json::to_vec(self).map_err(|err| err.to_string())?;
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, content).map_err(|err| err.to_string())?;
+        std::fs::rename(&tmp_path, path).map_err(|err| err.to_string())
+    }
+
+    pub fn read(path: &Path) -> Result<Session, String> {
+        let content = std::fs::read(path).map_err(|err| err.to_string())?;
+        serde_json::from_slice(&content).map_err(|err| err.to_string())
+    }
+}
+
+// What changed between two sessions of the same tree. Pairs are by path, sorted, so that they match
+// whatever the order of the images in each session.
+#[derive(Debug, Default)]
+pub struct SessionDiff {
+    pub added_files: Vec<String>,
+    pub removed_files: Vec<String>,
+    pub new_duplicates: Vec<(String, String)>,
+    pub resolved_duplicates: Vec<(String, String)>,
+}
+
+impl Session {
+    // Images trashed during the review count as removed.
+    fn files(&self) -> BTreeSet<&str> {
+        self.images
+            .iter()
+            .flatten()
+            .map(|image| image.path.as_str())
+            .collect()
+    }
+
+    fn duplicates(&self) -> BTreeSet<(&str, &str)> {
+        self.similar_images
+            .iter()
+            .filter_map(|&(i, j)| {
+                let a = self.images.get(i)?.as_ref()?.path.as_str();
+                let b = self.images.get(j)?.as_ref()?.path.as_str();
+                Some((a.min(b), a.max(b)))
+            })
+            .collect()
+    }
+}
+
+pub fn diff(old: &Session, new: &Session) -> SessionDiff {
+    let (old_files, new_files) = (old.files(), new.files());
+    let (old_duplicates, new_duplicates) = (old.duplicates(), new.duplicates());
+    let owned = |(a, b): &(&str, &str)| (a.to_string(), b.to_string());
+    SessionDiff {
+        added_files: new_files
+            .difference(&old_files)
+            .map(|path| path.to_string())
+            .collect(),
+        removed_files: old_files
+            .difference(&new_files)
+            .map(|path| path.to_string())
+            .collect(),
+        new_duplicates: new_duplicates
+            .difference(&old_duplicates)
+            .map(owned)
+            .collect(),
+        resolved_duplicates: old_duplicates
+            .difference(&new_duplicates)
+            .map(owned)
+            .collect(),
+    }
+}
+
+impl std::fmt::Display for SessionDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Added files: {}", self.added_files.len())?;
+        for path in &self.added_files {
+            writeln!(f, "  + {}", path)?;
+        }
+        writeln!(f, "Removed files: {}", self.removed_files.len())?;
+        for path in &self.removed_files {
+            writeln!(f, "  - {}", path)?;
+        }
+        writeln!(f, "New duplicates: {}", self.new_duplicates.len())?;
+        for (a, b) in &self.new_duplicates {
+            writeln!(f, "  + {} ↔ {}", a, b)?;
+        }
+        writeln!(f, "Resolved duplicates: {}", self.resolved_duplicates.len())?;
+        for (a, b) in &self.resolved_duplicates {
+            writeln!(f, "  - {} ↔ {}", a, b)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,503 @@
+use crate::cache::HashCacheMode;
+use crate::identity::VerifiedAction;
+use crate::paths;
+use crate::rules::KeepRule;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::str::FromStr;
+use walkdir::WalkDir;
+
+const SETTINGS_FILE_NAME: &str = "settings.toml";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    // Shell commands, run with the file path, group id and hash as positional arguments and
+    // as `IMG_DEDUP_*` environment variables.
+    pub on_keep: String,
+    pub on_trash: String,
+    pub on_replace: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    Mean,
+    Gradient,
+    DoubleGradient,
+    VertGradient,
+    Blockhash,
+    // Compatible with libpHash's DCT hash, see `phash.rs`.
+    PHash,
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 6] = [
+        HashAlgorithm::Mean,
+        HashAlgorithm::Gradient,
+        HashAlgorithm::DoubleGradient,
+        HashAlgorithm::VertGradient,
+        HashAlgorithm::Blockhash,
+        HashAlgorithm::PHash,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Mean => "mean",
+            HashAlgorithm::Gradient => "gradient",
+            HashAlgorithm::DoubleGradient => "double_gradient",
+            HashAlgorithm::VertGradient => "vert_gradient",
+            HashAlgorithm::Blockhash => "blockhash",
+            HashAlgorithm::PHash => "phash",
+        }
+    }
+
+    // `None` for the algorithms not provided by `img_hash`.
+    pub fn to_img_hash(self) -> Option<img_hash::HashAlg> {
+        match self {
+            HashAlgorithm::Mean => Some(img_hash::HashAlg::Mean),
+            HashAlgorithm::Gradient => Some(img_hash::HashAlg::Gradient),
+            HashAlgorithm::DoubleGradient => Some(img_hash::HashAlg::DoubleGradient),
+            HashAlgorithm::VertGradient => Some(img_hash::HashAlg::VertGradient),
+            HashAlgorithm::Blockhash => Some(img_hash::HashAlg::Blockhash),
+            HashAlgorithm::PHash => None,
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HashAlgorithm::ALL
+            .into_iter()
+            .find(|alg| alg.name() == s)
+            .ok_or(())
+    }
+}
+
+// A similarity threshold for the images under a folder, e.g. stricter for `originals/` than for
+// `Downloads/`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FolderThreshold {
+    // Relative to the scanned directory, or absolute.
+    pub prefix: String,
+    pub threshold: u32,
+}
+
+// Everything that influences how a scan runs, bundled so that it can be saved as a profile.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScanOptions {
+    pub similarity_threshold: u32,
+    pub hash_alg: HashAlgorithm,
+    // Size of the hashing thread pool, 0 means one thread per CPU.
+    pub threads: usize,
+    // Files smaller than this (in bytes) are not hashed.
+    pub min_file_size: u64,
+    // Nor files larger than this (in bytes), 0 for no limit.
+    pub max_file_size: u64,
+    // Images with a side shorter than this (in pixels) are not hashed, checked from the header.
+    pub min_dimension: u32,
+    // Images with an embedded ICC profile are converted to sRGB before hashing, so that wide gamut
+    // and sRGB exports of the same photo match.
+    pub color_management: bool,
+    // Only every Nth frame of numbered EXR sequences is hashed, and each pair of similar sequences
+    // is reported once. 0 treats frames like any other image.
+    pub sequence_step: u32,
+    // For dumps of phone and camera cards: copies in DCIM folders are suggested as keepers and
+    // `IMG_E1234` edits are told apart from duplicates, see `dcim.rs`.
+    pub dcim_layout: bool,
+    // Looser threshold for pairs where one image is a smaller copy sent through a messaging app,
+    // see `messaging.rs`. Only used when above `similarity_threshold`.
+    pub messaging_threshold: u32,
+    // Replace `similarity_threshold` for the images under these folders, the longest matching
+    // prefix wins. A pair gets the stricter threshold of its two images.
+    pub folder_thresholds: Vec<FolderThreshold>,
+    // Pairs whose aspect ratios differ by more than this percentage are left out, they are
+    // usually false positives at loose thresholds. 0 keeps them all.
+    pub aspect_ratio_tolerance: u32,
+    // The images under these folders are hashed first, relative to the scanned directory or
+    // absolute, see `queue.rs`.
+    pub priority_folders: Vec<String>,
+    // Globs of the files and directories left out, see `exclude.rs`.
+    pub exclude: Vec<String>,
+    // How deep directories are walked, 1 for only the files of the scanned directory. 0 walks
+    // them all.
+    pub max_depth: usize,
+    // Symbolic links are walked into, a file reached from several links is hashed once.
+    pub follow_links: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        ScanOptions {
+            similarity_threshold: 40,
+            hash_alg: HashAlgorithm::DoubleGradient,
+            threads: 0,
+            min_file_size: 10 * 1024, // 10 KiB
+            max_file_size: 0,
+            min_dimension: 32,
+            color_management: true,
+            sequence_step: 10,
+            dcim_layout: false,
+            messaging_threshold: 0,
+            folder_thresholds: Vec::new(),
+            aspect_ratio_tolerance: 0,
+            priority_folders: Vec::new(),
+            exclude: Vec::new(),
+            max_depth: 0,
+            follow_links: false,
+        }
+    }
+}
+
+impl ScanOptions {
+    // The threshold of an image found while scanning `root`.
+    pub fn threshold_for(&self, root: &str, path: &str) -> u32 {
+        let path = Path::new(path);
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        self.folder_thresholds
+            .iter()
+            .filter(|folder| !folder.prefix.is_empty())
+            .filter(|folder| {
+                relative.starts_with(&folder.prefix) || path.starts_with(&folder.prefix)
+            })
+            .max_by_key(|folder| Path::new(&folder.prefix).components().count())
+            .map_or(self.similarity_threshold, |folder| folder.threshold)
+    }
+
+    pub fn is_prioritized(&self, root: &str, path: &str) -> bool {
+        let path = Path::new(path);
+        let relative = path.strip_prefix(root).unwrap_or(path);
+        self.priority_folders
+            .iter()
+            .filter(|folder| !folder.is_empty())
+            .any(|folder| relative.starts_with(folder) || path.starts_with(folder))
+    }
+
+    pub fn walk(&self, root: &Path) -> WalkDir {
+        let walk = WalkDir::new(root).follow_links(self.follow_links);
+        match self.max_depth {
+            0 => walk,
+            depth => walk.max_depth(depth),
+        }
+    }
+
+    pub fn size_in_range(&self, size: u64) -> bool {
+        size >= self.min_file_size && (self.max_file_size == 0 || size <= self.max_file_size)
+    }
+
+    // Everything deciding which hashes make a pair, to match them again when it changes.
+    pub fn matching(&self) -> (u32, u32, Vec<FolderThreshold>, u32) {
+        (
+            self.similarity_threshold,
+            self.messaging_threshold,
+            self.folder_thresholds.clone(),
+            self.aspect_ratio_tolerance,
+        )
+    }
+
+    // Any pair within this threshold may be similar for one of the thresholds.
+    pub fn max_threshold(&self) -> u32 {
+        self.folder_thresholds
+            .iter()
+            .map(|folder| folder.threshold)
+            .fold(
+                self.similarity_threshold.max(self.messaging_threshold),
+                u32::max,
+            )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(flatten)]
+    pub scan: ScanOptions,
+}
+
+fn default_profiles() -> Vec<Profile> {
+    vec![
+        Profile {
+            name: "Strict (exact-ish)".to_owned(),
+            scan: ScanOptions {
+                similarity_threshold: 10,
+                ..Default::default()
+            },
+        },
+        Profile {
+            name: "Loose (find everything)".to_owned(),
+            scan: ScanOptions {
+                similarity_threshold: 60,
+                hash_alg: HashAlgorithm::Gradient,
+                min_file_size: 0,
+                ..Default::default()
+            },
+        },
+        Profile {
+            // Few threads so that the NAS is not flooded with concurrent reads.
+            name: "NAS friendly".to_owned(),
+            scan: ScanOptions {
+                threads: 2,
+                min_file_size: 100 * 1024,
+                ..Default::default()
+            },
+        },
+        Profile {
+            name: "Phone/camera dump (DCIM)".to_owned(),
+            scan: ScanOptions {
+                dcim_layout: true,
+                ..Default::default()
+            },
+        },
+        Profile {
+            name: "Messaging app copies".to_owned(),
+            scan: ScanOptions {
+                messaging_threshold: 60,
+                ..Default::default()
+            },
+        },
+    ]
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    #[serde(flatten)]
+    pub scan: ScanOptions,
+    pub profiles: Vec<Profile>,
+    pub hooks: Hooks,
+    // Opt-in: nothing is sent over the network unless the user enables it.
+    pub check_for_updates: bool,
+    pub hash_cache: HashCacheMode,
+    // Files replacing a duplicate get its timestamps, permissions and owner.
+    pub preserve_metadata: bool,
+    // In bytes, the least recently used thumbnails are removed beyond that.
+    pub thumbnail_cache_size: u64,
+    // Companion files, e.g. the video of a Live Photo or XMP sidecars, are trashed or moved along
+    // with their image.
+    pub trash_companions: bool,
+    // Files referenced by this Lightroom catalog are only trashed after confirmation, empty for
+    // none.
+    pub lightroom_catalog: String,
+    // Applied without review to the pairs found identical byte for byte.
+    pub verified_action: VerifiedAction,
+    // Pairs with an image whose shorter side is below this (in pixels) are hidden, e.g. thumbnails
+    // next to their original. 0 shows them all.
+    pub min_pair_dimension: u32,
+    // Only hide the pairs where both images are below `min_pair_dimension`.
+    pub min_pair_dimension_both: bool,
+    // Pairs whose confidence score (percent) is below this are hidden. 0 shows them all.
+    pub min_confidence: u8,
+    // Mount points, the copy on the volume listed first is suggested as the keeper, see
+    // `volumes.rs`.
+    pub keep_volumes: Vec<String>,
+    // Trashing the last copy of an image on this volume asks for confirmation, empty for none.
+    pub archive_volume: String,
+    // Base URL of a hash cache shared with other machines, see `netcache.rs`. Empty for none.
+    pub shared_cache_url: String,
+    // Duplicates are moved to this folder instead of the trash, see `quarantine.rs`. Empty for the
+    // trash.
+    pub quarantine_dir: String,
+    // Duplicates are deleted for good rather than trashed or moved, after confirmation.
+    pub delete_permanently: bool,
+    // Which image of each pair "Mark the others" keeps.
+    pub keep_rule: KeepRule,
+    // Seconds between two shots of the same camera for them to be paired by capture time.
+    pub capture_window: u32,
+    // Transitively similar images are listed as one group rather than as all their pairs.
+    pub group_pairs: bool,
+    // As read from the settings file, and once the environment and command line overrides are
+    // applied, so that `save` writes the overrides back as they were in the file.
+    #[serde(skip)]
+    from_file: Option<toml::Value>,
+    #[serde(skip)]
+    overridden: Option<toml::Value>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            scan: ScanOptions::default(),
+            profiles: default_profiles(),
+            hooks: Hooks::default(),
+            check_for_updates: false,
+            hash_cache: HashCacheMode::Central,
+            preserve_metadata: true,
+            thumbnail_cache_size: 512 * 1024 * 1024, // 512 MiB
+            trash_companions: false,
+            lightroom_catalog: String::new(),
+            verified_action: VerifiedAction::Review,
+            min_pair_dimension: 0,
+            min_pair_dimension_both: false,
+            min_confidence: 0,
+            keep_volumes: Vec::new(),
+            archive_volume: String::new(),
+            shared_cache_url: String::new(),
+            quarantine_dir: String::new(),
+            delete_permanently: false,
+            keep_rule: KeepRule::Largest,
+            capture_window: 2,
+            group_pairs: true,
+            from_file: None,
+            overridden: None,
+        }
+    }
+}
+
+fn env_override<T: FromStr>(name: &str, value: &mut T) {
+    let Ok(raw) = std::env::var(name) else {
+        return;
+    };
+    match raw.parse() {
+        Ok(parsed) => {
+            info!("Using {}={}", name, raw);
+            *value = parsed;
+        }
+        Err(_) => warn!("Ignoring invalid value for {}: {}", name, raw),
+    }
+}
+
+// Values still as overridden are written as they were in the file, those changed since are saved.
+fn keep_overrides_out(value: &mut toml::Value, from_file: &toml::Value, overridden: &toml::Value) {
+    let (Some(table), Some(from_file), Some(overridden)) = (
+        value.as_table_mut(),
+        from_file.as_table(),
+        overridden.as_table(),
+    ) else {
+        return;
+    };
+    for (key, value) in table.iter_mut() {
+        let (Some(in_file), Some(override_value)) = (from_file.get(key), overridden.get(key))
+        else {
+            continue;
+        };
+        if value.is_table() {
+            keep_overrides_out(value, in_file, override_value);
+        } else if value == override_value && override_value != in_file {
+            *value = in_file.clone();
+        }
+    }
+}
+
+impl Settings {
+    // Precedence, from lowest to highest: defaults, settings file, `IMG_DEDUP_*` environment
+    // variables. Command line arguments are applied on top by the caller, which then calls
+    // `overrides_applied`.
+    pub fn load() -> Self {
+        let mut settings = Self::load_file();
+        settings.from_file = toml::Value::try_from(&settings).ok();
+        settings.apply_env();
+        settings
+    }
+
+    pub fn overrides_applied(&mut self) {
+        self.overridden = toml::Value::try_from(&*self).ok();
+    }
+
+    fn apply_env(&mut self) {
+        env_override("IMG_DEDUP_THRESHOLD", &mut self.scan.similarity_threshold);
+        env_override("IMG_DEDUP_HASH_ALG", &mut self.scan.hash_alg);
+        env_override("IMG_DEDUP_THREADS", &mut self.scan.threads);
+        env_override("IMG_DEDUP_MIN_FILE_SIZE", &mut self.scan.min_file_size);
+        env_override("IMG_DEDUP_MAX_FILE_SIZE", &mut self.scan.max_file_size);
+        env_override("IMG_DEDUP_MAX_DEPTH", &mut self.scan.max_depth);
+        env_override("IMG_DEDUP_FOLLOW_LINKS", &mut self.scan.follow_links);
+        env_override("IMG_DEDUP_MIN_DIMENSION", &mut self.scan.min_dimension);
+        env_override(
+            "IMG_DEDUP_COLOR_MANAGEMENT",
+            &mut self.scan.color_management,
+        );
+        env_override("IMG_DEDUP_SEQUENCE_STEP", &mut self.scan.sequence_step);
+        env_override("IMG_DEDUP_DCIM_LAYOUT", &mut self.scan.dcim_layout);
+        env_override(
+            "IMG_DEDUP_ASPECT_RATIO_TOLERANCE",
+            &mut self.scan.aspect_ratio_tolerance,
+        );
+        env_override(
+            "IMG_DEDUP_MESSAGING_THRESHOLD",
+            &mut self.scan.messaging_threshold,
+        );
+        env_override("IMG_DEDUP_HOOK_ON_KEEP", &mut self.hooks.on_keep);
+        env_override("IMG_DEDUP_HOOK_ON_TRASH", &mut self.hooks.on_trash);
+        env_override("IMG_DEDUP_HOOK_ON_REPLACE", &mut self.hooks.on_replace);
+        env_override("IMG_DEDUP_CHECK_FOR_UPDATES", &mut self.check_for_updates);
+        env_override("IMG_DEDUP_HASH_CACHE", &mut self.hash_cache);
+        env_override("IMG_DEDUP_PRESERVE_METADATA", &mut self.preserve_metadata);
+        env_override(
+            "IMG_DEDUP_THUMBNAIL_CACHE_SIZE",
+            &mut self.thumbnail_cache_size,
+        );
+        env_override("IMG_DEDUP_TRASH_COMPANIONS", &mut self.trash_companions);
+        env_override("IMG_DEDUP_LIGHTROOM_CATALOG", &mut self.lightroom_catalog);
+        env_override("IMG_DEDUP_VERIFIED_ACTION", &mut self.verified_action);
+        env_override("IMG_DEDUP_MIN_PAIR_DIMENSION", &mut self.min_pair_dimension);
+        env_override(
+            "IMG_DEDUP_MIN_PAIR_DIMENSION_BOTH",
+            &mut self.min_pair_dimension_both,
+        );
+        env_override("IMG_DEDUP_MIN_CONFIDENCE", &mut self.min_confidence);
+        env_override("IMG_DEDUP_ARCHIVE_VOLUME", &mut self.archive_volume);
+        env_override("IMG_DEDUP_SHARED_CACHE_URL", &mut self.shared_cache_url);
+        env_override("IMG_DEDUP_QUARANTINE_DIR", &mut self.quarantine_dir);
+        // Separated like PATH, `:` or `;` on Windows. The cache directory is read by `paths`.
+        if let Some(raw) = std::env::var_os("IMG_DEDUP_EXCLUDE") {
+            info!("Using IMG_DEDUP_EXCLUDE={}", raw.to_string_lossy());
+            self.scan.exclude = std::env::split_paths(&raw)
+                .filter(|glob| !glob.as_os_str().is_empty())
+                .map(|glob| glob.to_string_lossy().to_string())
+                .collect();
+        }
+        self.scan.similarity_threshold = self.scan.similarity_threshold.min(100);
+        self.scan.messaging_threshold = self.scan.messaging_threshold.min(100);
+        self.min_confidence = self.min_confidence.min(100);
+    }
+
+    fn load_file() -> Self {
+        let path = match paths::config_dir() {
+            Some(dir) => dir.join(SETTINGS_FILE_NAME),
+            None => return Settings::default(),
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(content) => match toml::from_str(&content) {
+                Ok(settings) => {
+                    info!("Loaded settings from {}", path.display());
+                    settings
+                }
+                Err(err) => {
+                    error!("Failed to parse settings {}: {}", path.display(), err);
+                    Settings::default()
+                }
+            },
+            Err(_) => Settings::default(),
+        }
+    }
+
+    // Profiles are matched by name, so saving under an existing name replaces it.
+    pub fn save_profile(&mut self, name: &str) {
+        let profile = Profile {
+            name: name.to_owned(),
+            scan: self.scan.clone(),
+        };
+        match self.profiles.iter_mut().find(|p| p.name == name) {
+            Some(existing) => *existing = profile,
+            None => self.profiles.push(profile),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let dir = paths::config_dir().ok_or("No configuration directory on this platform")?;
+        std::fs::create_dir_all(&dir).map_err(|err| err.to_string())?;
+        // Through a `Value`, which writes the plain values before the tables as TOML requires,
+        // whatever the order of the fields.
+        let mut value = toml::Value::try_from(self).map_err(|err| err.to_string())?;
+        if let (Some(from_file), Some(overridden)) = (&self.from_file, &self.overridden) {
+            keep_overrides_out(&mut value, from_file, overridden);
+        }
+        let content = toml::to_string_pretty(&value).map_err(|err| err.to_string())?;
+        std::fs::write(dir.join(SETTINGS_FILE_NAME), content).map_err(|err| err.to_string())
+    }
+}
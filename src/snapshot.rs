@@ -0,0 +1,128 @@
+// A pair composited into a single PNG: both thumbnails side by side with their paths and metadata,
+// to document why a file was removed or to ask someone else's opinion. The text is drawn with the
+// font egui ships with.
+use ab_glyph::{Font, FontVec, GlyphId, PxScale, ScaleFont};
+use eframe::egui;
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+
+// Longest side of each thumbnail.
+const THUMBNAIL_SIZE: u32 = 512;
+const MARGIN: u32 = 16;
+const TEXT_SIZE: f32 = 16.0;
+const LINE_HEIGHT: u32 = 22;
+const BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+const TEXT: [u8; 3] = [230, 230, 230];
+const FONT_NAME: &str = "Ubuntu-Light";
+
+pub struct Side {
+    pub image: DynamicImage,
+    // Path first, then the metadata.
+    pub lines: Vec<String>,
+}
+
+fn text_width(font: &FontVec, text: &str) -> f32 {
+    let font = font.as_scaled(PxScale::from(TEXT_SIZE));
+    let mut width = 0.0;
+    let mut previous: Option<GlyphId> = None;
+    for c in text.chars() {
+        let id = font.glyph_id(c);
+        if let Some(previous) = previous {
+            width += font.kern(previous, id);
+        }
+        width += font.h_advance(id);
+        previous = Some(id);
+    }
+    width
+}
+
+// Long paths lose their beginning, the file name matters most.
+fn elide(font: &FontVec, text: &str, max_width: f32) -> String {
+    if text_width(font, text) <= max_width {
+        return text.to_owned();
+    }
+    let mut chars: Vec<char> = text.chars().collect();
+    while !chars.is_empty() {
+        chars.remove(0);
+        let elided = format!("…{}", chars.iter().collect::<String>());
+        if text_width(font, &elided) <= max_width {
+            return elided;
+        }
+    }
+    String::new()
+}
+
+fn draw_text(canvas: &mut RgbaImage, font: &FontVec, x: f32, y: f32, text: &str) {
+    let scale = PxScale::from(TEXT_SIZE);
+    let scaled = font.as_scaled(scale);
+    let mut caret = x;
+    let mut previous: Option<GlyphId> = None;
+    for c in text.chars() {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        let glyph = id.with_scale_and_position(scale, ab_glyph::point(caret, y + scaled.ascent()));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+        let Some(outlined) = font.outline_glyph(glyph) else {
+            continue;
+        };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|gx, gy, coverage| {
+            let (px, py) = (
+                bounds.min.x as i64 + gx as i64,
+                bounds.min.y as i64 + gy as i64,
+            );
+            if px < 0 || py < 0 || px >= canvas.width() as i64 || py >= canvas.height() as i64 {
+                return;
+            }
+            let pixel = canvas.get_pixel_mut(px as u32, py as u32);
+            for (channel, text) in pixel.0.iter_mut().zip(TEXT) {
+                *channel = (*channel as f32 * (1.0 - coverage) + text as f32 * coverage) as u8;
+            }
+        });
+    }
+}
+
+// `header` describes the pair, e.g. the distance, above both sides.
+pub fn compose(header: &[String], sides: [Side; 2]) -> Result<RgbaImage, String> {
+    let fonts = egui::FontDefinitions::default();
+    let data = fonts
+        .font_data
+        .get(FONT_NAME)
+        .ok_or_else(|| format!("No {} font", FONT_NAME))?;
+    let font = FontVec::try_from_vec(data.font.to_vec()).map_err(|err| err.to_string())?;
+
+    let sides = sides.map(|side| Side {
+        image: side.image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE),
+        lines: side.lines,
+    });
+    let column = THUMBNAIL_SIZE + MARGIN;
+    let header_height = header.len() as u32 * LINE_HEIGHT + MARGIN;
+    let image_height = sides
+        .iter()
+        .map(|side| side.image.height())
+        .max()
+        .unwrap_or(0);
+    let lines = sides.iter().map(|side| side.lines.len()).max().unwrap_or(0) as u32;
+    let width = MARGIN + 2 * column;
+    let height = MARGIN + header_height + image_height + MARGIN + lines * LINE_HEIGHT + MARGIN;
+    let mut canvas = RgbaImage::from_pixel(width, height, BACKGROUND);
+
+    for (row, line) in header.iter().enumerate() {
+        let line = elide(&font, line, (width - 2 * MARGIN) as f32);
+        let y = MARGIN + row as u32 * LINE_HEIGHT;
+        draw_text(&mut canvas, &font, MARGIN as f32, y as f32, &line);
+    }
+    for (i, side) in sides.iter().enumerate() {
+        let x = MARGIN + i as u32 * column;
+        let y = MARGIN + header_height;
+        image::imageops::overlay(&mut canvas, &side.image.to_rgba8(), x, y);
+        for (row, line) in side.lines.iter().enumerate() {
+            let line = elide(&font, line, THUMBNAIL_SIZE as f32);
+            let y = y + image_height + MARGIN + row as u32 * LINE_HEIGHT;
+            draw_text(&mut canvas, &font, x as f32, y as f32, &line);
+        }
+    }
+    Ok(canvas)
+}
@@ -0,0 +1,84 @@
+// Orders of the results list, to suit different cleanup strategies, e.g. biggest files first to
+// free space quickly. Pairs are ordered by the image listed first: the larger one when descending,
+// the smaller one otherwise.
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    // Order in which the pairs were found.
+    Found,
+    Distance,
+    Size,
+    // Modification time.
+    Date,
+    Name,
+    Resolution,
+    // Combined confidence score of the pair.
+    Confidence,
+}
+
+impl SortKey {
+    pub const ALL: [SortKey; 7] = [
+        SortKey::Found,
+        SortKey::Distance,
+        SortKey::Size,
+        SortKey::Date,
+        SortKey::Name,
+        SortKey::Resolution,
+        SortKey::Confidence,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SortKey::Found => "found",
+            SortKey::Distance => "distance",
+            SortKey::Size => "size",
+            SortKey::Date => "date",
+            SortKey::Name => "name",
+            SortKey::Resolution => "resolution",
+            SortKey::Confidence => "confidence",
+        }
+    }
+
+    // Whether the images of a pair are ordered too, the other keys are about the pair.
+    pub fn orders_images(self) -> bool {
+        !matches!(
+            self,
+            SortKey::Found | SortKey::Distance | SortKey::Confidence
+        )
+    }
+}
+
+// What an image is sorted by, only one variant is used at a time.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ImageKey {
+    Size(u64),
+    Date(SystemTime),
+    Name(String),
+    Resolution(u64),
+}
+
+pub fn image_key(key: SortKey, path: &str, dimensions: (u32, u32)) -> Option<ImageKey> {
+    let metadata = || std::fs::metadata(path).ok();
+    match key {
+        SortKey::Found | SortKey::Distance | SortKey::Confidence => None,
+        SortKey::Size => Some(ImageKey::Size(
+            metadata().map_or(0, |metadata| metadata.len()),
+        )),
+        SortKey::Date => Some(ImageKey::Date(
+            metadata()
+                .and_then(|metadata| metadata.modified().ok())
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+        )),
+        SortKey::Name => Some(ImageKey::Name(
+            std::path::Path::new(path)
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_lowercase(),
+        )),
+        SortKey::Resolution => Some(ImageKey::Resolution(
+            dimensions.0 as u64 * dimensions.1 as u64,
+        )),
+    }
+}
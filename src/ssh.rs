@@ -0,0 +1,114 @@
+// Directories of remote servers, e.g. `ssh://me@nas:2222/volume1/photos`, without mounting them.
+// This is not SFTP: the `ssh` command runs shell commands on the server, which brings the agent,
+// the keys and the host aliases of `~/.ssh/config` along. It runs in batch mode, passwords cannot
+// be typed in, and connections are shared between the commands where OpenSSH supports it. The
+// server needs a POSIX shell, `cat`, `rm` and GNU `find`, found on Linux servers and most NAS, but
+// not on servers only allowing SFTP.
+use crate::storage::{Backend, Object};
+use log::warn;
+use std::process::Command;
+
+pub struct Server {
+    // `user@host`, or a host alias.
+    destination: String,
+    port: Option<u16>,
+}
+
+// For the remote shell.
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+impl Server {
+    // `location` is the part of the URL after `ssh://`: the destination, then the absolute path.
+    pub fn from_url(location: &str) -> Result<(Self, String), String> {
+        let (authority, path) = location
+            .find('/')
+            .map_or((location, "/"), |slash| location.split_at(slash));
+        let (destination, port) = match authority.rsplit_once(':') {
+            Some((destination, port)) => (
+                destination,
+                Some(port.parse().map_err(|_| format!("Invalid port {}", port))?),
+            ),
+            None => (authority, None),
+        };
+        if destination.is_empty() {
+            return Err("No host in the URL".to_owned());
+        }
+        let server = Server {
+            destination: destination.to_owned(),
+            port,
+        };
+        Ok((server, path.to_owned()))
+    }
+
+    // Runs `remote` on the server and returns its output.
+    fn run(&self, remote: &str) -> Result<Vec<u8>, String> {
+        let mut command = Command::new("ssh");
+        command.args(["-o", "BatchMode=yes"]);
+        #[cfg(unix)]
+        {
+            let socket = std::env::temp_dir().join("img-dedup-ssh-%C");
+            command
+                .args(["-o", "ControlMaster=auto", "-o", "ControlPersist=60"])
+                .arg("-o")
+                .arg(format!("ControlPath={}", socket.display()));
+        }
+        if let Some(port) = self.port {
+            command.arg("-p").arg(port.to_string());
+        }
+        let output = command
+            .arg(&self.destination)
+            .arg("--")
+            .arg(remote)
+            .output()
+            .map_err(|err| format!("Failed to run ssh: {}", err))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+        }
+        Ok(output.stdout)
+    }
+}
+
+impl Backend for Server {
+    fn list(&self, prefix: &str) -> Result<Vec<Object>, String> {
+        let output = self.run(&format!(
+            r"find {} -type f -printf '%s\t%p\0'",
+            quote(prefix)
+        ))?;
+        Ok(output
+            .split(|&byte| byte == 0)
+            .filter(|entry| !entry.is_empty())
+            .filter_map(|entry| {
+                // Paths are kept as text, a lossy one could not be read back.
+                let Ok(entry) = std::str::from_utf8(entry) else {
+                    warn!(
+                        "Skipping {}, its name is not valid UTF-8",
+                        String::from_utf8_lossy(entry)
+                    );
+                    return None;
+                };
+                let (size, key) = entry.split_once('\t')?;
+                Some(Object {
+                    key: key.to_owned(),
+                    size: size.parse().ok()?,
+                })
+            })
+            .collect())
+    }
+
+    fn read(&self, key: &str) -> Result<Vec<u8>, String> {
+        self.run(&format!("cat -- {}", quote(key)))
+    }
+
+    fn delete(&self, key: &str) -> Result<(), String> {
+        self.run(&format!("rm -- {}", quote(key))).map(|_| ())
+    }
+
+    fn url(&self, key: &str) -> String {
+        match self.port {
+            Some(port) => format!("ssh://{}:{}{}", self.destination, port, key),
+            None => format!("ssh://{}{}", self.destination, key),
+        }
+    }
+}
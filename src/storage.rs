@@ -0,0 +1,54 @@
+// Images scanned from remote storage rather than from a local or mounted file system. They keep a
+// URL as their path, e.g. `s3://bucket/photos/IMG_1234.JPG`, which is how they are shown and how
+// the backend to read or delete them is found again.
+use crate::{s3, ssh};
+
+pub struct Object {
+    // Absolute, as used by `read` and `delete`.
+    pub key: String,
+    pub size: u64,
+}
+
+pub trait Backend: Send + Sync {
+    // Every object under `prefix`.
+    fn list(&self, prefix: &str) -> Result<Vec<Object>, String>;
+    fn read(&self, key: &str) -> Result<Vec<u8>, String>;
+    // For good, remote storage has no trash.
+    fn delete(&self, key: &str) -> Result<(), String>;
+    // The path of the image of `key`.
+    fn url(&self, key: &str) -> String;
+}
+
+// A backend and the key, or prefix, of a URL in it.
+pub type Opened = (Box<dyn Backend>, String);
+
+pub fn is_remote(path: &str) -> bool {
+    path.starts_with("s3://") || path.starts_with("ssh://")
+}
+
+// `None` for local paths.
+pub fn open(url: &str) -> Option<Result<Opened, String>> {
+    let opened = if let Some(rest) = url.strip_prefix("s3://") {
+        s3::Bucket::from_env(rest).map(|(bucket, key)| (Box::new(bucket) as Box<dyn Backend>, key))
+    } else {
+        let rest = url.strip_prefix("ssh://")?;
+        ssh::Server::from_url(rest)
+            .map(|(server, path)| (Box::new(server) as Box<dyn Backend>, path))
+    };
+    Some(opened)
+}
+
+// Like `std::fs::read`, for remote images too.
+pub fn read(path: &str) -> std::io::Result<Vec<u8>> {
+    match open(path) {
+        Some(backend) => backend
+            .and_then(|(backend, key)| backend.read(&key))
+            .map_err(std::io::Error::other),
+        None => std::fs::read(path),
+    }
+}
+
+pub fn delete(path: &str) -> Result<(), String> {
+    let (backend, key) = open(path).ok_or("Not a remote path")??;
+    backend.delete(&key)
+}
@@ -0,0 +1,197 @@
+use crate::paths;
+use image::{DynamicImage, GenericImageView, ImageResult};
+use log::{debug, error, info};
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const CACHE_DIR_NAME: &str = "thumbnails";
+
+// Bounds of the longest side of the textures shown in the results list, in physical pixels.
+const MIN_THUMBNAIL_SIZE: u32 = 256;
+const MAX_THUMBNAIL_SIZE: u32 = 4096;
+
+// Thumbnail size for images displayed `pixels` wide on screen, in physical pixels. Sizes go by
+// powers of two so that resizing the window or moving it to another monitor only rarely needs new
+// thumbnails.
+pub fn size_for(pixels: f32) -> u32 {
+    (pixels.max(1.0).ceil() as u32)
+        .next_power_of_two()
+        .clamp(MIN_THUMBNAIL_SIZE, MAX_THUMBNAIL_SIZE)
+}
+
+// Cheapest good enough preview: the cached thumbnail, the embedded EXIF preview when big enough,
+// then a JPEG decoded at a reduced DCT scale, and a full decode for everything else. The result is
+// at most `size` on its longest side. Local files are cached by path, size and modification time,
+// so that a cached thumbnail is found without reading the file. Files on remote storage have to be
+// read anyway and are cached by content. Videos are not read at all, ffmpeg reads them itself.
+pub fn load(path: &str, size: u32) -> ImageResult<DynamicImage> {
+    let metadata = std::fs::metadata(path)
+        .ok()
+        .filter(|metadata| metadata.is_file());
+    let mut buffer = None;
+    let key = match &metadata {
+        Some(metadata) => file_key(path, metadata),
+        None => content_key(buffer.insert(read(path)?)),
+    };
+    let cache_path = cache_dir().map(|dir| dir.join(format!("{:016x}-{}", key, size)));
+    if let Some(cache_path) = &cache_path {
+        if let Ok(cached) = std::fs::read(cache_path) {
+            if let Ok(image) = image::load_from_memory(&cached) {
+                debug!("Thumbnail of {} found in the cache", path);
+                touch(cache_path);
+                return Ok(image);
+            }
+        }
+    }
+    let buffer = match buffer {
+        Some(buffer) => buffer,
+        None if metadata.is_some() && crate::formats::is_video(Path::new(path)) => Vec::new(),
+        None => read(path)?,
+    };
+    let image = shrink(decode_preview(Path::new(path), &buffer, size)?, size);
+    if let Some(cache_path) = &cache_path {
+        if let Err(err) = store(cache_path, &image) {
+            error!("Failed to cache the thumbnail of {}: {}", path, err);
+        }
+    }
+    Ok(image)
+}
+
+fn read(path: &str) -> ImageResult<Vec<u8>> {
+    crate::storage::read(path).map_err(image::ImageError::IoError)
+}
+
+fn decode_preview(path: &Path, buffer: &[u8], size: u32) -> ImageResult<DynamicImage> {
+    if image::guess_format(buffer).ok() != Some(image::ImageFormat::Jpeg) {
+        return crate::formats::load(path, buffer);
+    }
+    // Smaller embedded previews would look blurry.
+    let preview = crate::exif::preview(buffer)
+        .and_then(|preview| image::load_from_memory(preview).ok())
+        .filter(|preview| preview.width().max(preview.height()) >= size / 2);
+    match preview {
+        Some(preview) => Ok(preview),
+        // JPEGs the `image` crate cannot decode at a reduced size get the slower fallbacks.
+        None => scaled_jpeg(buffer, size).or_else(|_| crate::formats::load(path, buffer)),
+    }
+}
+
+fn scaled_jpeg(buffer: &[u8], size: u32) -> ImageResult<DynamicImage> {
+    let mut decoder = image::codecs::jpeg::JpegDecoder::new(Cursor::new(buffer))?;
+    decoder.scale(size as u16, size as u16)?;
+    DynamicImage::from_decoder(decoder)
+}
+
+fn shrink(image: DynamicImage, size: u32) -> DynamicImage {
+    if image.width().max(image.height()) > size {
+        image.thumbnail(size, size)
+    } else {
+        image
+    }
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    paths::cache_dir().map(|dir| dir.join(CACHE_DIR_NAME))
+}
+
+// FNV-1a: stable across runs and Rust versions, unlike `DefaultHasher`. The length is mixed in to
+// make collisions between files of different sizes even less likely.
+pub fn content_key(content: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &byte in content {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash ^ content.len() as u64
+}
+
+fn file_key(path: &str, metadata: &std::fs::Metadata) -> u64 {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map_or(0, |modified| modified.as_nanos());
+    let mut key = path.as_bytes().to_vec();
+    key.extend_from_slice(&metadata.len().to_le_bytes());
+    key.extend_from_slice(&modified.to_le_bytes());
+    content_key(&key)
+}
+
+// The modification time doubles as the last use, for the eviction in `trim_cache`.
+fn touch(path: &Path) {
+    if let Ok(file) = std::fs::File::options().write(true).open(path) {
+        let _ = file.set_modified(SystemTime::now());
+    }
+}
+
+// JPEG is much smaller, PNG keeps the transparency.
+fn store(path: &Path, image: &DynamicImage) -> ImageResult<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(image::ImageError::IoError)?;
+    }
+    let mut content = Vec::new();
+    if image.color().has_alpha() {
+        image.write_to(&mut content, image::ImageOutputFormat::Png)?;
+    } else {
+        DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_to(&mut content, image::ImageOutputFormat::Jpeg(85))?;
+    }
+    std::fs::write(path, content).map_err(image::ImageError::IoError)
+}
+
+fn cache_files() -> Vec<(PathBuf, u64, SystemTime)> {
+    let Some(entries) = cache_dir().and_then(|dir| std::fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((
+                entry.path(),
+                metadata.len(),
+                metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+            ))
+        })
+        .collect()
+}
+
+pub fn cache_size() -> u64 {
+    cache_files().iter().map(|(_, size, _)| size).sum()
+}
+
+// Removes the least recently used thumbnails until the cache fits in `max_size` bytes.
+pub fn trim_cache(max_size: u64) {
+    let mut files = cache_files();
+    let mut size: u64 = files.iter().map(|(_, size, _)| size).sum();
+    if size <= max_size {
+        return;
+    }
+    files.sort_by_key(|(_, _, used)| *used);
+    let mut removed = 0;
+    for (path, file_size, _) in files {
+        if size <= max_size {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            size -= file_size;
+            removed += 1;
+        }
+    }
+    info!(
+        "Removed {} thumbnails from the cache, now {}",
+        removed,
+        ubyte::ByteUnit::Byte(size)
+    );
+}
+
+pub fn clear_cache() -> Result<(), String> {
+    let Some(dir) = cache_dir() else {
+        return Ok(());
+    };
+    match std::fs::remove_dir_all(&dir) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.to_string()),
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,184 @@
+// Disk usage of the scanned directory, drawn as nested rectangles sized by bytes with the share of
+// redundant copies in red, to see at a glance which subdirectories are worth cleaning up first.
+use eframe::egui;
+use egui::{Color32, Pos2, Rect, Stroke};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Levels of subdirectories drawn below the one shown.
+const DEPTH: usize = 3;
+
+// Smaller rectangles are not split further nor labelled.
+const MIN_SIDE: f32 = 12.0;
+const MIN_LABEL_WIDTH: f32 = 60.0;
+
+#[derive(Default)]
+pub struct Node {
+    pub size: u64,
+    // Bytes of the images which are a copy of another one.
+    pub redundant: u64,
+    pub children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn add(&mut self, dirs: &[String], size: u64, redundant: bool) {
+        self.size += size;
+        if redundant {
+            self.redundant += size;
+        }
+        if let Some((dir, rest)) = dirs.split_first() {
+            self.children
+                .entry(dir.clone())
+                .or_default()
+                .add(rest, size, redundant);
+        }
+    }
+
+    pub fn get(&self, path: &[String]) -> Option<&Node> {
+        match path.split_first() {
+            Some((dir, rest)) => self.children.get(dir)?.get(rest),
+            None => Some(self),
+        }
+    }
+}
+
+// Files are (path, whether it is a redundant copy). Files outside of `root`, e.g. from an index,
+// are left out.
+pub fn build(root: &Path, files: &[(String, bool)]) -> Node {
+    let mut tree = Node::default();
+    for (path, redundant) in files {
+        let Ok(relative) = Path::new(path).strip_prefix(root) else {
+            continue;
+        };
+        let size = std::fs::metadata(path).map_or(0, |metadata| metadata.len());
+        let dirs: Vec<String> = relative
+            .parent()
+            .into_iter()
+            .flat_map(|parent| parent.iter())
+            .map(|dir| dir.to_string_lossy().to_string())
+            .collect();
+        tree.add(&dirs, size, *redundant);
+    }
+    tree
+}
+
+fn fill(level: usize) -> Color32 {
+    let shade = 60 + 30 * level.min(5) as u8;
+    Color32::from_rgb(shade / 2, shade / 2 + 20, shade)
+}
+
+// Children take slices along the longer side, largest first. What is left is the files directly
+// in the directory.
+fn paint(
+    painter: &egui::Painter,
+    node: &Node,
+    path: Vec<String>,
+    rect: Rect,
+    level: usize,
+    areas: &mut Vec<(Rect, Vec<String>)>,
+) {
+    painter.rect(rect, 0.0, fill(level), Stroke::new(1.0, Color32::BLACK));
+    if node.size > 0 && node.redundant > 0 {
+        let height = rect.height() * (node.redundant as f32 / node.size as f32);
+        let redundant =
+            Rect::from_min_max(Pos2::new(rect.left(), rect.bottom() - height), rect.max);
+        painter.rect_filled(
+            redundant,
+            0.0,
+            Color32::from_rgba_unmultiplied(220, 40, 40, 90),
+        );
+    }
+    if let Some(name) = path.last().filter(|_| rect.width() >= MIN_LABEL_WIDTH) {
+        painter.text(
+            rect.left_top() + egui::vec2(3.0, 2.0),
+            egui::Align2::LEFT_TOP,
+            name,
+            egui::FontId::proportional(11.0),
+            Color32::WHITE,
+        );
+    }
+    areas.push((rect, path.clone()));
+    if level >= DEPTH || node.size == 0 {
+        return;
+    }
+
+    // Room for the label.
+    let inner = Rect::from_min_max(
+        rect.min + egui::vec2(2.0, 15.0),
+        rect.max - egui::vec2(2.0, 2.0),
+    );
+    if inner.width() < MIN_SIDE || inner.height() < MIN_SIDE {
+        return;
+    }
+    let mut children: Vec<(&String, &Node)> = node.children.iter().collect();
+    children.sort_by_key(|(_, child)| std::cmp::Reverse(child.size));
+    let horizontal = inner.width() >= inner.height();
+    let length = if horizontal {
+        inner.width()
+    } else {
+        inner.height()
+    };
+    let mut offset = 0.0;
+    for (name, child) in children {
+        let extent = length * (child.size as f32 / node.size as f32);
+        if extent < MIN_SIDE {
+            break;
+        }
+        let child_rect = if horizontal {
+            Rect::from_min_size(
+                inner.min + egui::vec2(offset, 0.0),
+                egui::vec2(extent, inner.height()),
+            )
+        } else {
+            Rect::from_min_size(
+                inner.min + egui::vec2(0.0, offset),
+                egui::vec2(inner.width(), extent),
+            )
+        };
+        let mut child_path = path.clone();
+        child_path.push(name.clone());
+        paint(painter, child, child_path, child_rect, level + 1, areas);
+        offset += extent;
+    }
+}
+
+// Draws `node` in the available space, returns the path of the clicked subdirectory, relative to
+// `node`.
+pub fn show(ui: &mut egui::Ui, node: &Node) -> Option<Vec<String>> {
+    let (response, painter) = ui.allocate_painter(ui.available_size(), egui::Sense::click());
+    let mut areas = Vec::new();
+    paint(&painter, node, Vec::new(), response.rect, 0, &mut areas);
+    response.widget_info(|| {
+        egui::WidgetInfo::labeled(
+            egui::WidgetType::Other,
+            format!(
+                "Disk usage map: {} ({} redundant) in {} subdirectories",
+                ubyte::ByteUnit::Byte(node.size),
+                ubyte::ByteUnit::Byte(node.redundant),
+                node.children.len()
+            ),
+        )
+    });
+
+    // The innermost area under the pointer was drawn last.
+    let pointed = response
+        .hover_pos()
+        .and_then(|pos| areas.iter().rev().find(|(rect, _)| rect.contains(pos)))?;
+    let pointed_node = node.get(&pointed.1)?;
+    let clicked = response.clicked();
+    response.on_hover_text(format!(
+        "{}\n{} ({} redundant)",
+        if pointed.1.is_empty() {
+            ".".to_owned()
+        } else {
+            pointed.1.join("/")
+        },
+        ubyte::ByteUnit::Byte(pointed_node.size),
+        ubyte::ByteUnit::Byte(pointed_node.redundant)
+    ));
+    if clicked && !pointed.1.is_empty() {
+        Some(pointed.1.clone())
+    } else {
+        None
+    }
+}
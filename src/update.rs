@@ -0,0 +1,51 @@
+use log::{debug, info};
+use serde::Deserialize;
+
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/gaultier/img-dedup-rs/releases/latest";
+
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub html_url: String,
+}
+
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map_while(|part| part.parse().ok())
+        .collect()
+}
+
+fn fetch_latest_release() -> Result<Release, String> {
+    ureq::get(LATEST_RELEASE_URL)
+        .set(
+            "User-Agent",
+            concat!("img-dedup/", env!("CARGO_PKG_VERSION")),
+        )
+        .timeout(std::time::Duration::from_secs(10))
+        .call()
+        .map_err(|err| err.to_string())?
+        .into_json()
+        .map_err(|err| err.to_string())
+}
+
+// Returns the latest release if it is newer than the running version. Failures are only logged:
+// being offline should not bother the user.
+pub fn check() -> Option<Release> {
+    let release = match fetch_latest_release() {
+        Ok(release) => release,
+        Err(err) => {
+            debug!("Update check failed: {}", err);
+            return None;
+        }
+    };
+
+    if parse_version(&release.tag_name) > parse_version(env!("CARGO_PKG_VERSION")) {
+        info!("A newer version is available: {}", release.tag_name);
+        Some(release)
+    } else {
+        None
+    }
+}
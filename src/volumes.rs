@@ -0,0 +1,30 @@
+// Duplicates spread over several drives, e.g. a NAS and a laptop. Volumes are given by the path
+// they are mounted at, the copy on the volume listed first is the one to keep, and the copy on the
+// archive volume is never the last one to go without a warning.
+use std::path::Path;
+
+// Index in `volumes` of the one holding `path`, the longest matching mount point wins so that a
+// volume mounted inside another one is told apart.
+fn volume_of(volumes: &[String], path: &str) -> Option<usize> {
+    volumes
+        .iter()
+        .enumerate()
+        .filter(|(_, volume)| !volume.is_empty() && Path::new(path).starts_with(volume))
+        .max_by_key(|(_, volume)| Path::new(volume).components().count())
+        .map(|(i, _)| i)
+}
+
+// Whether `a` (true) or `b` (false) is on the volume of higher priority. Paths on no listed volume
+// come last.
+pub fn keep_first(volumes: &[String], a: &str, b: &str) -> Option<bool> {
+    let rank = |path| volume_of(volumes, path).unwrap_or(volumes.len());
+    match rank(a).cmp(&rank(b)) {
+        std::cmp::Ordering::Less => Some(true),
+        std::cmp::Ordering::Greater => Some(false),
+        std::cmp::Ordering::Equal => None,
+    }
+}
+
+pub fn is_on(archive: &str, path: &str) -> bool {
+    !archive.is_empty() && Path::new(path).starts_with(archive)
+}